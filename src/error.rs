@@ -0,0 +1,182 @@
+use std::fmt;
+use std::io;
+
+use crate::MemMode;
+
+// A logical RAM that no enabled physical type can implement
+#[derive(Clone, Debug)]
+pub struct MappingError {
+    pub circuit_id: i32,
+    pub ram_id: i32,
+    pub mode: MemMode,
+    pub depth: i32,
+    pub width: i32,
+    // Some(s) when some type had shapes rejected only for needing s > max_series blocks in
+    // series (the smallest such s)
+    pub min_series: Option<i32>,
+    // Some(p) likewise for shapes needing p > max_parallel blocks side by side
+    pub min_parallel: Option<i32>,
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "No legal mapping for logical RAM {} ({} {}x{}) in circuit {} under current memory config",
+            self.ram_id,
+            self.mode.as_str(),
+            self.depth,
+            self.width,
+            self.circuit_id
+        )?;
+        if let Some(s) = self.min_series {
+            write!(
+                f,
+                " (its shallowest cascade needs {} blocks in series, over max_series)",
+                s
+            )?;
+        }
+        if let Some(p) = self.min_parallel {
+            write!(
+                f,
+                " (its narrowest row needs {} blocks in parallel, over max_parallel)",
+                p
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// An input line read_data skipped, with its 1-based line number
+#[derive(Clone, Debug)]
+pub struct ParseWarning {
+    pub path: String,
+    pub line: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} line {}: {}", self.path, self.line, self.reason)
+    }
+}
+
+// Errors that end a run. Each category has its own process exit code:
+// 1 config error, 2 input/IO error, 3 no legal mapping (or an invalid shared block),
+// 4 device too small, 5 nondeterministic result under --check-determinism,
+// 6 geometric mean over --max-area-budget.
+#[derive(Debug)]
+pub enum RapidMapError {
+    Config(String),
+    Io(io::Error),
+    // an input file that can't be opened or read
+    File {
+        path: String,
+        source: io::Error,
+    },
+    NoLegalMapping(MappingError),
+    InvalidSharing {
+        circuit_id: i32,
+        group_id: i32,
+        stacked_depth: i32,
+        phys_depth: i32,
+    },
+    Unmappable {
+        reason: String,
+        // (circuit, ram)
+        rams: Vec<(i32, i32)>,
+    },
+    DoesNotFit(Vec<String>),
+    Nondeterministic {
+        first: u64,
+        second: u64,
+    },
+    OverAreaBudget {
+        geomean: f64,
+        budget: f64,
+    },
+}
+
+impl RapidMapError {
+    pub fn file(path: &str, source: io::Error) -> Self {
+        RapidMapError::File {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RapidMapError::Config(_) => 1,
+            RapidMapError::Io(_) | RapidMapError::File { .. } => 2,
+            RapidMapError::NoLegalMapping(_)
+            | RapidMapError::InvalidSharing { .. }
+            | RapidMapError::Unmappable { .. } => 3,
+            RapidMapError::DoesNotFit(_) => 4,
+            RapidMapError::Nondeterministic { .. } => 5,
+            RapidMapError::OverAreaBudget { .. } => 6,
+        }
+    }
+}
+
+impl fmt::Display for RapidMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RapidMapError::Config(msg) => write!(f, "{}", msg),
+            RapidMapError::Io(e) => write!(f, "{}", e),
+            RapidMapError::File { path, source } => write!(f, "cannot read {}: {}", path, source),
+            RapidMapError::NoLegalMapping(e) => write!(f, "{}", e),
+            RapidMapError::InvalidSharing {
+                circuit_id,
+                group_id,
+                stacked_depth,
+                phys_depth,
+            } => write!(
+                f,
+                "Shared group {} in circuit {} needs depth {} but its physical block holds {}",
+                group_id, circuit_id, stacked_depth, phys_depth
+            ),
+            RapidMapError::Unmappable { reason, rams } => {
+                write!(f, "{} logical RAMs {}:", rams.len(), reason)?;
+                for (circuit_id, ram_id) in rams {
+                    write!(f, "\n  circuit {} ram {}", circuit_id, ram_id)?;
+                }
+                Ok(())
+            }
+            RapidMapError::DoesNotFit(shortfalls) => {
+                write!(f, "design does not fit the target device:")?;
+                for s in shortfalls {
+                    write!(f, "\n  {}", s)?;
+                }
+                Ok(())
+            }
+            RapidMapError::Nondeterministic { first, second } => write!(
+                f,
+                "mapping is not deterministic: run hashes {:016x} and {:016x} differ",
+                first, second
+            ),
+            RapidMapError::OverAreaBudget { geomean, budget } => write!(
+                f,
+                "geometric mean FPGA area {:.5e} exceeds the budget {:.5e} by {:.5e} ({:+.2}%)",
+                geomean,
+                budget,
+                geomean - budget,
+                100.0 * (geomean - budget) / budget
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RapidMapError {}
+
+impl From<MappingError> for RapidMapError {
+    fn from(e: MappingError) -> Self {
+        RapidMapError::NoLegalMapping(e)
+    }
+}
+
+impl From<io::Error> for RapidMapError {
+    fn from(e: io::Error) -> Self {
+        RapidMapError::Io(e)
+    }
+}
@@ -0,0 +1,4804 @@
+#![allow(dead_code)]
+#![allow(clippy::too_many_arguments)]
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::f64;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+pub mod error;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod utils;
+
+pub const AVG_LB_AREA: f64 = (35000.0 + 40000.0) / 2.0;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::error::{MappingError, ParseWarning, RapidMapError};
+use crate::utils::{BlockBudgets, GeomeanParams, compute_geometric_area_in_memory};
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MemMode {
+    Rom,
+    SinglePort,
+    SimpleDualPort,
+    TrueDualPort,
+}
+
+impl MemMode {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "ROM" => Some(MemMode::Rom),
+            "SinglePort" => Some(MemMode::SinglePort),
+            "SimpleDualPort" => Some(MemMode::SimpleDualPort),
+            "TrueDualPort" => Some(MemMode::TrueDualPort),
+            _ => None,
+        }
+    }
+    // integer codes used by some upstream tools: 0=ROM, 1=SinglePort, 2=SimpleDualPort, 3=TrueDualPort
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(MemMode::Rom),
+            1 => Some(MemMode::SinglePort),
+            2 => Some(MemMode::SimpleDualPort),
+            3 => Some(MemMode::TrueDualPort),
+            _ => None,
+        }
+    }
+    fn code(&self) -> i32 {
+        match self {
+            MemMode::Rom => 0,
+            MemMode::SinglePort => 1,
+            MemMode::SimpleDualPort => 2,
+            MemMode::TrueDualPort => 3,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MemMode::Rom => "ROM",
+            MemMode::SinglePort => "SinglePort",
+            MemMode::SimpleDualPort => "SimpleDualPort",
+            MemMode::TrueDualPort => "TrueDualPort",
+        }
+    }
+}
+
+// serialized as its as_str name, the same as in ram_mapped.txt
+impl Serialize for MemMode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PhysType {
+    Lutram,
+    Ram8K,
+    Ram128K,
+    // ArchConfig::extra_rams[i]; named ram3, ram4, ... after ram1/ram2, type id 4 + i
+    Extra(u8),
+}
+impl PhysType {
+    // the built-in types; extra BRAM tiers come from PhysConfigs::types
+    pub const ALL: [PhysType; 3] = [PhysType::Lutram, PhysType::Ram8K, PhysType::Ram128K];
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        let s = s.to_ascii_lowercase();
+        match s.as_str() {
+            "lutram" => Some(PhysType::Lutram),
+            "m8k" | "ram1" => Some(PhysType::Ram8K),
+            "m128k" | "ram2" => Some(PhysType::Ram128K),
+            _ => {
+                let n: u8 = s.strip_prefix("ram")?.parse().ok()?;
+                Some(PhysType::Extra(n.checked_sub(3)?))
+            }
+        }
+    }
+    pub fn type_id(&self) -> i32 {
+        match self {
+            PhysType::Lutram => 1,
+            PhysType::Ram8K => 2,
+            PhysType::Ram128K => 3,
+            PhysType::Extra(i) => 4 + *i as i32,
+        }
+    }
+    pub fn name(&self) -> Cow<'static, str> {
+        match self {
+            PhysType::Lutram => "lutram".into(),
+            PhysType::Ram8K => "m8k".into(),
+            PhysType::Ram128K => "m128k".into(),
+            PhysType::Extra(i) => format!("ram{}", 3 + *i as i32).into(),
+        }
+    }
+    fn from_type_id(id: i32) -> Option<Self> {
+        match id {
+            1 => Some(PhysType::Lutram),
+            2 => Some(PhysType::Ram8K),
+            3 => Some(PhysType::Ram128K),
+            _ => u8::try_from(id - 4).ok().map(PhysType::Extra),
+        }
+    }
+}
+
+// serialized as its type_id, the same as the Type column of ram_mapped.txt
+impl Serialize for PhysType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(self.type_id())
+    }
+}
+
+/// One physical memory type as the mapper sees it. The widest port depends on the logical
+/// mode: `max_width_tdp` for TrueDualPort, `max_width_sdp` for SimpleDualPort, whose read
+/// and write ports can be paired into a wider one, and `max_width_non_tdp` otherwise.
+///
+/// ```
+/// use rapid_map::{
+///     ArchConfig, Circuit, MapOptions, MemMode, Memory, PhysConfigs, PhysType, assign_ram,
+/// };
+///
+/// // 64 x 128: two 32-wide M8K blocks side by side, or one block in a 64-wide SDP mode
+/// let circuits = vec![Circuit {
+///     id: 0,
+///     logic_blocks: 500,
+///     memories: vec![Memory { ram_id: 0, mode: MemMode::SimpleDualPort, depth: 128, width: 64 }],
+/// }];
+/// let mut configs = ArchConfig::default().phys_configs();
+/// configs.disable(PhysType::Lutram);
+/// configs.disable(PhysType::Ram128K);
+/// let blocks = |configs: &PhysConfigs| {
+///     let result = assign_ram(&circuits, configs, &MapOptions::default()).unwrap();
+///     result.m8k_blocks
+/// };
+/// assert_eq!(blocks(&configs), 2);
+///
+/// configs.get_mut(PhysType::Ram8K).unwrap().max_width_sdp = 64;
+/// assert_eq!(blocks(&configs), 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct PhysConfig {
+    pub phys_type: PhysType,
+    pub bits: i32,
+    pub max_width_non_tdp: i32,
+    // SimpleDualPort only; the templates and -p types start it at max_width_non_tdp
+    pub max_width_sdp: i32,
+    pub max_width_tdp: i32,
+    // explicit (width, depth) shapes, e.g. parity-adjusted depths or the LUTRAM
+    // configurations. Empty means depth = bits / width
+    pub shapes: Cow<'static, [(i32, i32)]>,
+    // widths to try in place of the powers of two, e.g. parity widths 9, 18, 36. Empty means 1, 2, 4, ...
+    pub width_candidates: Vec<i32>,
+    // logical modes this type must never implement
+    pub forbidden_modes: Vec<MemMode>,
+    // also try max_width itself when it isn't a power of two (depth rounded down)
+    pub include_max_width: bool,
+    // decoder LUTs for series s at index s - 1; longer series use decoder_luts' formula
+    pub decoder_table: Vec<i32>,
+    // longest series cascade a mapping may use; deeper shapes are not candidates
+    pub max_series: i32,
+    // most blocks a mapping may place side by side; wider rows are not candidates.
+    // i32::MAX means no limit
+    pub max_parallel: i32,
+    // Some(t): series above t get a register stage per output mux level (see mux_luts)
+    pub pipeline_threshold: Option<i32>,
+    pub cost: CostModel,
+}
+
+// Constants of mapping_cost. for_type gives the tuned defaults; the optional terms default to zero
+#[derive(Clone, Copy, Debug)]
+pub struct CostModel {
+    // penalty_factor = penalty_base + penalty_strength * (10 - utilization)
+    pub penalty_base: f64,
+    pub penalty_strength: f64,
+    // area of one logic block
+    pub lb_area: f64,
+    pub bram_area: BramAreaModel,
+    // cost per unused depth row in the last series block (phys_depth * series - logical_depth)
+    pub depth_waste_weight: f64,
+    // multiplies the whole cost of a ROM mapping; below 1 draws ROMs to this type
+    pub rom_discount: f64,
+}
+
+impl CostModel {
+    const fn for_type(phys_type: PhysType) -> CostModel {
+        CostModel {
+            penalty_base: 10.0,
+            penalty_strength: match phys_type {
+                PhysType::Lutram => 1.6,
+                PhysType::Ram8K => 2.2,
+                PhysType::Ram128K | PhysType::Extra(_) => 5.0,
+            },
+            lb_area: AVG_LB_AREA,
+            bram_area: BramAreaModel::DEFAULT,
+            depth_waste_weight: 0.0,
+            rom_discount: 1.0,
+        }
+    }
+}
+
+// Area of one BRAM block: base + per_bit * bits + per_sqrt_bit * sqrt(bits) + per_width * 2 * max_width
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct BramAreaModel {
+    pub base: f64,
+    pub per_bit: f64,
+    pub per_sqrt_bit: f64,
+    pub per_width: f64,
+}
+
+impl BramAreaModel {
+    pub const DEFAULT: BramAreaModel = BramAreaModel {
+        base: 9000.0,
+        per_bit: 5.0,
+        per_sqrt_bit: 90.0,
+        per_width: 600.0,
+    };
+
+    pub fn area(&self, bits: i32, max_width: i32) -> f64 {
+        let bits_f = bits as f64;
+        self.base
+            + self.per_bit * bits_f
+            + self.per_sqrt_bit * bits_f.sqrt()
+            + self.per_width * 2.0 * (max_width as f64)
+    }
+
+    fn is_valid(&self) -> bool {
+        [self.base, self.per_bit, self.per_sqrt_bit, self.per_width]
+            .iter()
+            .all(|c| c.is_finite() && *c >= 0.0)
+    }
+}
+
+impl Default for BramAreaModel {
+    fn default() -> Self {
+        BramAreaModel::DEFAULT
+    }
+}
+
+// Default templates
+pub const PHYS_LUTRAM: PhysConfig = PhysConfig {
+    phys_type: PhysType::Lutram,
+    bits: 64 * 10,
+    max_width_non_tdp: 20,
+    max_width_sdp: 20,
+    max_width_tdp: 0,
+    shapes: Cow::Borrowed(&[(10, 64), (20, 32)]),
+    width_candidates: Vec::new(),
+    forbidden_modes: Vec::new(),
+    include_max_width: false,
+    decoder_table: Vec::new(),
+    max_series: 16,
+    max_parallel: i32::MAX,
+    pipeline_threshold: None,
+    cost: CostModel::for_type(PhysType::Lutram),
+};
+
+pub const PHYS_RAM1: PhysConfig = PhysConfig {
+    phys_type: PhysType::Ram8K,
+    bits: 8192,
+    max_width_non_tdp: 32,
+    max_width_sdp: 32,
+    max_width_tdp: 16,
+    shapes: Cow::Borrowed(&[]),
+    width_candidates: Vec::new(),
+    forbidden_modes: Vec::new(),
+    include_max_width: false,
+    decoder_table: Vec::new(),
+    max_series: 16,
+    max_parallel: i32::MAX,
+    pipeline_threshold: None,
+    cost: CostModel::for_type(PhysType::Ram8K),
+};
+
+pub const PHYS_RAM2: PhysConfig = PhysConfig {
+    phys_type: PhysType::Ram128K,
+    bits: 128 * 1024,
+    max_width_non_tdp: 128,
+    max_width_sdp: 128,
+    max_width_tdp: 64,
+    shapes: Cow::Borrowed(&[]),
+    width_candidates: Vec::new(),
+    forbidden_modes: Vec::new(),
+    include_max_width: false,
+    decoder_table: Vec::new(),
+    max_series: 16,
+    max_parallel: i32::MAX,
+    pipeline_threshold: None,
+    cost: CostModel::for_type(PhysType::Ram128K),
+};
+
+// BRAM config built from the -p parameters; TDP gets half the non-TDP width
+fn bram_config(phys_type: PhysType, bits: i32, max_width: i32) -> PhysConfig {
+    PhysConfig {
+        phys_type,
+        bits,
+        max_width_non_tdp: max_width,
+        max_width_sdp: max_width,
+        max_width_tdp: max_width / 2,
+        shapes: Cow::Borrowed(&[]),
+        width_candidates: Vec::new(),
+        forbidden_modes: Vec::new(),
+        include_max_width: false,
+        decoder_table: Vec::new(),
+        max_series: 16,
+        max_parallel: i32::MAX,
+        pipeline_threshold: None,
+        cost: CostModel::for_type(phys_type),
+    }
+}
+
+// Physical memory types enabled for a run. A disabled type is None.
+#[derive(Clone, Debug)]
+pub struct PhysConfigs {
+    pub lutram: Option<PhysConfig>,
+    pub m8k: Option<PhysConfig>,
+    pub m128k: Option<PhysConfig>,
+    // extra BRAM tiers, indexed like PhysType::Extra
+    pub extra: Vec<Option<PhysConfig>>,
+}
+
+impl PhysConfigs {
+    pub fn get(&self, phys_type: PhysType) -> Option<&PhysConfig> {
+        match phys_type {
+            PhysType::Lutram => self.lutram.as_ref(),
+            PhysType::Ram8K => self.m8k.as_ref(),
+            PhysType::Ram128K => self.m128k.as_ref(),
+            PhysType::Extra(i) => self.extra.get(i as usize)?.as_ref(),
+        }
+    }
+
+    pub fn get_mut(&mut self, phys_type: PhysType) -> Option<&mut PhysConfig> {
+        match phys_type {
+            PhysType::Lutram => self.lutram.as_mut(),
+            PhysType::Ram8K => self.m8k.as_mut(),
+            PhysType::Ram128K => self.m128k.as_mut(),
+            PhysType::Extra(i) => self.extra.get_mut(i as usize)?.as_mut(),
+        }
+    }
+
+    pub fn disable(&mut self, phys_type: PhysType) {
+        match phys_type {
+            PhysType::Lutram => self.lutram = None,
+            PhysType::Ram8K => self.m8k = None,
+            PhysType::Ram128K => self.m128k = None,
+            PhysType::Extra(i) => {
+                if let Some(cfg) = self.extra.get_mut(i as usize) {
+                    *cfg = None;
+                }
+            }
+        }
+    }
+
+    // every type that may be configured, built-in ones first, enabled or not
+    pub fn all_types(&self) -> Vec<PhysType> {
+        let extra = (0..self.extra.len()).map(|i| PhysType::Extra(i as u8));
+        PhysType::ALL.into_iter().chain(extra).collect()
+    }
+
+    // the enabled types, built-in ones first
+    pub fn types(&self) -> Vec<PhysType> {
+        let mut types = self.all_types();
+        types.retain(|&t| self.get(t).is_some());
+        types
+    }
+
+    // the enabled BRAM configs (every type but LUTRAM)
+    fn brams(&self) -> impl Iterator<Item = &PhysConfig> {
+        [&self.m8k, &self.m128k]
+            .into_iter()
+            .chain(&self.extra)
+            .flatten()
+    }
+}
+
+// Most extra BRAM tiers an architecture may add: their type ids 4..=255 must fit the one-byte
+// type field of the binary mapping format
+pub const MAX_EXTRA_RAMS: usize = 252;
+
+/// A BRAM tier beyond ram1 and ram2, such as a 512K block. The first one is type `ram3`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct ExtraRam {
+    pub bits: i32,
+    // one block every lbs_per logic blocks
+    pub lbs_per: i32,
+    pub max_width: i32,
+    pub area: BramAreaModel,
+}
+
+/// Architecture parameters, the values given to the binary's `-p` flag, plus any extra BRAM
+/// tiers from an `--arch` file.
+#[derive(Clone, Debug, Serialize)]
+pub struct ArchConfig {
+    pub has_lutram: bool,
+    // fraction of logic blocks that can act as LUTRAM
+    pub lutram_fraction: f64,
+    // LUTRAM cost multiplier for ROMs, which need no write port; 1 treats them like RAMs
+    pub rom_discount: f64,
+    // false for devices without a true dual-port mode: no type gets TDP ports, so TDP RAMs
+    // can't be mapped and nothing is shared (a shared block runs in TDP mode)
+    pub allow_tdp: bool,
+    pub has_ram1: bool,
+    pub ram1_bits: i32,
+    // one RAM1 site every lbs_per_ram1 logic blocks
+    pub lbs_per_ram1: i32,
+    pub max_width_ram1: i32,
+    // block area coefficients, for the mapping cost and the area reports
+    pub ram1_area: BramAreaModel,
+    pub has_ram2: bool,
+    pub ram2_bits: i32,
+    pub lbs_per_ram2: i32,
+    pub max_width_ram2: i32,
+    pub ram2_area: BramAreaModel,
+    pub extra_rams: Vec<ExtraRam>,
+    // register the output mux tree of cascades deeper than pipeline_threshold blocks
+    pub pipeline_mux: bool,
+    pub pipeline_threshold: i32,
+}
+
+impl Default for ArchConfig {
+    fn default() -> Self {
+        ArchConfig {
+            has_lutram: true,
+            lutram_fraction: 0.5,
+            rom_discount: 1.0,
+            allow_tdp: true,
+            has_ram1: true,
+            ram1_bits: 8192,
+            lbs_per_ram1: 10,
+            max_width_ram1: 32,
+            ram1_area: BramAreaModel::DEFAULT,
+            has_ram2: true,
+            ram2_bits: 128 * 1024,
+            lbs_per_ram2: 300,
+            max_width_ram2: 128,
+            ram2_area: BramAreaModel::DEFAULT,
+            extra_rams: Vec::new(),
+            pipeline_mux: false,
+            pipeline_threshold: 4,
+        }
+    }
+}
+
+impl ArchConfig {
+    // rejects values no run can use, naming the offending key
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.lutram_fraction) {
+            return Err(format!(
+                "lutram_fraction = {} is outside [0, 1]",
+                self.lutram_fraction
+            ));
+        }
+        if !(self.rom_discount.is_finite() && self.rom_discount > 0.0) {
+            return Err(format!(
+                "rom_discount = {} must be a positive number",
+                self.rom_discount
+            ));
+        }
+        for (i, ram) in self.extra_rams.iter().enumerate() {
+            if ram.bits <= 0 || ram.lbs_per <= 0 || ram.max_width <= 0 {
+                return Err(format!(
+                    "extra_ram {} (ram{}) needs positive bits, lbs_per and max_width",
+                    i + 1,
+                    i + 3
+                ));
+            }
+        }
+        let areas = [("ram1", &self.ram1_area), ("ram2", &self.ram2_area)]
+            .into_iter()
+            .map(|(name, area)| (name.to_string(), area))
+            .chain(
+                (self.extra_rams.iter().enumerate())
+                    .map(|(i, ram)| (format!("extra_ram {}", i + 1), &ram.area)),
+            );
+        for (name, area) in areas {
+            if !area.is_valid() {
+                return Err(format!(
+                    "{} area coefficients must be finite and non-negative",
+                    name
+                ));
+            }
+        }
+        if self.pipeline_mux && self.pipeline_threshold < 1 {
+            return Err(format!(
+                "pipeline_threshold = {} must be at least 1",
+                self.pipeline_threshold
+            ));
+        }
+        if self.extra_rams.len() > MAX_EXTRA_RAMS {
+            return Err(format!(
+                "{} extra_ram tiers given, at most {}",
+                self.extra_rams.len(),
+                MAX_EXTRA_RAMS
+            ));
+        }
+        if !self.has_lutram && !self.has_ram1 && !self.has_ram2 && self.extra_rams.is_empty() {
+            return Err(
+                "At least one memory type (LUTRAM, M8K, or M128K) must be enabled \
+                        (has_lutram, ram1.enabled and ram2.enabled are all false)"
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+
+    // the enabled types with default shapes and cost model
+    pub fn phys_configs(&self) -> PhysConfigs {
+        let mut configs = PhysConfigs {
+            lutram: self.has_lutram.then_some(PHYS_LUTRAM),
+            m8k: self
+                .has_ram1
+                .then(|| bram_config(PhysType::Ram8K, self.ram1_bits, self.max_width_ram1)),
+            m128k: self
+                .has_ram2
+                .then(|| bram_config(PhysType::Ram128K, self.ram2_bits, self.max_width_ram2)),
+            extra: self
+                .extra_rams
+                .iter()
+                .enumerate()
+                .map(|(i, ram)| {
+                    Some(bram_config(
+                        PhysType::Extra(i as u8),
+                        ram.bits,
+                        ram.max_width,
+                    ))
+                })
+                .collect(),
+        };
+        let areas = [
+            (PhysType::Ram8K, self.ram1_area),
+            (PhysType::Ram128K, self.ram2_area),
+        ]
+        .into_iter()
+        .chain(
+            (self.extra_rams.iter().enumerate())
+                .map(|(i, ram)| (PhysType::Extra(i as u8), ram.area)),
+        );
+        for (t, area) in areas {
+            if let Some(cfg) = configs.get_mut(t) {
+                cfg.cost.bram_area = area;
+            }
+        }
+        if let Some(cfg) = configs.get_mut(PhysType::Lutram) {
+            cfg.cost.rom_discount = self.rom_discount;
+        }
+        if !self.allow_tdp {
+            for t in configs.all_types() {
+                if let Some(cfg) = configs.get_mut(t) {
+                    cfg.max_width_tdp = 0;
+                }
+            }
+        }
+        if self.pipeline_mux {
+            for t in configs.all_types() {
+                if let Some(cfg) = configs.get_mut(t) {
+                    cfg.pipeline_threshold = Some(self.pipeline_threshold);
+                }
+            }
+        }
+        configs
+    }
+}
+
+// Mapping knobs that are not part of the architecture
+#[derive(Clone, Debug)]
+pub struct MapOptions {
+    // map only the first N memories of each circuit, in read order
+    pub memory_limit: Option<usize>,
+    // upper bound on share_type passes per BRAM type; stops early once a pass merges nothing.
+    // 0 leaves every RAM in its own blocks
+    pub sharing_iterations: usize,
+    // (circuit, ram) -> physical shape to use instead of searching
+    pub hints: HashMap<(i32, i32), MappingHint>,
+    // order types are tried in; on equal cost the earlier type wins
+    pub type_priority: Vec<PhysType>,
+    // once the running extra-LUT total exceeds this, prefer shapes with fewer extra LUTs
+    pub lut_budget: Option<i32>,
+    // print each winning mapping to stderr as soon as it is chosen
+    pub stream_decisions: bool,
+    // map the memories of each circuit on the rayon pool; ignored under a LUT budget,
+    // whose bias depends on the running total of earlier memories
+    pub parallel_memories: bool,
+    // which memories the sharing pass may pair up
+    pub sharing: SharingConfig,
+    // abort on the first unmappable RAM instead of skipping its circuit
+    pub strict: bool,
+    // also try splitting each RAM's width across two types (see best_width_split)
+    pub allow_split: bool,
+    // Some(seed): near-equal candidates in the search are picked at random instead of by
+    // candidate order, reproducibly for a given seed
+    pub seed: Option<u64>,
+    // also consider building each RAM from LUTs alone (see lut_memory_mapping)
+    pub allow_lut_memory: bool,
+    // report circuits mapped and an ETA on stderr while assign_ram runs
+    pub progress: bool,
+}
+
+// Constraints on which logical RAMs may share a physical BRAM
+#[derive(Clone, Debug)]
+pub struct SharingConfig {
+    // logical modes that may share; each sharer gets one port of the TDP block
+    pub modes: Vec<MemMode>,
+    // Some(g): each sharer must occupy a whole number of 1/g blocks (e.g. 4 for quarters).
+    // g <= 0 is no constraint, like None
+    pub granularity: Option<i32>,
+    // most RAMs in one block. 2 pairs RAMs that fill the block exactly; above 2, first-fit
+    // packs RAMs whose bits and stacked depth fit, which assumes their ports can be multiplexed
+    pub max_sharers: usize,
+}
+
+impl Default for SharingConfig {
+    fn default() -> Self {
+        SharingConfig {
+            modes: vec![MemMode::Rom, MemMode::SinglePort],
+            granularity: None,
+            max_sharers: 2,
+        }
+    }
+}
+
+impl Default for MapOptions {
+    fn default() -> Self {
+        MapOptions {
+            memory_limit: None,
+            sharing_iterations: 1,
+            hints: HashMap::new(),
+            type_priority: PhysType::ALL.to_vec(),
+            lut_budget: None,
+            stream_decisions: false,
+            parallel_memories: false,
+            sharing: SharingConfig::default(),
+            strict: false,
+            allow_split: false,
+            seed: None,
+            allow_lut_memory: false,
+            progress: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Memory {
+    pub ram_id: i32,
+    pub mode: MemMode,
+    pub depth: i32,
+    pub width: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Circuit {
+    pub id: i32,
+    pub logic_blocks: i32,
+    pub memories: Vec<Memory>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RamMapping {
+    pub circuit_id: i32,
+    pub logical_ram_id: i32,
+    pub extra_luts: i32,
+    pub logical_width: i32,
+    pub logical_depth: i32,
+    // the RAM id, or under sharing the smallest RAM id in the block; unique per circuit only
+    pub group_id: i32,
+    pub series: i32,
+    pub parallel: i32,
+    pub phys_type: PhysType,
+    pub mode: MemMode,
+    pub phys_width: i32,
+    pub phys_depth: i32,
+    pub phys_blocks: i32,
+    // mode from the input; mode above becomes TrueDualPort when the block is shared
+    pub logical_mode: MemMode,
+}
+
+impl RamMapping {
+    // phys_blocks must equal series * parallel: the mapped file only carries S and P, and
+    // compute_geometric_area recomputes the block count from them
+    pub fn validate(&self) -> Result<(), String> {
+        if self.phys_blocks != self.series * self.parallel {
+            return Err(format!(
+                "phys_blocks {} != series {} * parallel {} for RAM {} in circuit {}",
+                self.phys_blocks, self.series, self.parallel, self.logical_ram_id, self.circuit_id
+            ));
+        }
+        Ok(())
+    }
+
+    // built from LUTs by lut_memory_mapping, with no physical blocks at all
+    pub fn is_lut_only(&self) -> bool {
+        self.phys_blocks == 0
+    }
+
+    // logical bits over the bits of its blocks on cfg, in [0, 1]; 1 for a LUT-only mapping.
+    // A shared block's members each count only their own bits
+    pub fn utilization(&self, cfg: &PhysConfig) -> f64 {
+        let logical_bits = (self.logical_width as i64 * self.logical_depth as i64) as f64;
+        let phys_bits = (self.phys_blocks as i64 * cfg.bits as i64) as f64;
+        if phys_bits > 0.0 {
+            (logical_bits / phys_bits).clamp(0.0, 1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CircuitResult {
+    pub mappings: Vec<RamMapping>,
+    pub extra_luts: i32,
+    pub lutram_blocks: i32,
+    pub m8k_blocks: i32,
+    pub m128k_blocks: i32,
+    // blocks of each extra BRAM tier, indexed like PhysType::Extra
+    pub extra_blocks: Vec<i32>,
+    // shapes costed by best_mapping_for_phys_type across all memories and types
+    pub candidates_evaluated: u64,
+    // circuits left out because one of their RAMs has no legal mapping (non-strict only)
+    pub skipped: Vec<MappingError>,
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+impl CircuitResult {
+    pub fn blocks(&self, phys_type: PhysType) -> i32 {
+        match phys_type {
+            PhysType::Lutram => self.lutram_blocks,
+            PhysType::Ram8K => self.m8k_blocks,
+            PhysType::Ram128K => self.m128k_blocks,
+            PhysType::Extra(i) => self.extra_blocks.get(i as usize).copied().unwrap_or(0),
+        }
+    }
+
+    fn blocks_mut(&mut self, phys_type: PhysType) -> &mut i32 {
+        match phys_type {
+            PhysType::Lutram => &mut self.lutram_blocks,
+            PhysType::Ram8K => &mut self.m8k_blocks,
+            PhysType::Ram128K => &mut self.m128k_blocks,
+            PhysType::Extra(i) => {
+                let i = i as usize;
+                if self.extra_blocks.len() <= i {
+                    self.extra_blocks.resize(i + 1, 0);
+                }
+                &mut self.extra_blocks[i]
+            }
+        }
+    }
+
+    // FNV-1a over every mapping (in order) and the totals; stable across runs and builds
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = FNV_OFFSET;
+        for m in &self.mappings {
+            for v in [
+                m.circuit_id,
+                m.logical_ram_id,
+                m.extra_luts,
+                m.logical_width,
+                m.logical_depth,
+                m.group_id,
+                m.series,
+                m.parallel,
+                m.phys_type.type_id(),
+                m.mode.code(),
+                m.phys_width,
+                m.phys_depth,
+                m.phys_blocks,
+            ] {
+                hash = fnv1a(hash, &v.to_le_bytes());
+            }
+        }
+        for v in [
+            self.extra_luts,
+            self.lutram_blocks,
+            self.m8k_blocks,
+            self.m128k_blocks,
+        ]
+        .into_iter()
+        .chain(self.extra_blocks.iter().copied())
+        {
+            hash = fnv1a(hash, &v.to_le_bytes());
+        }
+        fnv1a(hash, &self.candidates_evaluated.to_le_bytes())
+    }
+}
+
+//applying physical RAM sharing, repeated until a pass merges nothing or the iteration cap is hit
+fn apply_sharing(
+    result: &mut CircuitResult,
+    configs: &PhysConfigs,
+    iterations: usize,
+    sharing: &SharingConfig,
+) {
+    for cfg in configs.brams() {
+        let mut blocks = result.blocks(cfg.phys_type);
+        for _ in 0..iterations {
+            if share_type(&mut result.mappings, cfg, sharing, &mut blocks) == 0 {
+                break;
+            }
+        }
+        *result.blocks_mut(cfg.phys_type) = blocks;
+    }
+}
+
+// logical bits of a mapping that may share its physical block, or None if it can't share
+fn share_candidate_bits(m: &RamMapping, cfg: &PhysConfig, sharing: &SharingConfig) -> Option<i32> {
+    if m.phys_type != cfg.phys_type {
+        return None;
+    }
+    if !sharing.modes.contains(&m.mode) {
+        return None;
+    }
+    if m.phys_blocks != 1 {
+        return None;
+    }
+    // the shared block runs in TDP mode, so each sharer must fit the TDP width
+    // (a type with max_width_tdp 0 cannot share at all)
+    if m.phys_width > cfg.max_width_tdp {
+        return None;
+    }
+
+    let logical_bits = m.logical_width * m.logical_depth;
+    if logical_bits <= 0 || logical_bits >= cfg.bits {
+        return None;
+    }
+    if let Some(g) = sharing.granularity
+        && g > 0
+        && (cfg.bits % g != 0 || logical_bits % (cfg.bits / g) != 0)
+    {
+        return None;
+    }
+    Some(logical_bits)
+}
+
+// two mappings can only share if physical shape is identical and both fit the block depth
+fn share_compatible(a: &RamMapping, b: &RamMapping) -> bool {
+    // only share if physical shape is identical. Avoids id mismatch when mapping
+    if a.phys_width != b.phys_width
+        || a.phys_depth != b.phys_depth
+        || a.series != b.series
+        || a.parallel != b.parallel
+    {
+        return false;
+    }
+    //checking depth so it does not exceed
+    let total_phys_depth = a.phys_depth * a.series;
+
+    // how much depth the two logical RAMs would collectively need
+    let combined_logical_depth = a.logical_depth + b.logical_depth;
+
+    combined_logical_depth <= total_phys_depth
+}
+
+//function to share BRAMs, returns the number of blocks freed
+fn share_type(
+    mappings: &mut [RamMapping],
+    cfg: &PhysConfig,
+    sharing: &SharingConfig,
+    total_blocks: &mut i32,
+) -> usize {
+    let phys_bits = cfg.bits;
+
+    // the parts of a width-split RAM keep their own group
+    let split = split_rams(mappings);
+    let candidates: Vec<(usize, i32)> = mappings
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| !split.contains(&(m.circuit_id, m.logical_ram_id)))
+        .filter_map(|(idx, m)| share_candidate_bits(m, cfg, sharing).map(|bits| (idx, bits)))
+        .collect();
+
+    let packing = sharing.max_sharers > 2;
+    let mut merges = 0;
+    let mut already_shared = vec![false; mappings.len()];
+    // each unshared candidate i leads a group filled from the compatible candidates after it
+    for (pos, &(idx_i, bits_i)) in candidates.iter().enumerate() {
+        if already_shared[idx_i] {
+            continue;
+        }
+
+        let mut group = vec![idx_i];
+        let mut group_bits = bits_i;
+        let mut group_depth = mappings[idx_i].logical_depth;
+        let phys_depth = mappings[idx_i].phys_depth * mappings[idx_i].series;
+        for &(idx_j, bits_j) in &candidates[pos + 1..] {
+            if group.len() >= sharing.max_sharers {
+                break;
+            }
+            if already_shared[idx_j] {
+                continue;
+            }
+            if mappings[idx_i].circuit_id != mappings[idx_j].circuit_id {
+                continue;
+            }
+            if !share_compatible(&mappings[idx_i], &mappings[idx_j]) {
+                continue;
+            }
+
+            let joins = if packing {
+                group_bits + bits_j <= phys_bits
+                    && group_depth + mappings[idx_j].logical_depth <= phys_depth
+            } else {
+                group_bits + bits_j == phys_bits
+            };
+            if joins {
+                group.push(idx_j);
+                group_bits += bits_j;
+                group_depth += mappings[idx_j].logical_depth;
+            }
+        }
+        if group.len() < 2 {
+            continue;
+        }
+
+        let gid = (group.iter())
+            .map(|&idx| mappings[idx].group_id)
+            .min()
+            .unwrap_or(mappings[idx_i].group_id);
+        for &idx in &group {
+            already_shared[idx] = true;
+            mappings[idx].mode = MemMode::TrueDualPort;
+            mappings[idx].group_id = gid;
+        }
+        let freed = group.len() - 1;
+        *total_blocks -= freed as i32;
+        merges += freed;
+    }
+    merges
+}
+
+// Every shared group must still fit in one physical block: same shape for all members and
+// the members' logical depths stacked within phys_depth * series.
+fn validate_shared_groups(mappings: &[RamMapping]) -> Result<(), RapidMapError> {
+    let mut groups: HashMap<(i32, i32), Vec<&RamMapping>> = HashMap::new();
+    for m in mappings {
+        groups
+            .entry((m.circuit_id, m.group_id))
+            .or_default()
+            .push(m);
+    }
+
+    for ((circuit_id, group_id), members) in groups {
+        if members.len() < 2 {
+            continue;
+        }
+        let first = members[0];
+        // the parts of one width-split RAM, not a shared block
+        if members
+            .iter()
+            .all(|m| m.logical_ram_id == first.logical_ram_id)
+        {
+            continue;
+        }
+        let same_shape = members.iter().all(|m| {
+            m.phys_type == first.phys_type
+                && m.phys_width == first.phys_width
+                && m.phys_depth == first.phys_depth
+                && m.series == first.series
+                && m.parallel == first.parallel
+        });
+        let stacked_depth: i32 = members.iter().map(|m| m.logical_depth).sum();
+        let phys_depth = first.phys_depth * first.series;
+        if !same_shape || stacked_depth > phys_depth {
+            return Err(RapidMapError::InvalidSharing {
+                circuit_id,
+                group_id,
+                stacked_depth,
+                phys_depth,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Informational only: pairs of complementary RAMs that would share a block if they were
+// in the same circuit. Mappings are not modified. Each mapping appears in at most one pair,
+// so the number of pairs is the number of blocks lost to circuit isolation.
+pub fn cross_circuit_share_report<'a>(
+    result: &'a CircuitResult,
+    configs: &PhysConfigs,
+    sharing: &SharingConfig,
+) -> Vec<(&'a RamMapping, &'a RamMapping)> {
+    let mut pairs = Vec::new();
+    let split = split_rams(&result.mappings);
+
+    for cfg in configs.brams() {
+        let candidates: Vec<(&RamMapping, i32)> = result
+            .mappings
+            .iter()
+            .filter(|m| !split.contains(&(m.circuit_id, m.logical_ram_id)))
+            .filter_map(|m| share_candidate_bits(m, cfg, sharing).map(|bits| (m, bits)))
+            .collect();
+
+        let mut paired = vec![false; candidates.len()];
+        for i in 0..candidates.len() {
+            if paired[i] {
+                continue;
+            }
+            let (m_i, bits_i) = candidates[i];
+            for j in (i + 1)..candidates.len() {
+                let (m_j, bits_j) = candidates[j];
+                if paired[j] || m_i.circuit_id == m_j.circuit_id {
+                    continue;
+                }
+                if bits_i + bits_j == cfg.bits && share_compatible(m_i, m_j) {
+                    paired[i] = true;
+                    paired[j] = true;
+                    pairs.push((m_i, m_j));
+                    break;
+                }
+            }
+        }
+    }
+
+    pairs
+}
+
+// (circuit, ram) of the logical RAMs mapped as more than one part under --allow-split
+fn split_rams(mappings: &[RamMapping]) -> HashSet<(i32, i32)> {
+    let mut seen = HashSet::new();
+    mappings
+        .iter()
+        .map(|m| (m.circuit_id, m.logical_ram_id))
+        .filter(|&key| !seen.insert(key))
+        .collect()
+}
+
+//reading data with error-handling
+// integer with an optional size suffix: "Ki" or "K" is x1024, "k" is x1000
+fn parse_size(s: &str) -> Option<i32> {
+    let (digits, scale) = if let Some(d) = s.strip_suffix("Ki") {
+        (d, 1024)
+    } else if let Some(d) = s.strip_suffix('K') {
+        (d, 1024)
+    } else if let Some(d) = s.strip_suffix('k') {
+        (d, 1000)
+    } else {
+        (s, 1)
+    };
+    digits.parse::<i32>().ok()?.checked_mul(scale)
+}
+
+// one data line of the logic block file (header already skipped); Err says why it was skipped
+fn add_logic_block_line(
+    circuits_map: &mut HashMap<i32, Circuit>,
+    line: &str,
+) -> Result<(), String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() < 2 {
+        return Err("expected a circuit id and a logic block count".into());
+    }
+    let circuit_id: i32 = parts[0]
+        .parse()
+        .map_err(|_| format!("bad circuit id '{}'", parts[0]))?;
+    let logic_blocks: i32 = match parts[1].parse() {
+        Ok(v) if v >= 0 => v,
+        _ => {
+            return Err(format!(
+                "bad logic block count '{}' for circuit {}",
+                parts[1], circuit_id
+            ));
+        }
+    };
+
+    circuits_map.insert(
+        circuit_id,
+        Circuit {
+            id: circuit_id,
+            logic_blocks,
+            memories: Vec::new(),
+        },
+    );
+    Ok(())
+}
+
+// one data line of the logical RAM file (header lines already skipped); Err says why the
+// RAM was dropped
+fn add_ram_line(circuits_map: &mut HashMap<i32, Circuit>, line: &str) -> Result<(), String> {
+    let (circuit_id, mem) = parse_ram_line(line)?;
+    let entry = circuits_map.entry(circuit_id).or_insert(Circuit {
+        id: circuit_id,
+        logic_blocks: 0,
+        memories: Vec::new(),
+    });
+    entry.memories.push(mem);
+    Ok(())
+}
+
+// circuit id and memory of one logical RAM line
+fn parse_ram_line(line: &str) -> Result<(i32, Memory), String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() < 5 {
+        return Err("expected circuit, ram id, mode, depth and width; RAM dropped".into());
+    }
+
+    let circuit_id: i32 = parts[0]
+        .parse()
+        .map_err(|_| format!("bad circuit id '{}', RAM dropped", parts[0]))?;
+    let ram_id: i32 = parts[1]
+        .parse()
+        .map_err(|_| format!("bad ram id '{}' in circuit {}", parts[1], circuit_id))?;
+    let dropped = |what: String| format!("{} for RAM {} in circuit {}", what, ram_id, circuit_id);
+
+    let mode_str = parts[2];
+    // mode column may be a name or an integer code
+    let mode = match mode_str.parse::<i32>() {
+        Ok(code) => MemMode::from_code(code),
+        Err(_) => MemMode::from_name(mode_str),
+    };
+    let mode = mode.ok_or_else(|| dropped(format!("unknown mode '{}'", mode_str)))?;
+
+    let depth: i32 = match parse_size(parts[3]) {
+        Some(v) if v > 0 => v,
+        Some(v) => return Err(dropped(format!("depth must be positive, got {}", v))),
+        None => return Err(dropped(format!("bad depth '{}'", parts[3]))),
+    };
+    let width: i32 = match parse_size(parts[4]) {
+        Some(v) if v > 0 => v,
+        Some(v) => return Err(dropped(format!("width must be positive, got {}", v))),
+        None => return Err(dropped(format!("bad width '{}'", parts[4]))),
+    };
+
+    let mem = Memory {
+        ram_id,
+        mode,
+        depth,
+        width,
+    };
+    Ok((circuit_id, mem))
+}
+
+// first line that starts with an integer (a circuit id), split into columns; header lines
+// never do, so this works whichever file the path really points at
+fn first_data_line(path: &str) -> io::Result<Option<Vec<String>>> {
+    let reader = BufReader::new(File::open(path)?);
+    for line_res in reader.lines() {
+        let line = line_res?;
+        let cols: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+        if cols.first().is_some_and(|c| c.parse::<i32>().is_ok()) {
+            return Ok(Some(cols));
+        }
+    }
+    Ok(None)
+}
+
+// Cheap check that the two inputs have the expected column shapes, to catch swapped or
+// duplicated paths before read_data quietly skips every row
+pub fn check_input_shapes(
+    logic_block_file: &str,
+    logic_rams_file: &str,
+) -> Result<(), RapidMapError> {
+    let is_int = |s: &String| s.parse::<i32>().is_ok();
+    // extra trailing columns are tolerated in both files
+    let looks_like_rams = |cols: &[String]| {
+        cols.len() >= 5
+            && is_int(&cols[0])
+            && is_int(&cols[1])
+            && (MemMode::from_name(&cols[2]).is_some()
+                || cols[2].parse().ok().and_then(MemMode::from_code).is_some())
+    };
+    let looks_like_blocks = |cols: &[String]| {
+        cols.len() >= 2 && is_int(&cols[0]) && is_int(&cols[1]) && !looks_like_rams(cols)
+    };
+
+    let blocks =
+        first_data_line(logic_block_file).map_err(|e| RapidMapError::file(logic_block_file, e))?;
+    let rams =
+        first_data_line(logic_rams_file).map_err(|e| RapidMapError::file(logic_rams_file, e))?;
+    // only when each file has the other's shape
+    let swapped = match (&blocks, &rams) {
+        (Some(b), Some(r)) if looks_like_rams(b) && looks_like_blocks(r) => {
+            "; were the logic block and logical RAM files swapped?"
+        }
+        _ => "",
+    };
+    if let Some(cols) = &blocks
+        && !looks_like_blocks(cols)
+    {
+        return Err(RapidMapError::Config(format!(
+            "{} does not look like a logic block file (expected `circuit logic_blocks`, got `{}`){}",
+            logic_block_file,
+            cols.join(" "),
+            swapped
+        )));
+    }
+    if let Some(cols) = &rams
+        && !looks_like_rams(cols)
+    {
+        return Err(RapidMapError::Config(format!(
+            "{} does not look like a logical RAM file (expected `circuit ram mode depth width`, got `{}`){}",
+            logic_rams_file,
+            cols.join(" "),
+            swapped
+        )));
+    }
+    Ok(())
+}
+
+/// What the readers do with logical RAMs whose circuit id has no line in the logic block
+/// file. Such a circuit can only be created with 0 logic blocks, which understates its area.
+///
+/// ```
+/// use rapid_map::{OrphanRamPolicy, ReadOptions, read_data};
+///
+/// let dir = std::env::temp_dir().join(format!("rapid-map-orphan-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let blocks = dir.join("logic_block_count.txt");
+/// let rams = dir.join("logical_rams.txt");
+/// std::fs::write(&blocks, "Circuit\tLogic_Blocks\n0\t500\n").unwrap();
+/// // circuit 7 has a RAM but no logic block entry
+/// std::fs::write(
+///     &rams,
+///     "Num_Circuits 2\nCircuit\tRAM_ID\tMode\tDepth\tWidth\n\
+///      0\t0\tSinglePort\t1024\t16\n7\t0\tROM\t256\t8\n",
+/// )
+/// .unwrap();
+/// let (blocks, rams) = (blocks.to_str().unwrap(), rams.to_str().unwrap());
+///
+/// let read = |orphans| read_data(blocks, rams, &ReadOptions { orphans, ..Default::default() });
+/// for policy in [OrphanRamPolicy::Create, OrphanRamPolicy::Warn] {
+///     let (circuits, warnings) = read(policy).unwrap();
+///     assert!(warnings.is_empty());
+///     let ids: Vec<_> = circuits.iter().map(|c| (c.id, c.logic_blocks)).collect();
+///     assert_eq!(ids, [(0, 500), (7, 0)]);
+/// }
+/// let err = read(OrphanRamPolicy::Error).unwrap_err();
+/// assert!(err.to_string().ends_with("no logic block entry: 7"));
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OrphanRamPolicy {
+    // create the circuit and print the orphan circuit ids to stderr
+    #[default]
+    Warn,
+    // fail the read with an InvalidData error listing the orphan circuit ids
+    Error,
+    // create the circuit silently
+    Create,
+}
+
+/// How read_data and the other readers parse the two input files.
+///
+/// ```
+/// use rapid_map::{ReadOptions, read_data};
+///
+/// let dir = std::env::temp_dir().join(format!("rapid-map-header-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let blocks = dir.join("blocks.txt");
+/// let rams = dir.join("rams.txt");
+/// let (blocks_path, rams_path) = (blocks.to_str().unwrap(), rams.to_str().unwrap());
+/// let ids = |opts: &ReadOptions| {
+///     let (circuits, warnings) = read_data(blocks_path, rams_path, opts).unwrap();
+///     assert!(warnings.is_empty());
+///     let ids: Vec<_> = circuits.iter().map(|c| (c.id, c.memories.len())).collect();
+///     ids
+/// };
+///
+/// // no headers at all
+/// std::fs::write(&blocks, "0 500\n1 800\n").unwrap();
+/// std::fs::write(&rams, "0 0 SinglePort 1024 16\n1 0 ROM 256 8\n").unwrap();
+/// let headerless = ReadOptions {
+///     logic_blocks_header: 0,
+///     logical_rams_header: 0,
+///     ..Default::default()
+/// };
+/// assert_eq!(ids(&headerless), [(0, 1), (1, 1)]);
+/// // the default header sizes drop the first line of one file and both lines of the other
+/// assert_eq!(ids(&ReadOptions::default()), [(1, 0)]);
+///
+/// // a comment line on top of the usual headers
+/// std::fs::write(&blocks, "# exported 2024-01-01\nCircuit Logic_Blocks\n0 500\n1 800\n").unwrap();
+/// std::fs::write(
+///     &rams,
+///     "# exported 2024-01-01\nNum_Circuits 2\nCircuit RAM_ID Mode Depth Width\n\
+///      0 0 SinglePort 1024 16\n1 0 ROM 256 8\n",
+/// )
+/// .unwrap();
+/// let extra = ReadOptions {
+///     logic_blocks_header: 2,
+///     logical_rams_header: 3,
+///     ..Default::default()
+/// };
+/// assert_eq!(ids(&extra), [(0, 1), (1, 1)]);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct ReadOptions {
+    // leading lines of the logic block file to skip (the column names)
+    pub logic_blocks_header: usize,
+    // leading lines of the logical RAM file to skip (Num_Circuits and the column names)
+    pub logical_rams_header: usize,
+    pub orphans: OrphanRamPolicy,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            logic_blocks_header: 1,
+            logical_rams_header: 2,
+            orphans: OrphanRamPolicy::Warn,
+        }
+    }
+}
+
+// applies the policy to circuits that RAM lines of `path` created without a logic block entry
+fn check_orphans(path: &str, mut orphans: Vec<i32>, policy: OrphanRamPolicy) -> io::Result<()> {
+    if orphans.is_empty() || policy == OrphanRamPolicy::Create {
+        return Ok(());
+    }
+    orphans.sort_unstable();
+    let ids: Vec<String> = orphans.iter().map(i32::to_string).collect();
+    let msg = format!(
+        "{}: logical RAMs reference circuits with no logic block entry: {}",
+        path,
+        ids.join(", ")
+    );
+    if policy == OrphanRamPolicy::Error {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    }
+    eprintln!("Warning: {} (created with 0 logic blocks)", msg);
+    Ok(())
+}
+
+// circuits in `circuits_map` that are not in `known`
+fn orphan_ids(circuits_map: &HashMap<i32, Circuit>, known: &HashSet<i32>) -> Vec<i32> {
+    (circuits_map.keys())
+        .filter(|id| !known.contains(id))
+        .copied()
+        .collect()
+}
+
+// Circuits from the two input files, plus one warning per data line that was skipped
+pub fn read_data(
+    logic_block_file: &str,
+    logic_rams_file: &str,
+    opts: &ReadOptions,
+) -> io::Result<(Vec<Circuit>, Vec<ParseWarning>)> {
+    let file = File::open(logic_block_file)?;
+    let reader = BufReader::new(file);
+    let mut circuits_map: HashMap<i32, Circuit> = HashMap::new();
+    let mut warnings = Vec::new();
+
+    for (line_idx, line_res) in reader.lines().enumerate() {
+        let line = line_res?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line_idx < opts.logic_blocks_header {
+            continue;
+        }
+        if let Err(reason) = add_logic_block_line(&mut circuits_map, line) {
+            warnings.push(parse_warning(logic_block_file, line_idx, reason));
+        }
+    }
+
+    let known: HashSet<i32> = circuits_map.keys().copied().collect();
+    let file = File::open(logic_rams_file)?;
+    let reader = BufReader::new(file);
+
+    for (line_idx, line_res) in reader.lines().enumerate() {
+        let line = line_res?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line_idx < opts.logical_rams_header {
+            continue;
+        }
+        if let Err(reason) = add_ram_line(&mut circuits_map, line) {
+            warnings.push(parse_warning(logic_rams_file, line_idx, reason));
+        }
+    }
+    check_orphans(
+        logic_rams_file,
+        orphan_ids(&circuits_map, &known),
+        opts.orphans,
+    )?;
+
+    Ok((sorted_circuits(circuits_map), warnings))
+}
+
+// Same output as read_data, but splits memory-mapped files on newlines instead of
+// reading through a BufReader. Intended for multi-GB inputs.
+#[cfg(feature = "mmap")]
+pub fn read_data_mmap(
+    logic_block_file: &str,
+    logic_rams_file: &str,
+    opts: &ReadOptions,
+) -> io::Result<(Vec<Circuit>, Vec<ParseWarning>)> {
+    // lines of a mapped file with the same header skipping and trimming as read_data
+    fn for_each_line(
+        path: &str,
+        header_lines: usize,
+        mut f: impl FnMut(usize, &str),
+    ) -> io::Result<()> {
+        let file = File::open(path)?;
+        // SAFETY: the input files are only read, and not expected to change during the run
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        let text =
+            std::str::from_utf8(&map).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line_idx < header_lines {
+                continue;
+            }
+            f(line_idx, line);
+        }
+        Ok(())
+    }
+
+    let mut circuits_map: HashMap<i32, Circuit> = HashMap::new();
+    let mut warnings = Vec::new();
+    for_each_line(
+        logic_block_file,
+        opts.logic_blocks_header,
+        |line_idx, line| {
+            if let Err(reason) = add_logic_block_line(&mut circuits_map, line) {
+                warnings.push(parse_warning(logic_block_file, line_idx, reason));
+            }
+        },
+    )?;
+    let known: HashSet<i32> = circuits_map.keys().copied().collect();
+    for_each_line(
+        logic_rams_file,
+        opts.logical_rams_header,
+        |line_idx, line| {
+            if let Err(reason) = add_ram_line(&mut circuits_map, line) {
+                warnings.push(parse_warning(logic_rams_file, line_idx, reason));
+            }
+        },
+    )?;
+    check_orphans(
+        logic_rams_file,
+        orphan_ids(&circuits_map, &known),
+        opts.orphans,
+    )?;
+    Ok((sorted_circuits(circuits_map), warnings))
+}
+
+// Circuits from the two input files one at a time, in the same order and with the same
+// warnings as read_data. When the logical RAM file is sorted by circuit id only the current
+// circuit's memories are held; otherwise it falls back to read_data and holds them all.
+// Orphan circuits are reported as they are reached, so under OrphanRamPolicy::Error the
+// stream ends with an error at the first one.
+pub fn read_circuits_streaming(
+    logic_block_file: &str,
+    logic_rams_file: &str,
+    opts: &ReadOptions,
+) -> io::Result<CircuitStream> {
+    if !rams_sorted_by_circuit(logic_rams_file, opts.logical_rams_header)? {
+        let (circuits, warnings) = read_data(logic_block_file, logic_rams_file, opts)?;
+        return Ok(CircuitStream {
+            source: StreamSource::Buffered(circuits.into_iter()),
+            warnings,
+        });
+    }
+
+    // the logic block file has one short line per circuit, so it is read up front
+    let reader = BufReader::new(File::open(logic_block_file)?);
+    let mut circuits_map: HashMap<i32, Circuit> = HashMap::new();
+    let mut warnings = Vec::new();
+    for (line_idx, line_res) in reader.lines().enumerate() {
+        let line = line_res?;
+        let line = line.trim();
+        if line.is_empty() || line_idx < opts.logic_blocks_header {
+            continue;
+        }
+        if let Err(reason) = add_logic_block_line(&mut circuits_map, line) {
+            warnings.push(parse_warning(logic_block_file, line_idx, reason));
+        }
+    }
+
+    Ok(CircuitStream {
+        source: StreamSource::Sorted(SortedRams {
+            path: logic_rams_file.to_string(),
+            header_lines: opts.logical_rams_header,
+            orphans: opts.orphans,
+            lines: BufReader::new(File::open(logic_rams_file)?).lines(),
+            line_idx: 0,
+            logic: sorted_circuits(circuits_map).into_iter().peekable(),
+            current: None,
+            ready: VecDeque::new(),
+            orphan_error: None,
+            done: false,
+        }),
+        warnings,
+    })
+}
+
+// whether the circuit ids of the RAM file's data lines never decrease; lines without an
+// integer circuit id are left to the parser to warn about
+fn rams_sorted_by_circuit(path: &str, header_lines: usize) -> io::Result<bool> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut last = i32::MIN;
+    for line_res in reader.lines().skip(header_lines) {
+        let line = line_res?;
+        let Some(id) = line
+            .split_whitespace()
+            .next()
+            .and_then(|s| s.parse::<i32>().ok())
+        else {
+            continue;
+        };
+        if id < last {
+            return Ok(false);
+        }
+        last = id;
+    }
+    Ok(true)
+}
+
+pub struct CircuitStream {
+    source: StreamSource,
+    warnings: Vec<ParseWarning>,
+}
+
+enum StreamSource {
+    Sorted(SortedRams),
+    Buffered(std::vec::IntoIter<Circuit>),
+}
+
+struct SortedRams {
+    path: String,
+    header_lines: usize,
+    orphans: OrphanRamPolicy,
+    lines: io::Lines<BufReader<File>>,
+    line_idx: usize,
+    // logic block file circuits not yet reached in the RAM file
+    logic: std::iter::Peekable<std::vec::IntoIter<Circuit>>,
+    // circuit whose memories are being read
+    current: Option<Circuit>,
+    // finished circuits, in id order
+    ready: VecDeque<Circuit>,
+    // an orphan circuit under OrphanRamPolicy::Error, returned once `ready` is drained
+    orphan_error: Option<io::Error>,
+    done: bool,
+}
+
+impl CircuitStream {
+    // false when the RAM file was not sorted and everything was read up front
+    pub fn is_streaming(&self) -> bool {
+        matches!(self.source, StreamSource::Sorted(_))
+    }
+
+    // lines skipped so far; complete once the iterator has returned None
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+}
+
+impl Iterator for CircuitStream {
+    type Item = io::Result<Circuit>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.source {
+            StreamSource::Sorted(rams) => rams.next_circuit(&mut self.warnings),
+            StreamSource::Buffered(circuits) => circuits.next().map(Ok),
+        }
+    }
+}
+
+impl SortedRams {
+    fn next_circuit(&mut self, warnings: &mut Vec<ParseWarning>) -> Option<io::Result<Circuit>> {
+        loop {
+            if let Some(c) = self.ready.pop_front() {
+                return Some(Ok(c));
+            }
+            if let Some(e) = self.orphan_error.take() {
+                return Some(Err(e));
+            }
+            if self.done {
+                return None;
+            }
+            let line = match self.lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+                None => {
+                    // circuits with no RAMs, after the last one that had any
+                    self.done = true;
+                    self.ready.extend(self.current.take());
+                    self.ready.extend(self.logic.by_ref());
+                    continue;
+                }
+            };
+            let line_idx = self.line_idx;
+            self.line_idx += 1;
+            let line = line.trim();
+            if line.is_empty() || line_idx < self.header_lines {
+                continue;
+            }
+            let (circuit_id, mem) = match parse_ram_line(line) {
+                Ok(parsed) => parsed,
+                Err(reason) => {
+                    warnings.push(parse_warning(&self.path, line_idx, reason));
+                    continue;
+                }
+            };
+            if self.current.as_ref().is_none_or(|c| c.id != circuit_id) {
+                self.ready.extend(self.current.take());
+                while let Some(c) = self.logic.next_if(|c| c.id < circuit_id) {
+                    self.ready.push_back(c);
+                }
+                let c = self.logic.next_if(|c| c.id == circuit_id);
+                if c.is_none()
+                    && let Err(e) = check_orphans(&self.path, vec![circuit_id], self.orphans)
+                {
+                    // the circuits before it are complete and still returned first
+                    self.done = true;
+                    self.orphan_error = Some(e);
+                    continue;
+                }
+                self.current = Some(c.unwrap_or(Circuit {
+                    id: circuit_id,
+                    logic_blocks: 0,
+                    memories: Vec::new(),
+                }));
+            }
+            if let Some(c) = &mut self.current {
+                c.memories.push(mem);
+            }
+        }
+    }
+}
+
+fn parse_warning(path: &str, line_idx: usize, reason: String) -> ParseWarning {
+    ParseWarning {
+        path: path.to_string(),
+        line: line_idx + 1,
+        reason,
+    }
+}
+
+fn sorted_circuits(circuits_map: HashMap<i32, Circuit>) -> Vec<Circuit> {
+    let mut circuits: Vec<Circuit> = circuits_map.into_values().collect();
+    circuits.sort_by_key(|c| c.id);
+    circuits
+}
+
+pub fn block_ram_area(bits: i32, max_width: i32, model: &BramAreaModel) -> f64 {
+    model.area(bits, max_width)
+}
+
+// Write-enable decoder for s series blocks, unless table covers s. A single block needs
+// none, and s=2 is the special case: one LUT drives one enable from the address bit and the
+// other from its inverse. From s=3 on it is one LUT per block, so 0..=17 gives
+// 0, 0, 1, 3, 4, 5, ..., 17 (s <= 0 cannot occur and also gives 0).
+fn decoder_luts(s: i32, table: &[i32]) -> i32 {
+    if let Some(&luts) = usize::try_from(s - 1).ok().and_then(|i| table.get(i)) {
+        return luts;
+    }
+    if s <= 1 {
+        0
+    } else if s == 2 {
+        1
+    } else {
+        s
+    }
+}
+
+// 4:1 mux tree selecting one of s series blocks, per output bit. Each level needs
+// ceil(n / 4) LUTs for the n signals below it, so per bit s=2..4 costs 1, s=5 costs 3
+// (2 + 1), s=8 costs 3, s=16 costs 5 (4 + 1) and s=17 costs 8 (5 + 2 + 1); the total is
+// that times width. With pipeline_threshold Some(t) and s > t, each tree level also gets a
+// register LUT per bit, e.g. s=16 with t=4 costs 7 per bit
+fn mux_luts(s: i32, width: i32, pipeline_threshold: Option<i32>) -> i32 {
+    if s <= 1 {
+        return 0;
+    }
+    let mut n = s;
+    let mut total_nodes = 0;
+    let mut levels = 0;
+    while n > 1 {
+        let level_nodes = (n + 3) / 4;
+        total_nodes += level_nodes;
+        levels += 1;
+        n = level_nodes;
+    }
+    let register_luts = match pipeline_threshold {
+        Some(t) if s > t => levels,
+        _ => 0,
+    };
+    let luts_per_bit = total_nodes + register_luts;
+    width * luts_per_bit
+}
+
+fn mapping_cost(mapping: &RamMapping, cfg: &PhysConfig) -> f64 {
+    let lb_for_extra_luts = (mapping.extra_luts + 9) / 10;
+
+    let base_area = match cfg.phys_type {
+        PhysType::Lutram => {
+            let lb_total = mapping.phys_blocks + lb_for_extra_luts;
+            (lb_total as f64) * cfg.cost.lb_area
+        }
+        _ => {
+            let lb_area = (lb_for_extra_luts as f64) * cfg.cost.lb_area;
+            let max_width = match mapping.mode {
+                MemMode::TrueDualPort => cfg.max_width_tdp,
+                _ => cfg.max_width_non_tdp,
+            };
+            let bram_area_per_block = cfg.cost.bram_area.area(cfg.bits, max_width);
+            lb_area + (mapping.phys_blocks as f64) * bram_area_per_block
+        }
+    };
+
+    let u = mapping.utilization(cfg);
+    let penalty_factor = cfg.cost.penalty_base + cfg.cost.penalty_strength * (10.0 - u);
+
+    let unused_rows = (mapping.phys_depth * mapping.series - mapping.logical_depth).max(0);
+    let cost = base_area * penalty_factor + cfg.cost.depth_waste_weight * unused_rows as f64;
+    if mapping.mode == MemMode::Rom {
+        cost * cfg.cost.rom_discount
+    } else {
+        cost
+    }
+}
+// widest physical port a memory of this mode may use on this type
+fn max_width_for_mode(cfg: &PhysConfig, mode: MemMode) -> i32 {
+    match mode {
+        MemMode::TrueDualPort => cfg.max_width_tdp,
+        MemMode::SimpleDualPort => cfg.max_width_sdp,
+        _ => cfg.max_width_non_tdp,
+    }
+}
+
+// physical depth of a block configured w_phys wide, or None if that width isn't legal
+fn phys_depth_for_width(cfg: &PhysConfig, w_phys: i32) -> Option<i32> {
+    let explicit_depth = cfg
+        .shapes
+        .iter()
+        .find(|&&(w, _)| w == w_phys)
+        .map(|&(_, d)| d);
+    let d_phys = match explicit_depth {
+        Some(d) => d,
+        None if !cfg.shapes.is_empty() => return None,
+        None => {
+            let is_max_width =
+                [cfg.max_width_non_tdp, cfg.max_width_sdp, cfg.max_width_tdp].contains(&w_phys);
+            if cfg.bits % w_phys != 0 && !(cfg.include_max_width && is_max_width) {
+                return None;
+            }
+            cfg.bits / w_phys
+        }
+    };
+    (d_phys > 0).then_some(d_phys)
+}
+
+fn build_mapping(
+    circuit_id: i32,
+    mem: &Memory,
+    group_id: i32,
+    cfg: &PhysConfig,
+    w_phys: i32,
+    d_phys: i32,
+    s: i32,
+    p: i32,
+) -> RamMapping {
+    let mut extra_luts =
+        decoder_luts(s, &cfg.decoder_table) + mux_luts(s, mem.width, cfg.pipeline_threshold);
+
+    if s > 1 && mem.mode == MemMode::TrueDualPort {
+        extra_luts *= 2;
+    }
+
+    let mapping = RamMapping {
+        circuit_id,
+        logical_ram_id: mem.ram_id,
+        extra_luts,
+        logical_width: mem.width,
+        logical_depth: mem.depth,
+        group_id,
+        series: s,
+        parallel: p,
+        phys_type: cfg.phys_type,
+        mode: mem.mode,
+        phys_width: w_phys,
+        phys_depth: d_phys,
+        phys_blocks: s * p,
+        logical_mode: mem.mode,
+    };
+    debug_assert_blocks_consistent(std::slice::from_ref(&mapping));
+    mapping
+}
+
+// The memory built from logic instead of a physical RAM: one LUT per bit, all of it in
+// extra_luts. Recorded as a LUTRAM mapping with S = P = 0 and no blocks, so the mapped file
+// and the area figures count only its LUTs. mapping_cost charges it ceil(LUTs / 10) logic
+// blocks at full utilization, which with the default costs beats a LUTRAM block only up
+// to ten bits. None when the LUT count overflows an i32.
+fn lut_memory_mapping(circuit_id: i32, mem: &Memory, group_id: i32) -> Option<RamMapping> {
+    Some(RamMapping {
+        circuit_id,
+        logical_ram_id: mem.ram_id,
+        extra_luts: mem.depth.checked_mul(mem.width)?,
+        logical_width: mem.width,
+        logical_depth: mem.depth,
+        group_id,
+        series: 0,
+        parallel: 0,
+        phys_type: PhysType::Lutram,
+        mode: mem.mode,
+        phys_width: mem.width,
+        phys_depth: mem.depth,
+        phys_blocks: 0,
+        logical_mode: mem.mode,
+    })
+}
+
+// checked after construction and after every pass that rewrites mappings
+fn debug_assert_blocks_consistent(mappings: &[RamMapping]) {
+    if cfg!(debug_assertions) {
+        for m in mappings {
+            if let Err(e) = m.validate() {
+                panic!("{}", e);
+            }
+        }
+    }
+}
+
+// calls visit with every legal shape of mem on cfg and its cost, in candidate order. Returns
+// the smallest series and parallel counts of the shapes rejected by max_series / max_parallel
+fn visit_candidates(
+    circuit_id: i32,
+    mem: &Memory,
+    group_id: i32,
+    cfg: &PhysConfig,
+    evaluated: &mut u64,
+    mut visit: impl FnMut(RamMapping, f64),
+) -> (Option<i32>, Option<i32>) {
+    if cfg.forbidden_modes.contains(&mem.mode) {
+        return (None, None);
+    }
+    if mem.mode == MemMode::TrueDualPort && cfg.max_width_tdp == 0 {
+        return (None, None);
+    }
+    let max_width = max_width_for_mode(cfg, mem.mode);
+    if max_width <= 0 {
+        return (None, None);
+    }
+    let mut width_candidates: Vec<i32> = match cfg.phys_type {
+        _ if !cfg.shapes.is_empty() => cfg.shapes.iter().map(|&(w, _)| w).collect(),
+        _ if !cfg.width_candidates.is_empty() => cfg.width_candidates.clone(),
+        _ => {
+            let mut v = Vec::new();
+            let mut w = 1;
+            while w <= max_width {
+                v.push(w);
+                w *= 2;
+            }
+            v
+        }
+    };
+    // on top of the powers of two or the listed widths; explicit shapes have only their own
+    if cfg.include_max_width && cfg.shapes.is_empty() && !width_candidates.contains(&max_width) {
+        width_candidates.push(max_width);
+    }
+
+    let mut over_series = i32::MAX;
+    let mut over_parallel = i32::MAX;
+    for &w_phys in &width_candidates {
+        if w_phys > max_width {
+            continue;
+        }
+        let Some(d_phys) = phys_depth_for_width(cfg, w_phys) else {
+            continue;
+        };
+        let mut p = mem.width / w_phys;
+        if mem.width % w_phys != 0 {
+            p += 1;
+        }
+
+        let mut s = mem.depth / d_phys;
+        if mem.depth % d_phys != 0 {
+            s += 1;
+        }
+
+        if s <= 0 || p <= 0 {
+            continue;
+        }
+        if s > cfg.max_series {
+            over_series = over_series.min(s);
+            continue;
+        }
+        if p > cfg.max_parallel {
+            over_parallel = over_parallel.min(p);
+            continue;
+        }
+        let mapping = build_mapping(circuit_id, mem, group_id, cfg, w_phys, d_phys, s, p);
+
+        let cost = mapping_cost(&mapping, cfg);
+        *evaluated += 1;
+        visit(mapping, cost);
+    }
+    (
+        (over_series < i32::MAX).then_some(over_series),
+        (over_parallel < i32::MAX).then_some(over_parallel),
+    )
+}
+
+fn best_mapping_for_phys_type(
+    circuit_id: i32,
+    mem: &Memory,
+    group_id: i32,
+    cfg: &PhysConfig,
+    evaluated: &mut u64,
+) -> Option<(RamMapping, f64)> {
+    let mut best: Option<(RamMapping, f64)> = None;
+    visit_candidates(
+        circuit_id,
+        mem,
+        group_id,
+        cfg,
+        evaluated,
+        |mapping, cost| match &mut best {
+            None => best = Some((mapping, cost)),
+            Some((_, best_cost)) => {
+                if cost < *best_cost {
+                    best = Some((mapping, cost));
+                }
+            }
+        },
+    );
+    best
+}
+
+// Every legal candidate for mem on the enabled types, in type_priority order, with its cost:
+// the search choose_mapping_for_memory runs, kept whole for --explain
+pub fn candidate_costs(
+    circuit_id: i32,
+    mem: &Memory,
+    configs: &PhysConfigs,
+    type_priority: &[PhysType],
+) -> Vec<(RamMapping, f64)> {
+    let mut candidates = Vec::new();
+    let mut evaluated = 0;
+    for &phys_type in type_priority {
+        if let Some(cfg) = configs.get(phys_type) {
+            visit_candidates(circuit_id, mem, 0, cfg, &mut evaluated, |m, cost| {
+                candidates.push((m, cost))
+            });
+        }
+    }
+    candidates
+}
+
+// User-supplied physical shape for one logical RAM, used instead of the search
+#[derive(Clone, Copy, Debug)]
+pub struct MappingHint {
+    pub phys_type: PhysType,
+    pub phys_width: i32,
+    pub series: i32,
+    pub parallel: i32,
+}
+
+// builds the hinted mapping, or explains why the hint is not legal under the current config
+fn mapping_from_hint(
+    circuit_id: i32,
+    mem: &Memory,
+    group_id: i32,
+    configs: &PhysConfigs,
+    hint: &MappingHint,
+) -> Result<RamMapping, String> {
+    let cfg = configs
+        .get(hint.phys_type)
+        .ok_or_else(|| format!("type {} is not enabled", hint.phys_type.type_id()))?;
+    if cfg.forbidden_modes.contains(&mem.mode) {
+        return Err(format!(
+            "{} is forbidden on {}",
+            mem.mode.as_str(),
+            hint.phys_type.name()
+        ));
+    }
+    let max_width = max_width_for_mode(cfg, mem.mode);
+    if hint.phys_width <= 0 || hint.phys_width > max_width {
+        return Err(format!(
+            "width {} exceeds max width {} for {}",
+            hint.phys_width,
+            max_width,
+            mem.mode.as_str()
+        ));
+    }
+    let d_phys = phys_depth_for_width(cfg, hint.phys_width)
+        .ok_or_else(|| format!("width {} is not a legal physical width", hint.phys_width))?;
+    if hint.series <= 0 || hint.series > cfg.max_series {
+        return Err(format!(
+            "series {} is outside 1..={}",
+            hint.series, cfg.max_series
+        ));
+    }
+    if hint.series * d_phys < mem.depth {
+        return Err(format!(
+            "series {} x depth {} does not cover logical depth {}",
+            hint.series, d_phys, mem.depth
+        ));
+    }
+    if hint.parallel > cfg.max_parallel {
+        return Err(format!(
+            "parallel {} is over max_parallel {}",
+            hint.parallel, cfg.max_parallel
+        ));
+    }
+    if hint.parallel <= 0 || hint.parallel * hint.phys_width < mem.width {
+        return Err(format!(
+            "parallel {} x width {} does not cover logical width {}",
+            hint.parallel, hint.phys_width, mem.width
+        ));
+    }
+    Ok(build_mapping(
+        circuit_id,
+        mem,
+        group_id,
+        cfg,
+        hint.phys_width,
+        d_phys,
+        hint.series,
+        hint.parallel,
+    ))
+}
+
+// hints file: one "circuit ram type width series parallel" per line, type as 1/2/3.
+// Blank lines and lines starting with '#' are ignored.
+pub fn read_hints(path: &str) -> io::Result<HashMap<(i32, i32), MappingHint>> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    let mut hints = HashMap::new();
+
+    for (line_idx, line_res) in reader.lines().enumerate() {
+        let line = line_res?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<i32> = line
+            .split_whitespace()
+            .filter_map(|p| p.parse().ok())
+            .collect();
+        let phys_type = parts.get(2).and_then(|&id| PhysType::from_type_id(id));
+        match (parts.len(), phys_type) {
+            (6, Some(phys_type)) => {
+                hints.insert(
+                    (parts[0], parts[1]),
+                    MappingHint {
+                        phys_type,
+                        phys_width: parts[3],
+                        series: parts[4],
+                        parallel: parts[5],
+                    },
+                );
+            }
+            _ => eprintln!("Bad hint on line {}: {}", line_idx + 1, line),
+        }
+    }
+    Ok(hints)
+}
+
+// (circuit, ram) of every memory that has no legal mapping on the given type
+pub fn unmappable_memories(circuits: &[Circuit], cfg: &PhysConfig) -> Vec<(i32, i32)> {
+    let mut evaluated = 0;
+    circuits
+        .iter()
+        .flat_map(|c| c.memories.iter().map(move |mem| (c.id, mem)))
+        .filter(|(cid, mem)| {
+            best_mapping_for_phys_type(*cid, mem, 0, cfg, &mut evaluated).is_none()
+        })
+        .map(|(cid, mem)| (cid, mem.ram_id))
+        .collect()
+}
+
+// (circuit, ram) of every TrueDualPort memory that no enabled type can implement, e.g. when
+// only LUTRAM (no TDP ports) is enabled or the BRAMs' TDP width cannot reach the depth
+pub fn impossible_tdp_memories(circuits: &[Circuit], configs: &PhysConfigs) -> Vec<(i32, i32)> {
+    let mut evaluated = 0;
+    circuits
+        .iter()
+        .flat_map(|c| c.memories.iter().map(move |mem| (c.id, mem)))
+        .filter(|(_, mem)| mem.mode == MemMode::TrueDualPort)
+        .filter(|(cid, mem)| {
+            configs.types().into_iter().all(|t| {
+                configs.get(t).is_none_or(|cfg| {
+                    best_mapping_for_phys_type(*cid, mem, 0, cfg, &mut evaluated).is_none()
+                })
+            })
+        })
+        .map(|(cid, mem)| (cid, mem.ram_id))
+        .collect()
+}
+
+// SplitMix64, enough randomness for --seed tie-breaking without an RNG dependency
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    // one stream per memory, so the draws don't depend on the order memories are mapped in
+    fn for_memory(seed: u64, circuit_id: i32, ram_id: i32) -> Self {
+        SplitMix64(seed ^ ((circuit_id as u32 as u64) << 32 | ram_id as u32 as u64))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in 0..n for n > 0 (the modulo bias is negligible for tie counts)
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+// relative cost difference under which two candidates count as tied for --seed
+const TIE_EPSILON: f64 = 1e-9;
+
+// memory mapper
+fn choose_mapping_for_memory(
+    circuit_id: i32,
+    mem: &Memory,
+    group_id: i32,
+    configs: &PhysConfigs,
+    hint: Option<&MappingHint>,
+    type_priority: &[PhysType],
+    lut_pressure: bool,
+    seed: Option<u64>,
+    evaluated: &mut u64,
+) -> Result<RamMapping, MappingError> {
+    if let Some(hint) = hint {
+        match mapping_from_hint(circuit_id, mem, group_id, configs, hint) {
+            Ok(m) => return Ok(m),
+            Err(reason) => eprintln!(
+                "Warning: ignoring hint for RAM {} in circuit {} ({}), searching instead",
+                mem.ram_id, circuit_id, reason
+            ),
+        }
+    }
+
+    let mut best_mapping: Option<RamMapping> = None;
+    let mut best_key = (i32::MIN, f64::INFINITY);
+    // shortest cascade rejected by a type's max_series and narrowest row rejected by its
+    // max_parallel, to explain a failure
+    let mut min_series: Option<i32> = None;
+    let mut min_parallel: Option<i32> = None;
+    let mut rng = seed.map(|s| SplitMix64::for_memory(s, circuit_id, mem.ram_id));
+    // candidates tied with the current best so far, for reservoir sampling under a seed
+    let mut ties = 0;
+
+    // Under LUT pressure the fewest extra LUTs win and cost only breaks ties, which favours
+    // shapes with more blocks and less series decoding. Otherwise the key is cost alone.
+    // strict < keeps the earlier type on a tie, so the priority order breaks ties. With a
+    // seed each of k tied candidates is kept with probability 1/k instead.
+    for &phys_type in type_priority {
+        let Some(cfg) = configs.get(phys_type) else {
+            continue;
+        };
+        let (over_series, over_parallel) =
+            visit_candidates(circuit_id, mem, group_id, cfg, evaluated, |m, cost| {
+                let key = (if lut_pressure { m.extra_luts } else { 0 }, cost);
+                let take = match &mut rng {
+                    None => best_mapping.is_none() || key < best_key,
+                    Some(rng) => {
+                        let tied = best_mapping.is_some()
+                            && key.0 == best_key.0
+                            && (key.1 - best_key.1).abs() <= TIE_EPSILON * best_key.1.abs();
+                        if tied {
+                            ties += 1;
+                            rng.below(ties) == 0
+                        } else if best_mapping.is_none() || key < best_key {
+                            ties = 1;
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                };
+                if take {
+                    best_key = key;
+                    best_mapping = Some(m);
+                }
+            });
+        if let Some(s) = over_series {
+            min_series = Some(min_series.map_or(s, |m: i32| m.min(s)));
+        }
+        if let Some(p) = over_parallel {
+            min_parallel = Some(min_parallel.map_or(p, |m: i32| m.min(p)));
+        }
+    }
+
+    best_mapping.ok_or(MappingError {
+        circuit_id,
+        ram_id: mem.ram_id,
+        mode: mem.mode,
+        depth: mem.depth,
+        width: mem.width,
+        min_series,
+        min_parallel,
+    })
+}
+
+// Cheapest split of mem's width into a power-of-two part on one type and the rest on
+// another, each part mapped as its own RAM of that width. Returns the parts and their summed
+// (extra LUTs under LUT pressure, cost) key.
+fn best_width_split(
+    circuit_id: i32,
+    mem: &Memory,
+    group_id: i32,
+    configs: &PhysConfigs,
+    type_priority: &[PhysType],
+    lut_pressure: bool,
+    evaluated: &mut u64,
+) -> Option<([RamMapping; 2], (i32, f64))> {
+    let mut best: Option<([RamMapping; 2], (i32, f64))> = None;
+    let mut low_width = 1;
+    while low_width < mem.width {
+        let low = Memory {
+            width: low_width,
+            ..*mem
+        };
+        let high = Memory {
+            width: mem.width - low_width,
+            ..*mem
+        };
+        // best shape of each part on every enabled type, in priority order
+        let mut per_type = Vec::new();
+        for &phys_type in type_priority {
+            if let Some(cfg) = configs.get(phys_type) {
+                per_type.push((
+                    best_mapping_for_phys_type(circuit_id, &low, group_id, cfg, evaluated),
+                    best_mapping_for_phys_type(circuit_id, &high, group_id, cfg, evaluated),
+                ));
+            }
+        }
+        for (a, (low_best, _)) in per_type.iter().enumerate() {
+            for (b, (_, high_best)) in per_type.iter().enumerate() {
+                if a == b {
+                    continue;
+                }
+                let (Some((low_m, low_cost)), Some((high_m, high_cost))) = (low_best, high_best)
+                else {
+                    continue;
+                };
+                let luts = if lut_pressure {
+                    low_m.extra_luts + high_m.extra_luts
+                } else {
+                    0
+                };
+                let key = (luts, low_cost + high_cost);
+                if best.as_ref().is_none_or(|(_, best_key)| key < *best_key) {
+                    best = Some(([low_m.clone(), high_m.clone()], key));
+                }
+            }
+        }
+        low_width *= 2;
+    }
+    best
+}
+
+// The memory's mapping: the single-type search, or under allow_lut_memory the LUT-only
+// realization and under allow_split the best width split when its key is strictly lower.
+// The LUT-only mapping is costed on the LUTRAM config, so it needs LUTRAM enabled. A hinted
+// RAM is always mapped as hinted.
+fn choose_mappings_for_memory(
+    circuit_id: i32,
+    mem: &Memory,
+    group_id: i32,
+    configs: &PhysConfigs,
+    opts: &MapOptions,
+    lut_pressure: bool,
+    evaluated: &mut u64,
+) -> Result<Vec<RamMapping>, MappingError> {
+    let hint = opts.hints.get(&(circuit_id, mem.ram_id));
+    let single = choose_mapping_for_memory(
+        circuit_id,
+        mem,
+        group_id,
+        configs,
+        hint,
+        &opts.type_priority,
+        lut_pressure,
+        opts.seed,
+        evaluated,
+    )?;
+    if !(opts.allow_split || opts.allow_lut_memory) || hint.is_some() {
+        return Ok(vec![single]);
+    }
+    let Some(cfg) = configs.get(single.phys_type) else {
+        return Ok(vec![single]);
+    };
+    let key_of = |m: &RamMapping, cost| (if lut_pressure { m.extra_luts } else { 0 }, cost);
+    let mut best_key = key_of(&single, mapping_cost(&single, cfg));
+    let mut best = vec![single];
+    if opts.allow_lut_memory
+        && let Some(lutram) = configs.get(PhysType::Lutram)
+        && let Some(m) = lut_memory_mapping(circuit_id, mem, group_id)
+    {
+        let key = key_of(&m, mapping_cost(&m, lutram));
+        if key < best_key {
+            best_key = key;
+            best = vec![m];
+        }
+    }
+    if opts.allow_split
+        && let Some((parts, key)) = best_width_split(
+            circuit_id,
+            mem,
+            group_id,
+            configs,
+            &opts.type_priority,
+            lut_pressure,
+            evaluated,
+        )
+        && key < best_key
+    {
+        best = parts.into();
+    }
+    Ok(best)
+}
+
+// one --stream-decisions line for a chosen mapping
+fn stream_decision(m: &RamMapping) {
+    eprintln!(
+        "Mapped circuit {} ram {}: type {} S {} P {}",
+        m.circuit_id,
+        m.logical_ram_id,
+        m.phys_type.name(),
+        m.series,
+        m.parallel
+    );
+}
+
+// Maps one circuit's memories. lut_start is the extra-LUT total of the circuits mapped
+// before it, which only matters under a LUT budget.
+fn map_circuit_memories(
+    c: &Circuit,
+    memories: &[Memory],
+    configs: &PhysConfigs,
+    opts: &MapOptions,
+    lut_start: i32,
+    evaluated: &mut u64,
+) -> Result<Vec<RamMapping>, MappingError> {
+    // A RAM's group id is its RAM id, so the parallel path hands out exactly the ids the
+    // serial loop would and the result is identical.
+    if opts.parallel_memories && opts.lut_budget.is_none() {
+        let results: Vec<Result<(Vec<RamMapping>, u64), MappingError>> = memories
+            .par_iter()
+            .map(|mem| {
+                let mut evaluated = 0;
+                choose_mappings_for_memory(
+                    c.id,
+                    mem,
+                    mem.ram_id,
+                    configs,
+                    opts,
+                    false,
+                    &mut evaluated,
+                )
+                .map(|m| (m, evaluated))
+            })
+            .collect();
+        // collected in order, so the error reported is the first failing RAM, as serially
+        return results
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .map(|results| {
+                results
+                    .into_iter()
+                    .flat_map(|(mappings, count)| {
+                        *evaluated += count;
+                        mappings
+                    })
+                    .inspect(|mapping| {
+                        if opts.stream_decisions {
+                            stream_decision(mapping);
+                        }
+                    })
+                    .collect()
+            });
+    }
+
+    let mut chosen = Vec::with_capacity(memories.len());
+    let mut running_luts = lut_start;
+    for mem in memories {
+        let mappings = choose_mappings_for_memory(
+            c.id,
+            mem,
+            mem.ram_id,
+            configs,
+            opts,
+            opts.lut_budget.is_some_and(|b| running_luts > b),
+            evaluated,
+        )?;
+        for mapping in mappings {
+            running_luts += mapping.extra_luts;
+            if opts.stream_decisions {
+                stream_decision(&mapping);
+            }
+            chosen.push(mapping);
+        }
+    }
+    Ok(chosen)
+}
+
+// --progress: circuits assign_ram has mapped so far, reported at most once a second and
+// after the last one. Bumped from the rayon workers, hence the atomics; only built when
+// the flag is on, so a normal run does no counting at all
+struct Progress {
+    total: usize,
+    done: AtomicUsize,
+    start: Instant,
+    // elapsed milliseconds at the last report
+    last_report_ms: AtomicU64,
+}
+
+impl Progress {
+    fn new(total: usize) -> Self {
+        Progress {
+            total,
+            done: AtomicUsize::new(0),
+            start: Instant::now(),
+            last_report_ms: AtomicU64::new(0),
+        }
+    }
+
+    fn circuit_done(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        let elapsed = self.start.elapsed();
+        let ms = elapsed.as_millis() as u64;
+        if done < self.total {
+            let last = self.last_report_ms.load(Ordering::Relaxed);
+            // the worker that wins the exchange reports for this second
+            if ms < last + 1000
+                || (self.last_report_ms)
+                    .compare_exchange(last, ms, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_err()
+            {
+                return;
+            }
+        }
+        let eta = elapsed.as_secs_f64() * (self.total - done) as f64 / done as f64;
+        eprintln!(
+            "Mapped {}/{} circuits ({:.0}%), {:.1}s elapsed, ETA {:.1}s",
+            done,
+            self.total,
+            100.0 * done as f64 / self.total as f64,
+            elapsed.as_secs_f64(),
+            eta
+        );
+    }
+}
+
+pub fn assign_ram(
+    circuits: &[Circuit],
+    configs: &PhysConfigs,
+    opts: &MapOptions,
+) -> Result<CircuitResult, RapidMapError> {
+    let mut result = CircuitResult {
+        mappings: Vec::new(),
+        extra_luts: 0,
+        lutram_blocks: 0,
+        m8k_blocks: 0,
+        m128k_blocks: 0,
+        extra_blocks: vec![0; configs.extra.len()],
+        candidates_evaluated: 0,
+        skipped: Vec::new(),
+    };
+
+    let memory_count = |c: &Circuit| {
+        opts.memory_limit
+            .map_or(c.memories.len(), |limit| limit.min(c.memories.len()))
+    };
+    let progress = opts.progress.then(|| Progress::new(circuits.len()));
+
+    // Circuits are independent until sharing, so they are mapped on the rayon pool and
+    // merged back in input order. A LUT budget biases each memory by the running total of
+    // every memory before it, so that case stays serial.
+    let per_circuit: Vec<(Result<Vec<RamMapping>, MappingError>, u64)> =
+        if opts.lut_budget.is_some() {
+            let mut lut_start = 0;
+            circuits
+                .iter()
+                .map(|c| {
+                    let mut evaluated = 0;
+                    let chosen = map_circuit_memories(
+                        c,
+                        &c.memories[..memory_count(c)],
+                        configs,
+                        opts,
+                        lut_start,
+                        &mut evaluated,
+                    );
+                    if let Ok(chosen) = &chosen {
+                        lut_start += chosen.iter().map(|m| m.extra_luts).sum::<i32>();
+                    }
+                    if let Some(progress) = &progress {
+                        progress.circuit_done();
+                    }
+                    (chosen, evaluated)
+                })
+                .collect()
+        } else {
+            circuits
+                .par_iter()
+                .map(|c| {
+                    let mut evaluated = 0;
+                    let chosen = map_circuit_memories(
+                        c,
+                        &c.memories[..memory_count(c)],
+                        configs,
+                        opts,
+                        0,
+                        &mut evaluated,
+                    );
+                    if let Some(progress) = &progress {
+                        progress.circuit_done();
+                    }
+                    (chosen, evaluated)
+                })
+                .collect()
+        };
+
+    for (chosen, evaluated) in per_circuit {
+        result.candidates_evaluated += evaluated;
+        let chosen = match chosen {
+            Ok(chosen) => chosen,
+            Err(e) if opts.strict => return Err(e.into()),
+            Err(e) => {
+                result.skipped.push(e);
+                continue;
+            }
+        };
+
+        for mapping in chosen {
+            adjust_totals(&mut result, &mapping, 1);
+            result.mappings.push(mapping);
+        }
+    }
+
+    // sharing uses dynamic configs
+    apply_sharing(&mut result, configs, opts.sharing_iterations, &opts.sharing);
+    validate_shared_groups(&result.mappings)?;
+    debug_assert_blocks_consistent(&result.mappings);
+
+    Ok(result)
+}
+/// Maps every logical RAM of `circuits` onto the physical types enabled in `arch`, then
+/// shares BRAMs within each circuit. Nothing is read or written; an unmappable RAM is an error.
+///
+/// ```
+/// use rapid_map::{ArchConfig, Circuit, MemMode, Memory, map_circuits};
+///
+/// let circuit = Circuit {
+///     id: 0,
+///     logic_blocks: 500,
+///     memories: vec![
+///         Memory { ram_id: 0, mode: MemMode::SinglePort, depth: 1024, width: 16 },
+///         Memory { ram_id: 1, mode: MemMode::TrueDualPort, depth: 4096, width: 32 },
+///     ],
+/// };
+/// let result = map_circuits(&[circuit], &ArchConfig::default()).unwrap();
+/// assert_eq!(result.mappings.len(), 2);
+/// assert!(result.mappings.iter().all(|m| m.phys_blocks > 0));
+/// ```
+pub fn map_circuits(
+    circuits: &[Circuit],
+    arch: &ArchConfig,
+) -> Result<CircuitResult, RapidMapError> {
+    let configs = arch.phys_configs();
+    let opts = MapOptions {
+        strict: true,
+        type_priority: configs.types(),
+        ..MapOptions::default()
+    };
+    assign_ram(circuits, &configs, &opts)
+}
+
+/// Re-maps only the circuits in `changed_ids`, plus any that `prev` has no record of, and keeps
+/// `prev`'s mappings for the rest. `prev` must come from [`map_circuits`] (or this function)
+/// with the same `arch`, and `circuits` is the full current list in mapping order; circuits
+/// no longer in it are dropped. Sharing never crosses circuits and group ids are the
+/// RAM ids, so the result is what [`map_circuits`] returns for `circuits`,
+/// except that `candidates_evaluated` counts only the re-mapped circuits.
+///
+/// ```
+/// use rapid_map::{ArchConfig, Circuit, MemMode, Memory, map_circuits, map_circuits_incremental};
+///
+/// let circuit = |id, rams: &[(MemMode, i32, i32)]| Circuit {
+///     id,
+///     logic_blocks: 500,
+///     memories: (rams.iter().enumerate())
+///         .map(|(i, &(mode, depth, width))| Memory { ram_id: i as i32, mode, depth, width })
+///         .collect(),
+/// };
+/// let arch = ArchConfig::default();
+/// let before = vec![
+///     circuit(0, &[(MemMode::SinglePort, 1024, 16)]),
+///     circuit(1, &[(MemMode::Rom, 256, 16), (MemMode::Rom, 256, 16)]),
+/// ];
+/// let prev = map_circuits(&before, &arch).unwrap();
+///
+/// // circuit 0 gains a RAM
+/// let after = vec![
+///     circuit(0, &[(MemMode::SinglePort, 1024, 16), (MemMode::TrueDualPort, 4096, 32)]),
+///     circuit(1, &[(MemMode::Rom, 256, 16), (MemMode::Rom, 256, 16)]),
+/// ];
+/// let incremental = map_circuits_incremental(&prev, &after, &[0], &arch).unwrap();
+/// let full = map_circuits(&after, &arch).unwrap();
+/// assert_eq!(incremental.mappings, full.mappings);
+/// assert_eq!(incremental.extra_luts, full.extra_luts);
+/// assert_eq!(incremental.m8k_blocks, full.m8k_blocks);
+/// assert_eq!(incremental.m128k_blocks, full.m128k_blocks);
+/// assert!(incremental.candidates_evaluated < full.candidates_evaluated);
+/// ```
+pub fn map_circuits_incremental(
+    prev: &CircuitResult,
+    circuits: &[Circuit],
+    changed_ids: &[i32],
+    arch: &ArchConfig,
+) -> Result<CircuitResult, RapidMapError> {
+    let mut kept: HashMap<i32, Vec<&RamMapping>> = HashMap::new();
+    for m in &prev.mappings {
+        kept.entry(m.circuit_id).or_default().push(m);
+    }
+    let prev_skipped: HashSet<i32> = prev.skipped.iter().map(|e| e.circuit_id).collect();
+    let remap = |c: &Circuit| {
+        changed_ids.contains(&c.id) || !(kept.contains_key(&c.id) || prev_skipped.contains(&c.id))
+    };
+
+    let changed: Vec<Circuit> = circuits.iter().filter(|c| remap(c)).cloned().collect();
+    let fresh = map_circuits(&changed, arch)?;
+    let mut remapped: HashMap<i32, Vec<RamMapping>> = HashMap::new();
+    for m in fresh.mappings {
+        remapped.entry(m.circuit_id).or_default().push(m);
+    }
+
+    let mut result = CircuitResult {
+        mappings: Vec::new(),
+        extra_luts: 0,
+        lutram_blocks: 0,
+        m8k_blocks: 0,
+        m128k_blocks: 0,
+        extra_blocks: vec![0; arch.extra_rams.len()],
+        candidates_evaluated: fresh.candidates_evaluated,
+        skipped: Vec::new(),
+    };
+    // group ids are the RAM ids, so both sides keep theirs
+    for c in circuits {
+        if remap(c) {
+            result
+                .mappings
+                .extend(remapped.remove(&c.id).unwrap_or_default());
+        } else {
+            result.skipped.extend(
+                prev.skipped
+                    .iter()
+                    .filter(|e| e.circuit_id == c.id)
+                    .cloned(),
+            );
+            if let Some(ms) = kept.get(&c.id) {
+                result.mappings.extend(ms.iter().map(|&m| m.clone()));
+            }
+        }
+    }
+
+    // a shared block and the members of its group are counted once
+    let mut counted = HashSet::new();
+    let mappings = std::mem::take(&mut result.mappings);
+    for m in &mappings {
+        result.extra_luts += m.extra_luts;
+        if counted.insert((m.circuit_id, m.group_id, m.phys_type)) {
+            *result.blocks_mut(m.phys_type) += m.phys_blocks;
+        }
+    }
+    result.mappings = mappings;
+    validate_shared_groups(&result.mappings)?;
+    debug_assert_blocks_consistent(&result.mappings);
+    Ok(result)
+}
+
+// adds (sign = 1) or removes (sign = -1) a mapping's blocks and extra LUTs from the totals
+fn adjust_totals(result: &mut CircuitResult, m: &RamMapping, sign: i32) {
+    result.extra_luts += sign * m.extra_luts;
+    *result.blocks_mut(m.phys_type) += sign * m.phys_blocks;
+}
+
+// Optional second pass minimizing alpha * total_area + beta * total_extra_luts at chip scale.
+// This is a heuristic: each unshared mapping is visited once, in mapping order, and switched
+// to the best mapping of another enabled type if that lowers the global objective given all
+// other choices. Earlier switches are not revisited. Returns the number of switched mappings.
+pub fn global_objective_pass(
+    circuits: &[Circuit],
+    result: &mut CircuitResult,
+    configs: &PhysConfigs,
+    alpha: f64,
+    beta: f64,
+    total_area: impl Fn(&CircuitResult) -> f64,
+) -> usize {
+    let memories: HashMap<(i32, i32), &Memory> = circuits
+        .iter()
+        .flat_map(|c| c.memories.iter().map(move |mem| ((c.id, mem.ram_id), mem)))
+        .collect();
+    // group ids are only unique within a circuit
+    let mut group_sizes: HashMap<(i32, i32), usize> = HashMap::new();
+    for m in &result.mappings {
+        *group_sizes.entry((m.circuit_id, m.group_id)).or_insert(0) += 1;
+    }
+
+    let objective = |r: &CircuitResult| alpha * total_area(r) + beta * (r.extra_luts as f64);
+    let mut current = objective(result);
+    let mut flips = 0;
+
+    for idx in 0..result.mappings.len() {
+        let old = result.mappings[idx].clone();
+        // shared blocks are left alone, switching one member would break the group
+        if (group_sizes.get(&(old.circuit_id, old.group_id)).copied()).unwrap_or(0) > 1 {
+            continue;
+        }
+        let Some(mem) = memories.get(&(old.circuit_id, old.logical_ram_id)) else {
+            continue;
+        };
+
+        for phys_type in configs.types() {
+            if phys_type == result.mappings[idx].phys_type {
+                continue;
+            }
+            let Some(cfg) = configs.get(phys_type) else {
+                continue;
+            };
+            let Some((candidate, _)) = best_mapping_for_phys_type(
+                old.circuit_id,
+                mem,
+                old.group_id,
+                cfg,
+                &mut result.candidates_evaluated,
+            ) else {
+                continue;
+            };
+
+            let previous = result.mappings[idx].clone();
+            adjust_totals(result, &previous, -1);
+            adjust_totals(result, &candidate, 1);
+            let value = objective(result);
+            if value < current {
+                current = value;
+                result.mappings[idx] = candidate;
+            } else {
+                adjust_totals(result, &candidate, -1);
+                adjust_totals(result, &previous, 1);
+            }
+        }
+
+        if result.mappings[idx].phys_type != old.phys_type {
+            flips += 1;
+        }
+    }
+    debug_assert_blocks_consistent(&result.mappings);
+
+    flips
+}
+
+// --optimize: global_objective_pass on total area alone, repeated until a pass switches
+// nothing, max_passes passes have run, or the deadline has passed. Every kept switch lowers
+// the area, so this terminates. The deadline is only checked between passes; a run that
+// stops on it may differ between machines, one that stops otherwise will not.
+// Returns (passes run, mappings switched).
+pub fn local_search(
+    circuits: &[Circuit],
+    result: &mut CircuitResult,
+    configs: &PhysConfigs,
+    max_passes: usize,
+    deadline: Option<Instant>,
+    total_area: impl Fn(&CircuitResult) -> f64,
+) -> (usize, usize) {
+    let mut passes = 0;
+    let mut switched = 0;
+    while passes < max_passes && deadline.is_none_or(|d| Instant::now() < d) {
+        let flips = global_objective_pass(circuits, result, configs, 1.0, 0.0, &total_area);
+        passes += 1;
+        switched += flips;
+        if flips == 0 {
+            break;
+        }
+    }
+    (passes, switched)
+}
+
+// Optional post-pass moving memories off a BRAM type that exceeds its block budget.
+// Greedy: while a type is over budget, take the unshared mapping on it whose best mapping on
+// another enabled BRAM type adds the least cost per freed block, provided the new cost is
+// within budgets.max_cost_ratio of the old one and the target stays within its own budget.
+// Types are relieved in PhysConfigs::all_types order, and moves are never undone, so the
+// result need not be the cheapest balanced assignment. LUTRAM is neither relieved nor a
+// target. Returns the number of moved mappings.
+pub fn balance_types(
+    circuits: &[Circuit],
+    result: &mut CircuitResult,
+    configs: &PhysConfigs,
+    budgets: &BlockBudgets,
+) -> usize {
+    let memories: HashMap<(i32, i32), &Memory> = circuits
+        .iter()
+        .flat_map(|c| c.memories.iter().map(move |mem| ((c.id, mem.ram_id), mem)))
+        .collect();
+    // group ids are only unique within a circuit
+    let mut group_sizes: HashMap<(i32, i32), usize> = HashMap::new();
+    for m in &result.mappings {
+        *group_sizes.entry((m.circuit_id, m.group_id)).or_insert(0) += 1;
+    }
+    let used = |r: &CircuitResult, t: PhysType| r.blocks(t);
+
+    let brams: Vec<PhysType> = (configs.types().into_iter())
+        .filter(|&t| t != PhysType::Lutram)
+        .collect();
+
+    let mut moves = 0;
+    for &from in &brams {
+        let (Some(budget), Some(from_cfg)) = (budgets.get(from), configs.get(from)) else {
+            continue;
+        };
+
+        while used(result, from) > budget {
+            let mut best: Option<(usize, RamMapping, f64)> = None;
+            for (idx, old) in result.mappings.iter().enumerate() {
+                if old.phys_type != from || group_sizes[&(old.circuit_id, old.group_id)] > 1 {
+                    continue;
+                }
+                let Some(mem) = memories.get(&(old.circuit_id, old.logical_ram_id)) else {
+                    continue;
+                };
+                let old_cost = mapping_cost(old, from_cfg);
+                for &to in brams.iter().filter(|&&t| t != from) {
+                    let Some(to_cfg) = configs.get(to) else {
+                        continue;
+                    };
+                    let Some((candidate, new_cost)) = best_mapping_for_phys_type(
+                        old.circuit_id,
+                        mem,
+                        old.group_id,
+                        to_cfg,
+                        &mut result.candidates_evaluated,
+                    ) else {
+                        continue;
+                    };
+                    let headroom = budgets.get(to).map(|b| b - used(result, to));
+                    if headroom.is_some_and(|h| candidate.phys_blocks > h) {
+                        continue;
+                    }
+                    if new_cost > old_cost * budgets.max_cost_ratio {
+                        continue;
+                    }
+                    let penalty = (new_cost - old_cost) / old.phys_blocks as f64;
+                    if best.as_ref().is_none_or(|(_, _, p)| penalty < *p) {
+                        best = Some((idx, candidate, penalty));
+                    }
+                }
+            }
+
+            let Some((idx, candidate, _)) = best else {
+                break;
+            };
+            let previous = std::mem::replace(&mut result.mappings[idx], candidate.clone());
+            adjust_totals(result, &previous, -1);
+            adjust_totals(result, &candidate, 1);
+            moves += 1;
+        }
+    }
+    debug_assert_blocks_consistent(&result.mappings);
+
+    moves
+}
+
+// Cost-model constants perturbed by --sensitivity
+#[derive(Clone, Copy, Debug)]
+pub enum CostConstant {
+    PenaltyStrength(PhysType),
+    PenaltyBase,
+    LbArea,
+    BramBase(PhysType),
+    BramPerBit(PhysType),
+    BramPerSqrtBit(PhysType),
+    BramPerWidth(PhysType),
+}
+
+impl CostConstant {
+    // a penalty strength per type, the two constants every type shares, then the area
+    // coefficients of each BRAM type
+    fn all(types: &[PhysType]) -> Vec<CostConstant> {
+        let mut constants: Vec<CostConstant> = types
+            .iter()
+            .map(|&t| CostConstant::PenaltyStrength(t))
+            .collect();
+        constants.extend([CostConstant::PenaltyBase, CostConstant::LbArea]);
+        for &t in types.iter().filter(|&&t| t != PhysType::Lutram) {
+            constants.extend([
+                CostConstant::BramBase(t),
+                CostConstant::BramPerBit(t),
+                CostConstant::BramPerSqrtBit(t),
+                CostConstant::BramPerWidth(t),
+            ]);
+        }
+        constants
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            CostConstant::PenaltyStrength(t) => format!("penalty_strength.{}", t.name()),
+            CostConstant::PenaltyBase => "penalty_base".to_string(),
+            CostConstant::LbArea => "lb_area".to_string(),
+            CostConstant::BramBase(t) => format!("bram_area.base.{}", t.name()),
+            CostConstant::BramPerBit(t) => format!("bram_area.per_bit.{}", t.name()),
+            CostConstant::BramPerSqrtBit(t) => format!("bram_area.per_sqrt_bit.{}", t.name()),
+            CostConstant::BramPerWidth(t) => format!("bram_area.per_width.{}", t.name()),
+        }
+    }
+
+    // the constant inside a type's cost model; None if it doesn't belong to that type
+    fn field(self, cost: &mut CostModel, phys_type: PhysType) -> Option<&mut f64> {
+        match self {
+            CostConstant::PenaltyStrength(t) if t == phys_type => Some(&mut cost.penalty_strength),
+            CostConstant::PenaltyBase => Some(&mut cost.penalty_base),
+            CostConstant::LbArea => Some(&mut cost.lb_area),
+            CostConstant::BramBase(t) if t == phys_type => Some(&mut cost.bram_area.base),
+            CostConstant::BramPerBit(t) if t == phys_type => Some(&mut cost.bram_area.per_bit),
+            CostConstant::BramPerSqrtBit(t) if t == phys_type => {
+                Some(&mut cost.bram_area.per_sqrt_bit)
+            }
+            CostConstant::BramPerWidth(t) if t == phys_type => Some(&mut cost.bram_area.per_width),
+            _ => None,
+        }
+    }
+}
+
+pub struct Sensitivity {
+    pub constant: CostConstant,
+    pub value: f64,
+    pub geomean: f64,
+    // d(geomean) / d(constant), forward difference
+    pub gradient: f64,
+}
+
+// Re-maps with each cost constant raised by rel_step of its value (or by rel_step itself when
+// it is 0) and reports how the in-memory geomean moves. lb_area and the 8K and 128K BRAM
+// coefficients also change the geomean's area model; geom_blocks are the (bits, max_width,
+// area model) its 8K and 128K block areas are computed from.
+// Constants of disabled types are skipped.
+pub fn cost_sensitivity(
+    circuits: &[Circuit],
+    configs: &PhysConfigs,
+    opts: &MapOptions,
+    geom_params: &GeomeanParams,
+    geom_blocks: [(i32, i32, BramAreaModel); 2],
+    rel_step: f64,
+) -> Result<(f64, Vec<Sensitivity>), RapidMapError> {
+    let baseline = compute_geometric_area_in_memory(
+        circuits,
+        &assign_ram(circuits, configs, opts)?,
+        geom_params,
+    );
+    let mut report = Vec::new();
+    for constant in CostConstant::all(&configs.all_types()) {
+        let mut perturbed = configs.clone();
+        let Some(value) = (configs.all_types().into_iter()).find_map(|t| {
+            let mut cost = configs.get(t)?.cost;
+            constant.field(&mut cost, t).map(|field| *field)
+        }) else {
+            continue;
+        };
+        // a relative step would leave a zero constant where it is
+        let step = if value == 0.0 {
+            rel_step
+        } else {
+            value.abs() * rel_step
+        };
+        for t in configs.all_types() {
+            if let Some(cfg) = perturbed.get_mut(t)
+                && let Some(field) = constant.field(&mut cfg.cost, t)
+            {
+                *field += step;
+            }
+        }
+
+        // the geomean's own area model, moved the same way as the types' cost models
+        let stepped = |bram_area: BramAreaModel, phys_type: PhysType| {
+            let mut cost = CostModel {
+                bram_area,
+                ..CostModel::for_type(phys_type)
+            };
+            if let Some(field) = constant.field(&mut cost, phys_type) {
+                *field += step;
+            }
+            cost
+        };
+        let [
+            (bits_8k, width_8k, model_8k),
+            (bits_128k, width_128k, model_128k),
+        ] = geom_blocks;
+        let cost_8k = stepped(model_8k, PhysType::Ram8K);
+        let cost_128k = stepped(model_128k, PhysType::Ram128K);
+        let params = GeomeanParams {
+            area_8k_block: cost_8k.bram_area.area(bits_8k, width_8k),
+            area_128k_block: cost_128k.bram_area.area(bits_128k, width_128k),
+            lb_area: cost_8k.lb_area,
+            ..geom_params.clone()
+        };
+
+        let result = assign_ram(circuits, &perturbed, opts)?;
+        let geomean = compute_geometric_area_in_memory(circuits, &result, &params);
+        report.push(Sensitivity {
+            constant,
+            value,
+            geomean,
+            gradient: (geomean - baseline) / step,
+        });
+    }
+    Ok((baseline, report))
+}
+
+pub struct Ablation {
+    pub phys_type: PhysType,
+    // Err when the remaining types can't hold every RAM
+    pub geomean: Result<f64, RapidMapError>,
+}
+
+// Re-maps with each enabled type removed in turn and reports the in-memory geomean without
+// it. Only assign_ram is re-run, so no optional passes.
+pub fn type_ablation(
+    circuits: &[Circuit],
+    configs: &PhysConfigs,
+    opts: &MapOptions,
+    geom_params: &GeomeanParams,
+) -> Result<(f64, Vec<Ablation>), RapidMapError> {
+    let baseline = compute_geometric_area_in_memory(
+        circuits,
+        &assign_ram(circuits, configs, opts)?,
+        geom_params,
+    );
+    let mut report = Vec::new();
+    for t in configs.types() {
+        let mut reduced = configs.clone();
+        reduced.disable(t);
+        let geomean = assign_ram(circuits, &reduced, opts)
+            .map(|r| compute_geometric_area_in_memory(circuits, &r, geom_params));
+        report.push(Ablation {
+            phys_type: t,
+            geomean,
+        });
+    }
+    Ok((baseline, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::AreaModel;
+
+    fn mem(ram_id: i32, mode: MemMode, depth: i32, width: i32) -> Memory {
+        Memory {
+            ram_id,
+            mode,
+            depth,
+            width,
+        }
+    }
+
+    fn circuit(id: i32, logic_blocks: i32, memories: Vec<Memory>) -> Circuit {
+        Circuit {
+            id,
+            logic_blocks,
+            memories,
+        }
+    }
+
+    // mem mapped on cfg with the given shape, in its own group
+    fn mapped(
+        circuit_id: i32,
+        mem: &Memory,
+        cfg: &PhysConfig,
+        w: i32,
+        d: i32,
+        s: i32,
+        p: i32,
+    ) -> RamMapping {
+        build_mapping(circuit_id, mem, mem.ram_id, cfg, w, d, s, p)
+    }
+
+    fn result_of(mappings: Vec<RamMapping>) -> CircuitResult {
+        CircuitResult {
+            mappings,
+            extra_luts: 0,
+            lutram_blocks: 0,
+            m8k_blocks: 0,
+            m128k_blocks: 0,
+            extra_blocks: Vec::new(),
+            candidates_evaluated: 0,
+            skipped: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn cross_circuit_report_pairs_complementary_roms() {
+        let configs = ArchConfig::default().phys_configs();
+        let rom = mem(0, MemMode::Rom, 512, 8);
+        let result = result_of(vec![
+            mapped(0, &rom, &PHYS_RAM1, 8, 1024, 1, 1),
+            mapped(1, &rom, &PHYS_RAM1, 8, 1024, 1, 1),
+        ]);
+
+        let pairs = cross_circuit_share_report(&result, &configs, &SharingConfig::default());
+        assert_eq!(pairs.len(), 1);
+        let (a, b) = pairs[0];
+        assert_eq!((a.circuit_id, b.circuit_id), (0, 1));
+        assert_eq!(a.logical_ram_id, b.logical_ram_id);
+    }
+
+    #[test]
+    fn global_objective_flips_a_greedy_choice() {
+        let arch = ArchConfig::default();
+        let configs = arch.phys_configs();
+        // deep and narrow: greedy stacks four M8Ks behind a decoder rather than pay for an M128K
+        let circuits = [circuit(
+            0,
+            100,
+            vec![
+                mem(0, MemMode::SinglePort, 16384, 1),
+                mem(1, MemMode::SinglePort, 256, 32),
+            ],
+        )];
+        let mut result = map_circuits(&circuits, &arch).unwrap();
+        assert_eq!(result.mappings[0].phys_type, PhysType::Ram8K);
+        assert!(result.mappings[0].extra_luts > 0);
+        let greedy_second = result.mappings[1].clone();
+
+        // weighting only extra LUTs moves it to one M128K, which needs none
+        let flips = global_objective_pass(&circuits, &mut result, &configs, 0.0, 1.0, |_| 0.0);
+        assert_eq!(flips, 1);
+        assert_eq!(result.mappings[0].phys_type, PhysType::Ram128K);
+        assert_eq!(result.extra_luts, 0);
+        assert_eq!(result.mappings[1], greedy_second);
+        assert_eq!(
+            (result.m8k_blocks, result.m128k_blocks),
+            (greedy_second.phys_blocks, 1)
+        );
+    }
+
+    #[test]
+    fn candidates_evaluated_scales_with_width_candidates() {
+        let wide = mem(0, MemMode::SinglePort, 512, 64);
+        let mut configs = ArchConfig::default().phys_configs();
+        let result = assign_ram(
+            &[circuit(0, 100, vec![wide.clone()])],
+            &configs,
+            &MapOptions::default(),
+        );
+        assert!(result.unwrap().candidates_evaluated > 0);
+
+        let mut evaluated_with = |widths: &[i32]| {
+            let cfg = configs.get_mut(PhysType::Ram8K).unwrap();
+            cfg.width_candidates = widths.to_vec();
+            let mut evaluated = 0;
+            best_mapping_for_phys_type(0, &wide, 0, cfg, &mut evaluated).unwrap();
+            evaluated
+        };
+        assert_eq!(evaluated_with(&[8, 16, 32]), 3);
+        assert_eq!(evaluated_with(&[1, 2, 4, 8, 16, 32]), 6);
+    }
+
+    #[test]
+    fn numeric_and_named_modes_parse_alike() {
+        let named = parse_ram_line("3 7 SimpleDualPort 45 12").unwrap();
+        let coded = parse_ram_line("3 7 2 45 12").unwrap();
+        assert_eq!(coded.1.mode, MemMode::SimpleDualPort);
+        assert_eq!(coded, named);
+        assert!(parse_ram_line("3 7 4 45 12").is_err());
+    }
+
+    #[test]
+    fn memory_limit_maps_the_first_memories_only() {
+        let memories = (0..5)
+            .map(|id| mem(id, MemMode::SinglePort, 1024, 8))
+            .collect();
+        let circuits = [
+            circuit(0, 100, memories),
+            circuit(1, 100, vec![mem(0, MemMode::Rom, 64, 4)]),
+        ];
+        let opts = MapOptions {
+            memory_limit: Some(2),
+            ..MapOptions::default()
+        };
+        let result = assign_ram(&circuits, &ArchConfig::default().phys_configs(), &opts).unwrap();
+        let ids = |circuit_id| {
+            (result.mappings.iter())
+                .filter(|m| m.circuit_id == circuit_id)
+                .map(|m| m.logical_ram_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(0), [0, 1]);
+        assert_eq!(ids(1), [0]);
+    }
+
+    #[test]
+    fn explicit_shapes_override_derived_depth() {
+        // parity widths lose depth: 9 x 1024 is 9216 bits, more than bits / 9 allows
+        let cfg = PhysConfig {
+            shapes: Cow::Owned(vec![(9, 1024), (18, 512)]),
+            ..PHYS_RAM1.clone()
+        };
+        let rom = mem(0, MemMode::Rom, 1024, 9);
+        let (best, _) = best_mapping_for_phys_type(0, &rom, 0, &cfg, &mut 0).unwrap();
+        assert_eq!((best.phys_width, best.phys_depth), (9, 1024));
+        assert_ne!(best.phys_depth, cfg.bits / 9);
+        assert_eq!(best.phys_blocks, 1);
+    }
+
+    #[test]
+    fn sharing_iterations_stop_once_a_pass_merges_nothing() {
+        let configs = ArchConfig::default().phys_configs();
+        // four half-block ROMs pair up into two blocks on the first pass
+        let roms = (0..4).map(|id| mem(id, MemMode::Rom, 512, 8)).collect();
+        let circuits = [circuit(0, 100, roms)];
+        let map = |sharing_iterations| {
+            let opts = MapOptions {
+                sharing_iterations,
+                ..MapOptions::default()
+            };
+            assign_ram(&circuits, &configs, &opts).unwrap()
+        };
+
+        assert_eq!(map(0).m8k_blocks, 4);
+        let once = map(1);
+        assert_eq!(once.m8k_blocks, 2);
+        // shared members are TrueDualPort afterwards, so a second pass has nothing to merge
+        let repeated = map(100);
+        assert_eq!(repeated.mappings, once.mappings);
+        assert_eq!(repeated.m8k_blocks, 2);
+    }
+
+    #[test]
+    fn complementary_bits_without_the_depth_stay_separate() {
+        // 600x8 + 848x4 fill the 8192 bits exactly, but stacked they need 1448 of 1024 rows
+        let a = mem(0, MemMode::Rom, 600, 8);
+        let b = mem(1, MemMode::Rom, 848, 4);
+        let mut mappings = vec![
+            mapped(0, &a, &PHYS_RAM1, 8, 1024, 1, 1),
+            mapped(0, &b, &PHYS_RAM1, 8, 1024, 1, 1),
+        ];
+        let mut blocks = 2;
+        let freed = share_type(
+            &mut mappings,
+            &PHYS_RAM1,
+            &SharingConfig::default(),
+            &mut blocks,
+        );
+        assert_eq!((freed, blocks), (0, 2));
+        assert_ne!(mappings[0].group_id, mappings[1].group_id);
+
+        // forced into one group anyway, the post-sharing check rejects it
+        mappings[1].group_id = mappings[0].group_id;
+        assert!(matches!(
+            validate_shared_groups(&mappings),
+            Err(RapidMapError::InvalidSharing {
+                stacked_depth: 1448,
+                phys_depth: 1024,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn legal_hints_skip_the_search_and_illegal_ones_fall_back() {
+        let configs = ArchConfig::default().phys_configs();
+        let circuits = [circuit(
+            0,
+            100,
+            vec![
+                mem(0, MemMode::SinglePort, 1024, 8),
+                mem(1, MemMode::SinglePort, 1024, 8),
+            ],
+        )];
+        let searched = assign_ram(&circuits, &configs, &MapOptions::default()).unwrap();
+        assert_eq!(searched.mappings[0].phys_type, PhysType::Ram8K);
+
+        let hint = |phys_width| MappingHint {
+            phys_type: PhysType::Ram128K,
+            phys_width,
+            series: 1,
+            parallel: 1,
+        };
+        let hinted = |hints: &[(i32, MappingHint)]| {
+            let opts = MapOptions {
+                hints: hints.iter().map(|&(ram, h)| ((0, ram), h)).collect(),
+                sharing_iterations: 0,
+                ..MapOptions::default()
+            };
+            assign_ram(&circuits, &configs, &opts).unwrap()
+        };
+
+        // both RAMs hinted: nothing is searched
+        let result = hinted(&[(0, hint(8)), (1, hint(8))]);
+        assert_eq!(result.candidates_evaluated, 0);
+        assert!(
+            result
+                .mappings
+                .iter()
+                .all(|m| m.phys_type == PhysType::Ram128K)
+        );
+
+        // 256 is wider than an M128K port, so RAM 0 is searched as if unhinted
+        let result = hinted(&[(0, hint(256)), (1, hint(8))]);
+        assert!(result.candidates_evaluated > 0);
+        assert_eq!(result.mappings[0], searched.mappings[0]);
+        assert_eq!(result.mappings[1].phys_type, PhysType::Ram128K);
+    }
+
+    #[test]
+    fn type_priority_breaks_cost_ties() {
+        // an M8K shaped like a LUTRAM block, whose block area is one LB: every shape costs
+        // the same on both
+        let lb_sized = BramAreaModel {
+            base: AVG_LB_AREA,
+            per_bit: 0.0,
+            per_sqrt_bit: 0.0,
+            per_width: 0.0,
+        };
+        let configs = PhysConfigs {
+            lutram: Some(PHYS_LUTRAM),
+            m8k: Some(PhysConfig {
+                phys_type: PhysType::Ram8K,
+                cost: CostModel {
+                    bram_area: lb_sized,
+                    ..PHYS_LUTRAM.cost
+                },
+                ..PHYS_LUTRAM
+            }),
+            m128k: None,
+            extra: Vec::new(),
+        };
+        let circuits = [circuit(0, 100, vec![mem(0, MemMode::SinglePort, 64, 10)])];
+        let chosen = |type_priority: Vec<PhysType>| {
+            let opts = MapOptions {
+                type_priority,
+                ..MapOptions::default()
+            };
+            assign_ram(&circuits, &configs, &opts).unwrap().mappings[0].phys_type
+        };
+
+        let costs = candidate_costs(0, &circuits[0].memories[0], &configs, &PhysType::ALL);
+        let best = |t| {
+            (costs.iter().filter(|(m, _)| m.phys_type == t))
+                .map(|&(_, cost)| cost)
+                .fold(f64::INFINITY, f64::min)
+        };
+        assert_eq!(best(PhysType::Lutram), best(PhysType::Ram8K));
+
+        assert_eq!(chosen(PhysType::ALL.to_vec()), PhysType::Lutram);
+        assert_eq!(
+            chosen(vec![PhysType::Ram8K, PhysType::Lutram, PhysType::Ram128K]),
+            PhysType::Ram8K
+        );
+    }
+
+    #[test]
+    fn diverging_block_count_is_caught() {
+        let ram = mem(4, MemMode::SinglePort, 2048, 16);
+        let mut m = mapped(2, &ram, &PHYS_RAM1, 8, 1024, 2, 2);
+        assert_eq!(m.validate(), Ok(()));
+        m.parallel = 1;
+        assert_eq!(
+            m.validate(),
+            Err("phys_blocks 4 != series 2 * parallel 1 for RAM 4 in circuit 2".into())
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "phys_blocks 4 != series 2 * parallel 1")]
+    fn debug_builds_panic_on_a_diverging_block_count() {
+        let ram = mem(4, MemMode::SinglePort, 2048, 16);
+        let mut m = mapped(2, &ram, &PHYS_RAM1, 8, 1024, 2, 2);
+        m.parallel = 1;
+        debug_assert_blocks_consistent(&[m]);
+    }
+
+    #[test]
+    fn forbidden_mode_moves_a_rom_off_its_type() {
+        let mut configs = ArchConfig::default().phys_configs();
+        let circuits = [circuit(0, 100, vec![mem(0, MemMode::Rom, 16384, 8)])];
+        let phys_type = |configs: &PhysConfigs| {
+            let result = assign_ram(&circuits, configs, &MapOptions::default()).unwrap();
+            result.mappings[0].phys_type
+        };
+        assert_eq!(phys_type(&configs), PhysType::Ram128K);
+
+        let m128k = configs.get_mut(PhysType::Ram128K).unwrap();
+        m128k.forbidden_modes.push(MemMode::Rom);
+        assert!(
+            best_mapping_for_phys_type(0, &circuits[0].memories[0], 0, m128k, &mut 0).is_none()
+        );
+        assert!(matches!(
+            phys_type(&configs),
+            PhysType::Ram8K | PhysType::Lutram
+        ));
+    }
+
+    #[test]
+    fn max_width_candidate_is_tried_when_asked() {
+        let mut cfg = PhysConfig {
+            max_width_non_tdp: 24,
+            max_width_sdp: 24,
+            ..PHYS_RAM1.clone()
+        };
+        let ram = mem(0, MemMode::SinglePort, 300, 24);
+        let widths = |cfg: &PhysConfig| {
+            let mut widths = Vec::new();
+            visit_candidates(0, &ram, 0, cfg, &mut 0, |m, _| widths.push(m.phys_width));
+            widths
+        };
+        assert_eq!(widths(&cfg), [1, 2, 4, 8, 16]);
+
+        cfg.include_max_width = true;
+        assert_eq!(widths(&cfg), [1, 2, 4, 8, 16, 24]);
+        // 8192 / 24 rounded down
+        let (best, _) = best_mapping_for_phys_type(0, &ram, 0, &cfg, &mut 0).unwrap();
+        assert_eq!(
+            (best.phys_width, best.phys_depth, best.phys_blocks),
+            (24, 341, 1)
+        );
+    }
+
+    #[test]
+    fn size_suffixes_scale_depth_and_width() {
+        assert_eq!(parse_size("4Ki"), Some(4096));
+        assert_eq!(parse_size("4K"), Some(4096));
+        assert_eq!(parse_size("4k"), Some(4000));
+        assert_eq!(parse_size("4Mi"), None);
+
+        let (circuit_id, ram) = parse_ram_line("0 0 SinglePort 4Ki 8").unwrap();
+        assert_eq!(ram.depth, 4096);
+        let result = map_circuits(
+            &[circuit(circuit_id, 100, vec![ram])],
+            &ArchConfig::default(),
+        );
+        assert_eq!(result.unwrap().mappings[0].logical_depth, 4096);
+        assert!(parse_ram_line("0 0 SinglePort 4Xi 8").is_err());
+    }
+
+    #[test]
+    fn balancing_relieves_an_exhausted_type() {
+        let extra = ExtraRam {
+            bits: 32768,
+            lbs_per: 50,
+            max_width: 64,
+            area: BramAreaModel::DEFAULT,
+        };
+        let arch = ArchConfig {
+            extra_rams: vec![extra],
+            ..ArchConfig::default()
+        };
+        let mut configs = arch.phys_configs();
+        let circuits = [circuit(
+            0,
+            100,
+            vec![
+                mem(0, MemMode::SinglePort, 1024, 8),
+                mem(1, MemMode::SinglePort, 1024, 8),
+                mem(2, MemMode::SinglePort, 1024, 8),
+            ],
+        )];
+        let map = |configs: &PhysConfigs| {
+            let opts = MapOptions {
+                type_priority: configs.types(),
+                ..MapOptions::default()
+            };
+            assign_ram(&circuits, configs, &opts).unwrap()
+        };
+        let on = |r: &CircuitResult, t| r.mappings.iter().filter(|m| m.phys_type == t).count();
+
+        // an M8K budget of one pushes two RAMs onto the M128K, the only other BRAM
+        configs.disable(PhysType::Extra(0));
+        let mut result = map(&configs);
+        assert_eq!(result.m8k_blocks, 3);
+        let budgets = BlockBudgets {
+            m8k: Some(1),
+            max_cost_ratio: 100.0,
+            ..BlockBudgets::default()
+        };
+        assert_eq!(balance_types(&circuits, &mut result, &configs, &budgets), 2);
+        assert_eq!((result.m8k_blocks, on(&result, PhysType::Ram128K)), (1, 2));
+
+        // with the M128K budget spent too, the extra tier takes them
+        let mut configs = arch.phys_configs();
+        let mut result = map(&configs);
+        let budgets = BlockBudgets {
+            m128k: Some(0),
+            ..budgets
+        };
+        assert_eq!(balance_types(&circuits, &mut result, &configs, &budgets), 2);
+        assert_eq!(result.m128k_blocks, 0);
+        assert_eq!(on(&result, PhysType::Extra(0)), 2);
+
+        // an extra tier over its own budget is relieved as well
+        configs.disable(PhysType::Ram8K);
+        let mut result = map(&configs);
+        let before = result.blocks(PhysType::Extra(0));
+        let budgets = BlockBudgets {
+            extra: vec![Some(before - 1)],
+            max_cost_ratio: 100.0,
+            ..BlockBudgets::default()
+        };
+        assert!(balance_types(&circuits, &mut result, &configs, &budgets) > 0);
+        assert!(result.blocks(PhysType::Extra(0)) < before);
+    }
+
+    #[test]
+    fn lut_budget_pushes_later_memories_to_more_blocks() {
+        // 16x512 needs a 4-deep cascade for 16x2048; 2x2048 does it in one row of 8 blocks
+        let m8k = PhysConfig {
+            shapes: Cow::Owned(vec![(16, 512), (2, 2048)]),
+            ..PHYS_RAM1.clone()
+        };
+        let configs = PhysConfigs {
+            lutram: None,
+            m8k: Some(m8k),
+            m128k: None,
+            extra: Vec::new(),
+        };
+        let circuits = [circuit(
+            0,
+            100,
+            vec![
+                mem(0, MemMode::SinglePort, 2048, 16),
+                mem(1, MemMode::SinglePort, 2048, 16),
+            ],
+        )];
+        let shapes = |lut_budget| {
+            let opts = MapOptions {
+                lut_budget,
+                ..MapOptions::default()
+            };
+            let result = assign_ram(&circuits, &configs, &opts).unwrap();
+            (result.mappings.iter())
+                .map(|m| (m.series, m.phys_blocks, m.extra_luts > 0))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(shapes(None), [(4, 4, true), (4, 4, true)]);
+        // the first memory's LUTs use up the budget, so the second avoids the cascade
+        assert_eq!(shapes(Some(0)), [(4, 4, true), (1, 8, false)]);
+    }
+
+    // a few circuits of mixed shapes, for checks that an option leaves the mapping alone
+    fn mixed_circuits() -> Vec<Circuit> {
+        let modes = [
+            MemMode::Rom,
+            MemMode::SinglePort,
+            MemMode::SimpleDualPort,
+            MemMode::TrueDualPort,
+        ];
+        (0..6)
+            .map(|id| {
+                let rams = (0..8)
+                    .map(|r| {
+                        mem(
+                            r,
+                            modes[(r + id) as usize % 4],
+                            64 << (r % 7),
+                            1 << (id % 6),
+                        )
+                    })
+                    .collect();
+                circuit(id, 100 * (id + 1), rams)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn streaming_decisions_leaves_mappings_alone() {
+        let circuits = mixed_circuits();
+        let configs = ArchConfig::default().phys_configs();
+        let quiet = assign_ram(&circuits, &configs, &MapOptions::default()).unwrap();
+        let opts = MapOptions {
+            stream_decisions: true,
+            ..MapOptions::default()
+        };
+        let streamed = assign_ram(&circuits, &configs, &opts).unwrap();
+        assert_eq!(streamed.mappings, quiet.mappings);
+        assert_eq!(streamed.content_hash(), quiet.content_hash());
+    }
+
+    #[test]
+    fn tdp_rams_without_a_tdp_type_are_flagged() {
+        let circuits = [circuit(
+            3,
+            100,
+            vec![
+                mem(0, MemMode::SinglePort, 64, 10),
+                mem(1, MemMode::TrueDualPort, 512, 64),
+            ],
+        )];
+        let mut configs = ArchConfig::default().phys_configs();
+        assert!(impossible_tdp_memories(&circuits, &configs).is_empty());
+        configs.disable(PhysType::Ram8K);
+        configs.disable(PhysType::Ram128K);
+        assert_eq!(impossible_tdp_memories(&circuits, &configs), [(3, 1)]);
+    }
+
+    #[test]
+    fn decoder_table_overrides_small_series() {
+        let ram = mem(0, MemMode::SinglePort, 1024, 8);
+        let custom = PhysConfig {
+            decoder_table: vec![0, 1, 2, 2],
+            ..PHYS_RAM1.clone()
+        };
+        let default = mapped(0, &ram, &PHYS_RAM1, 8, 256, 4, 1);
+        let tabled = mapped(0, &ram, &custom, 8, 256, 4, 1);
+        // the mux is 8 LUTs either way; the decoder drops from s = 4 to the table's 2
+        assert_eq!(default.extra_luts, 8 + 4);
+        assert_eq!(tabled.extra_luts, 8 + 2);
+        assert!(mapping_cost(&tabled, &custom) < mapping_cost(&default, &PHYS_RAM1));
+        // past the table the formula applies again
+        assert_eq!(decoder_luts(5, &custom.decoder_table), 5);
+    }
+
+    #[test]
+    fn parallel_memories_match_a_serial_run() {
+        // every RAM of mixed_circuits in one circuit, with sharable ROMs among them
+        let roms = [mem(0, MemMode::Rom, 512, 8), mem(0, MemMode::Rom, 512, 8)];
+        let memories = (mixed_circuits().into_iter())
+            .flat_map(|c| c.memories)
+            .chain(roms)
+            .enumerate()
+            .map(|(i, m)| Memory {
+                ram_id: i as i32,
+                ..m
+            })
+            .collect();
+        let circuits = [circuit(0, 5000, memories)];
+        let configs = ArchConfig::default().phys_configs();
+        let serial = assign_ram(&circuits, &configs, &MapOptions::default()).unwrap();
+        let opts = MapOptions {
+            parallel_memories: true,
+            ..MapOptions::default()
+        };
+        let parallel = assign_ram(&circuits, &configs, &opts).unwrap();
+        assert!(serial.mappings.iter().any(|m| m.mode != m.logical_mode));
+        assert_eq!(parallel.mappings, serial.mappings);
+        assert_eq!(parallel.content_hash(), serial.content_hash());
+    }
+
+    #[test]
+    fn simple_dual_port_rams_share_only_when_enabled() {
+        let circuits = [circuit(
+            0,
+            100,
+            vec![
+                mem(0, MemMode::SimpleDualPort, 512, 8),
+                mem(1, MemMode::SimpleDualPort, 512, 8),
+            ],
+        )];
+        let configs = ArchConfig::default().phys_configs();
+        let map = |sharing| {
+            let opts = MapOptions {
+                sharing,
+                ..MapOptions::default()
+            };
+            assign_ram(&circuits, &configs, &opts).unwrap()
+        };
+
+        let default = map(SharingConfig::default());
+        assert_eq!(default.m8k_blocks, 2);
+        assert_ne!(default.mappings[0].group_id, default.mappings[1].group_id);
+
+        let shared = map(SharingConfig {
+            modes: vec![MemMode::Rom, MemMode::SinglePort, MemMode::SimpleDualPort],
+            ..SharingConfig::default()
+        });
+        assert_eq!(shared.m8k_blocks, 1);
+        assert_eq!(shared.mappings[0].group_id, shared.mappings[1].group_id);
+        assert!(
+            shared
+                .mappings
+                .iter()
+                .all(|m| m.mode == MemMode::TrueDualPort)
+        );
+    }
+
+    #[test]
+    fn ablation_reports_each_type_and_penalises_the_busy_one() {
+        let arch = ArchConfig::default();
+        let configs = arch.phys_configs();
+        let circuits: Vec<Circuit> = (0..4)
+            .map(|id| {
+                let memories = (0..6)
+                    .map(|r| mem(r, MemMode::SimpleDualPort, 256, 32))
+                    .collect();
+                circuit(id, 400, memories)
+            })
+            .collect();
+        let opts = MapOptions::default();
+        let result = assign_ram(&circuits, &configs, &opts).unwrap();
+        assert!(result.m8k_blocks > 0);
+
+        let params = GeomeanParams::for_model(&arch, AreaModel::Unified);
+        let (baseline, report) = type_ablation(&circuits, &configs, &opts, &params).unwrap();
+        let types: Vec<PhysType> = report.iter().map(|a| a.phys_type).collect();
+        assert_eq!(types, configs.types());
+        assert_eq!(types.len(), 3);
+
+        let without_m8k = report
+            .iter()
+            .find(|a| a.phys_type == PhysType::Ram8K)
+            .map(|a| *a.geomean.as_ref().unwrap())
+            .unwrap();
+        assert!(without_m8k > baseline);
+    }
+
+    #[test]
+    fn share_granularity_rejects_unaligned_pairs() {
+        // 640x8 + 384x8 fill the 8192 bits exactly: 5/8 and 3/8 of the block
+        let a = mem(0, MemMode::Rom, 640, 8);
+        let b = mem(1, MemMode::Rom, 384, 8);
+        let share = |granularity| {
+            let mut mappings = vec![
+                mapped(0, &a, &PHYS_RAM1, 8, 1024, 1, 1),
+                mapped(0, &b, &PHYS_RAM1, 8, 1024, 1, 1),
+            ];
+            let sharing = SharingConfig {
+                granularity,
+                ..SharingConfig::default()
+            };
+            let mut blocks = 2;
+            share_type(&mut mappings, &PHYS_RAM1, &sharing, &mut blocks);
+            blocks
+        };
+        assert_eq!(share(None), 1);
+        // quarters: neither RAM is a whole number of 2048-bit quarters
+        assert_eq!(share(Some(4)), 2);
+        // eighths: both are aligned
+        assert_eq!(share(Some(8)), 1);
+        // zero or negative is no constraint rather than a division by zero
+        assert_eq!(share(Some(0)), 1);
+        assert_eq!(share(Some(-4)), 1);
+    }
+
+    #[test]
+    fn depth_waste_weight_prefers_the_better_fitting_shape() {
+        // 1100 rows: 2048-deep blocks leave 948 rows unused, five 256-deep ones only 180
+        let ram = mem(0, MemMode::SinglePort, 1100, 8);
+        let best = |weight| {
+            let mut cfg = PHYS_RAM1.clone();
+            cfg.cost.depth_waste_weight = weight;
+            let mut evaluated = 0;
+            best_mapping_for_phys_type(0, &ram, 0, &cfg, &mut evaluated).unwrap()
+        };
+        let (plain, _) = best(0.0);
+        assert_eq!((plain.phys_depth, plain.series), (2048, 1));
+
+        let mut weighted = PHYS_RAM1.clone();
+        weighted.cost.depth_waste_weight = 1e6;
+        assert_eq!(
+            mapping_cost(&plain, &weighted),
+            mapping_cost(&plain, &PHYS_RAM1) + 948.0 * 1e6
+        );
+
+        let (fitted, _) = best(1e6);
+        assert_eq!((fitted.phys_depth, fitted.series), (256, 5));
+        let unused = |m: &RamMapping| m.phys_depth * m.series - m.logical_depth;
+        assert!(unused(&fitted) < unused(&plain));
+    }
+
+    #[test]
+    fn swapped_input_files_are_caught_early() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let write = |name: &str, text: &str| std::fs::write(path(name), text).unwrap();
+        write("blocks.txt", "Circuit\tLogic_Blocks\n0\t500\n");
+        write(
+            "rams.txt",
+            "Num_Circuits 1\nCircuit\tRamID\tMode\tDepth\tWidth\n0\t0\tROM\t256\t8\n",
+        );
+        // a trailing column in either file is fine
+        write(
+            "blocks_extra.txt",
+            "Circuit\tLogic_Blocks\tName\n0\t500\tfoo\n",
+        );
+        write("rams_extra.txt", "0\t0\tROM\t256\t8\tnote\n");
+        write("junk.txt", "0 junk\n");
+
+        let check = |blocks: &str, rams: &str| check_input_shapes(&path(blocks), &path(rams));
+        assert!(check("blocks.txt", "rams.txt").is_ok());
+        assert!(check("blocks_extra.txt", "rams_extra.txt").is_ok());
+
+        let err = check("rams.txt", "blocks.txt").unwrap_err();
+        assert!(matches!(err, RapidMapError::Config(_)));
+        assert!(
+            err.to_string()
+                .contains("does not look like a logic block file")
+        );
+        assert!(err.to_string().ends_with("swapped?"));
+
+        // the RAM file passed twice: the logic block side is wrong, but nothing was swapped
+        let err = check("rams.txt", "rams.txt").unwrap_err().to_string();
+        assert!(err.contains("does not look like a logic block file"));
+        assert!(!err.contains("swapped"));
+        let err = check("blocks.txt", "junk.txt").unwrap_err().to_string();
+        assert!(err.contains("does not look like a logical RAM file"));
+        assert!(!err.contains("swapped"));
+    }
+
+    #[test]
+    fn sensitivity_covers_every_type_and_lb_area_raises_the_geomean() {
+        let extra = ExtraRam {
+            bits: 32768,
+            lbs_per: 50,
+            max_width: 64,
+            area: BramAreaModel::DEFAULT,
+        };
+        let arch = ArchConfig {
+            extra_rams: vec![extra],
+            ..ArchConfig::default()
+        };
+        let configs = arch.phys_configs();
+        let circuits = mixed_circuits();
+        let params = GeomeanParams::for_model(&arch, AreaModel::Unified);
+        let geom_blocks = [
+            (arch.ram1_bits, arch.max_width_ram1, arch.ram1_area),
+            (arch.ram2_bits, arch.max_width_ram2, arch.ram2_area),
+        ];
+        let (baseline, report) = cost_sensitivity(
+            &circuits,
+            &configs,
+            &MapOptions::default(),
+            &params,
+            geom_blocks,
+            0.01,
+        )
+        .unwrap();
+
+        // a penalty strength for each of the four types, the two shared constants and four
+        // area coefficients for each of the three BRAM types
+        let names: Vec<String> = report.iter().map(|s| s.constant.name()).collect();
+        assert_eq!(names.len(), 4 + 2 + 3 * 4);
+        assert!(names.contains(&"penalty_strength.ram3".to_string()));
+        assert!(names.contains(&"bram_area.per_bit.ram3".to_string()));
+        assert!(
+            !names
+                .iter()
+                .any(|n| n.ends_with(".lutram") && n.starts_with("bram_area"))
+        );
+
+        let lb_area = report
+            .iter()
+            .find(|s| matches!(s.constant, CostConstant::LbArea))
+            .unwrap();
+        assert_eq!(lb_area.value, AVG_LB_AREA);
+        assert!(lb_area.geomean > baseline);
+        assert!(lb_area.gradient > 0.0);
+    }
+
+    #[test]
+    fn too_wide_ram_is_an_error_not_a_panic() {
+        // only M8K, at most one block side by side: 64 bits is wider than any of its shapes
+        let mut configs = ArchConfig::default().phys_configs();
+        configs.disable(PhysType::Lutram);
+        configs.disable(PhysType::Ram128K);
+        configs.get_mut(PhysType::Ram8K).unwrap().max_parallel = 1;
+        let circuits = [
+            circuit(0, 100, vec![mem(0, MemMode::SinglePort, 256, 8)]),
+            circuit(1, 100, vec![mem(0, MemMode::SinglePort, 256, 64)]),
+        ];
+
+        let strict = MapOptions {
+            strict: true,
+            ..MapOptions::default()
+        };
+        match assign_ram(&circuits, &configs, &strict) {
+            Err(RapidMapError::NoLegalMapping(e)) => {
+                assert_eq!((e.circuit_id, e.ram_id, e.width), (1, 0, 64));
+                assert_eq!(e.min_parallel, Some(2));
+            }
+            other => panic!(
+                "expected NoLegalMapping, got {:?}",
+                other.map(|r| r.mappings)
+            ),
+        }
+
+        // without --strict the circuit is skipped and the rest still map
+        let result = assign_ram(&circuits, &configs, &MapOptions::default()).unwrap();
+        assert_eq!(result.skipped.len(), 1);
+        assert_eq!(result.skipped[0].circuit_id, 1);
+        assert_eq!(result.mappings.len(), 1);
+        assert_eq!(result.mappings[0].circuit_id, 0);
+    }
+
+    #[test]
+    fn four_roms_merge_into_two_complementary_pairs() {
+        // 640 + 384 and 512 + 512 rows of 8 bits each fill an 8192-bit block exactly
+        let roms = [
+            mem(0, MemMode::Rom, 640, 8),
+            mem(1, MemMode::Rom, 512, 8),
+            mem(2, MemMode::Rom, 384, 8),
+            mem(3, MemMode::Rom, 512, 8),
+        ];
+        let mut mappings: Vec<RamMapping> = (roms.iter())
+            .map(|m| mapped(0, m, &PHYS_RAM1, 8, 1024, 1, 1))
+            .collect();
+        let mut blocks = 4;
+        let freed = share_type(
+            &mut mappings,
+            &PHYS_RAM1,
+            &SharingConfig::default(),
+            &mut blocks,
+        );
+        assert_eq!((freed, blocks), (2, 2));
+        let groups: Vec<i32> = mappings.iter().map(|m| m.group_id).collect();
+        assert_eq!(groups, [0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn three_small_roms_pack_into_one_block() {
+        // 256x8 = 2048 bits each: 6144 bits and 768 rows stacked in a 1024x8 block
+        let roms: Vec<Memory> = (0..3).map(|r| mem(r, MemMode::Rom, 256, 8)).collect();
+        let share = |max_sharers| {
+            let mut mappings: Vec<RamMapping> = (roms.iter())
+                .map(|m| mapped(0, m, &PHYS_RAM1, 8, 1024, 1, 1))
+                .collect();
+            let sharing = SharingConfig {
+                max_sharers,
+                ..SharingConfig::default()
+            };
+            let mut blocks = 3;
+            share_type(&mut mappings, &PHYS_RAM1, &sharing, &mut blocks);
+            (blocks, mappings)
+        };
+
+        // pairs must fill the block exactly, so none form
+        assert_eq!(share(2).0, 3);
+
+        let (blocks, mappings) = share(3);
+        assert_eq!(blocks, 1);
+        assert!(mappings.iter().all(|m| m.group_id == 0));
+        assert!(validate_shared_groups(&mappings).is_ok());
+    }
+
+    #[test]
+    fn rams_wider_than_the_tdp_width_do_not_share() {
+        // 128x32 twice fills a 256x32 block, but the shared block would run both ports at 32
+        let a = mem(0, MemMode::SinglePort, 128, 32);
+        let b = mem(1, MemMode::SinglePort, 128, 32);
+        let share = |max_width_tdp| {
+            let cfg = PhysConfig {
+                max_width_tdp,
+                ..PHYS_RAM1.clone()
+            };
+            let mut mappings = vec![
+                mapped(0, &a, &cfg, 32, 256, 1, 1),
+                mapped(0, &b, &cfg, 32, 256, 1, 1),
+            ];
+            let mut blocks = 2;
+            share_type(&mut mappings, &cfg, &SharingConfig::default(), &mut blocks);
+            blocks
+        };
+        assert_eq!(share(16), 2);
+        assert_eq!(share(32), 1);
+    }
+
+    #[test]
+    fn max_series_limits_deep_narrow_rams() {
+        // 100000x1 in 4096-deep blocks needs 25 in series
+        let arch = ArchConfig {
+            has_lutram: false,
+            has_ram2: false,
+            ram1_bits: 4096,
+            ..ArchConfig::default()
+        };
+        let circuits = [circuit(
+            0,
+            100,
+            vec![mem(0, MemMode::SinglePort, 100000, 1)],
+        )];
+        let map = |max_series| {
+            let mut configs = arch.phys_configs();
+            configs.get_mut(PhysType::Ram8K).unwrap().max_series = max_series;
+            let opts = MapOptions {
+                strict: true,
+                ..MapOptions::default()
+            };
+            assign_ram(&circuits, &configs, &opts)
+        };
+
+        match map(16) {
+            Err(RapidMapError::NoLegalMapping(e)) => {
+                assert_eq!(e.min_series, Some(25));
+                assert!(
+                    e.to_string()
+                        .ends_with("needs 25 blocks in series, over max_series)")
+                );
+            }
+            other => panic!(
+                "expected NoLegalMapping, got {:?}",
+                other.map(|r| r.mappings)
+            ),
+        }
+        let result = map(32).unwrap();
+        assert_eq!(result.mappings[0].series, 25);
+        assert_eq!(result.m8k_blocks, 25);
+    }
+
+    #[test]
+    fn include_max_width_adds_to_listed_widths() {
+        // 341x24 fits one 24-wide block (8192 / 24 = 341 rows), or three 8-wide ones
+        let ram = mem(0, MemMode::SinglePort, 341, 24);
+        let best = |include_max_width| {
+            let cfg = PhysConfig {
+                max_width_non_tdp: 24,
+                max_width_sdp: 24,
+                width_candidates: vec![2, 4, 8],
+                include_max_width,
+                ..PHYS_RAM1.clone()
+            };
+            let mut evaluated = 0;
+            best_mapping_for_phys_type(0, &ram, 0, &cfg, &mut evaluated)
+                .unwrap()
+                .0
+        };
+        let listed = best(false);
+        assert_eq!((listed.phys_width, listed.parallel), (8, 3));
+        let widest = best(true);
+        assert_eq!(
+            (widest.phys_width, widest.phys_depth, widest.phys_blocks),
+            (24, 341, 1)
+        );
+    }
+
+    #[test]
+    fn parity_width_candidates_pick_the_cheapest_legal_shape() {
+        // a 9216-bit block is 1024x9, 512x18 or 256x36
+        let cfg = PhysConfig {
+            bits: 9216,
+            max_width_non_tdp: 36,
+            max_width_sdp: 36,
+            max_width_tdp: 18,
+            width_candidates: vec![9, 18, 36],
+            ..PHYS_RAM1.clone()
+        };
+        let best = |depth, width| {
+            let mut evaluated = 0;
+            let ram = mem(0, MemMode::SinglePort, depth, width);
+            let m = best_mapping_for_phys_type(0, &ram, 0, &cfg, &mut evaluated)
+                .unwrap()
+                .0;
+            (m.phys_width, m.phys_depth, m.phys_blocks)
+        };
+        assert_eq!(best(512, 18), (18, 512, 1));
+        assert_eq!(best(1000, 9), (9, 1024, 1));
+        assert_eq!(best(200, 36), (36, 256, 1));
+        // 72 bits wide: two 36-wide blocks side by side
+        assert_eq!(best(256, 72), (36, 256, 2));
+    }
+
+    #[test]
+    fn extra_tier_count_keeps_type_ids_in_a_byte() {
+        let tier = ExtraRam {
+            bits: 524288,
+            lbs_per: 1000,
+            max_width: 256,
+            area: BramAreaModel::DEFAULT,
+        };
+        let with_tiers = |n| ArchConfig {
+            extra_rams: vec![tier; n],
+            ..ArchConfig::default()
+        };
+        assert!(with_tiers(MAX_EXTRA_RAMS).validate().is_ok());
+        assert_eq!(
+            PhysType::Extra((MAX_EXTRA_RAMS - 1) as u8).type_id(),
+            i32::from(u8::MAX)
+        );
+        let err = with_tiers(MAX_EXTRA_RAMS + 1).validate().unwrap_err();
+        assert_eq!(err, "253 extra_ram tiers given, at most 252");
+    }
+
+    #[test]
+    fn a_512k_tier_wins_a_very_large_ram() {
+        let arch = ArchConfig {
+            extra_rams: vec![ExtraRam {
+                bits: 524288,
+                lbs_per: 1000,
+                max_width: 256,
+                area: BramAreaModel::DEFAULT,
+            }],
+            ..ArchConfig::default()
+        };
+        assert_eq!(arch.phys_configs().types().len(), 4);
+        // 4 Mbit: eight 512K blocks against 32 M128Ks
+        let circuits = [circuit(
+            0,
+            10000,
+            vec![mem(0, MemMode::SinglePort, 65536, 64)],
+        )];
+        let result = map_circuits(&circuits, &arch).unwrap();
+        assert_eq!(result.mappings[0].phys_type, PhysType::Extra(0));
+        assert_eq!(result.blocks(PhysType::Extra(0)), 8);
+    }
+
+    #[test]
+    fn bad_ram_lines_are_skipped_with_line_numbers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        std::fs::write(path("blocks.txt"), "Circuit\tLogic_Blocks\n0\t500\n").unwrap();
+        std::fs::write(
+            path("rams.txt"),
+            "Num_Circuits 1\nCircuit\tRamID\tMode\tDepth\tWidth\n\
+             0\t0\tSinglePort\t1024\t-8\n\
+             0\t1\tROM\t0\t16\n\
+             0\t2\tDualPort\t512\t8\n\
+             x\t3\tROM\t512\t8\n\
+             0\t4\tROM\t512\t8\n",
+        )
+        .unwrap();
+
+        let (circuits, warnings) = read_data(
+            &path("blocks.txt"),
+            &path("rams.txt"),
+            &ReadOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(circuits[0].memories, [mem(4, MemMode::Rom, 512, 8)]);
+        let reported: Vec<(usize, &str)> = (warnings.iter())
+            .map(|w| (w.line, w.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reported,
+            [
+                (3, "width must be positive, got -8 for RAM 0 in circuit 0"),
+                (4, "depth must be positive, got 0 for RAM 1 in circuit 0"),
+                (5, "unknown mode 'DualPort' for RAM 2 in circuit 0"),
+                (6, "bad circuit id 'x', RAM dropped"),
+            ]
+        );
+    }
+
+    #[test]
+    fn local_search_beats_greedy_on_total_area() {
+        let arch = ArchConfig::default();
+        let configs = arch.phys_configs();
+        // each 128 Kbit RAM greedily takes a whole M128K, and four M128Ks need 1200 tiles
+        let circuits = [circuit(
+            0,
+            100,
+            (0..4)
+                .map(|r| mem(r, MemMode::SinglePort, 16384, 8))
+                .collect(),
+        )];
+        let greedy = map_circuits(&circuits, &arch).unwrap();
+        assert_eq!(greedy.m128k_blocks, 4);
+        let total_area = |r: &CircuitResult| crate::utils::compute_total_area(&circuits, r, &arch);
+
+        let search = || {
+            let mut result = greedy.clone();
+            let (passes, switched) =
+                local_search(&circuits, &mut result, &configs, 10, None, total_area);
+            (result, passes, switched)
+        };
+        // three move to 16 M8Ks each: 480 tiles for 48 M8Ks, 300 for the last M128K
+        let (result, passes, switched) = search();
+        assert_eq!((passes, switched), (2, 3));
+        assert_eq!((result.m8k_blocks, result.m128k_blocks), (48, 1));
+        assert!(total_area(&result) < total_area(&greedy));
+        assert_eq!(search().0.mappings, result.mappings);
+    }
+
+    #[test]
+    fn mux_luts_with_and_without_pipelining() {
+        // per output bit, combinational: s=1 none, s=2 one 4:1 LUT, s=5 two plus one,
+        // s=16 four plus one
+        for (s, per_bit) in [(1, 0), (2, 1), (5, 3), (16, 5)] {
+            assert_eq!(mux_luts(s, 8, None), 8 * per_bit, "s={}", s);
+            // a threshold at or above s changes nothing
+            assert_eq!(mux_luts(s, 8, Some(16)), 8 * per_bit, "s={}", s);
+        }
+        // past threshold 4, one register LUT per tree level: s=5 and s=16 have two levels
+        let pipelined: Vec<i32> = [1, 2, 5, 16].map(|s| mux_luts(s, 8, Some(4))).to_vec();
+        assert_eq!(pipelined, [0, 8, 8 * 5, 8 * 7]);
+        // threshold 1 registers s=2's single level too
+        assert_eq!(mux_luts(2, 8, Some(1)), 8 * 2);
+    }
+
+    #[test]
+    fn decoder_luts_pinned() {
+        let counts: Vec<i32> = (0..=17).map(|s| decoder_luts(s, &[])).collect();
+        assert_eq!(
+            counts,
+            [0, 0, 1, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17]
+        );
+        // s=2 is the special case: one LUT drives both enables (the address bit and its
+        // inverse), so it costs 1, not the 2 that one-LUT-per-block would give
+        assert_eq!(decoder_luts(2, &[]), 1);
+        assert_eq!(decoder_luts(3, &[]), 3);
+    }
+
+    #[test]
+    fn mux_luts_pinned() {
+        // per output bit for s = 0..=17: one 4:1 LUT up to s=4, then a level per factor of 4
+        let per_bit: Vec<i32> = (0..=17).map(|s| mux_luts(s, 1, None)).collect();
+        assert_eq!(
+            per_bit,
+            [0, 0, 1, 1, 1, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 8]
+        );
+        // linear in width
+        for width in [1, 3, 8, 32, 128] {
+            for s in [2, 4, 5, 16, 17] {
+                assert_eq!(
+                    mux_luts(s, width, None),
+                    width * mux_luts(s, 1, None),
+                    "s={} width={}",
+                    s,
+                    width
+                );
+            }
+        }
+        assert_eq!(mux_luts(16, 32, None), 160);
+        assert_eq!(mux_luts(17, 12, None), 96);
+    }
+
+    #[test]
+    fn streamed_circuits_match_the_buffered_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        std::fs::write(
+            path("blocks.txt"),
+            "Circuit\tLogic_Blocks\n0\t500\n1\t300\n2\t40\n3\t800\n",
+        )
+        .unwrap();
+        // sorted by circuit; circuit 2 has no RAMs and one line is malformed
+        std::fs::write(
+            path("rams.txt"),
+            "Num_Circuits 3\nCircuit\tRamID\tMode\tDepth\tWidth\n\
+             0\t0\tSimpleDualPort\t45\t12\n\
+             0\t1\tTrueDualPort\t4096\t32\n\
+             1\t0\tROM\t1024\t16\n\
+             1\t1\tROM\t0\t16\n\
+             3\t0\tSinglePort\t512\t8\n\
+             3\t1\tSinglePort\t64\t10\n",
+        )
+        .unwrap();
+        let (blocks, rams) = (path("blocks.txt"), path("rams.txt"));
+        let opts = ReadOptions::default();
+
+        let (buffered, buffered_warnings) = read_data(&blocks, &rams, &opts).unwrap();
+        let mut stream = read_circuits_streaming(&blocks, &rams, &opts).unwrap();
+        assert!(stream.is_streaming());
+        let streamed: Vec<Circuit> = stream.by_ref().map(Result::unwrap).collect();
+        assert_eq!(streamed, buffered);
+        assert_eq!(streamed.len(), 4);
+        let lines = |w: &[ParseWarning]| w.iter().map(|w| w.line).collect::<Vec<_>>();
+        assert_eq!(lines(stream.warnings()), lines(&buffered_warnings));
+        assert_eq!(lines(&buffered_warnings), [6]);
+
+        // and the mapped results agree
+        let arch = ArchConfig::default();
+        let a = map_circuits(&buffered, &arch).unwrap();
+        let b = map_circuits(&streamed, &arch).unwrap();
+        assert_eq!(a.mappings, b.mappings);
+
+        // unsorted input falls back to the buffered read, with the same circuits
+        std::fs::write(
+            path("unsorted.txt"),
+            "Num_Circuits 2\nCircuit\tRamID\tMode\tDepth\tWidth\n\
+             1\t0\tROM\t1024\t16\n\
+             0\t0\tSimpleDualPort\t45\t12\n",
+        )
+        .unwrap();
+        let unsorted = path("unsorted.txt");
+        let stream = read_circuits_streaming(&blocks, &unsorted, &opts).unwrap();
+        assert!(!stream.is_streaming());
+        let fallback: Vec<Circuit> = stream.map(Result::unwrap).collect();
+        assert_eq!(fallback, read_data(&blocks, &unsorted, &opts).unwrap().0);
+    }
+
+    #[test]
+    fn without_sharing_totals_are_the_naive_sums() {
+        let roms = (0..2).map(|r| mem(8 + r, MemMode::Rom, 512, 8));
+        let mut circuits = mixed_circuits();
+        circuits[0].memories.extend(roms);
+        let configs = ArchConfig::default().phys_configs();
+        let map = |sharing_iterations| {
+            let opts = MapOptions {
+                sharing_iterations,
+                ..MapOptions::default()
+            };
+            assign_ram(&circuits, &configs, &opts).unwrap()
+        };
+
+        // what --no-sharing runs: every mapping keeps its own blocks
+        let unshared = map(0);
+        for t in configs.types() {
+            let naive: i32 = (unshared.mappings.iter())
+                .filter(|m| m.phys_type == t)
+                .map(|m| m.phys_blocks)
+                .sum();
+            assert_eq!(unshared.blocks(t), naive, "{}", t.name());
+        }
+        assert!(unshared.mappings.iter().all(|m| m.mode == m.logical_mode));
+
+        let shared = map(1);
+        assert!(shared.m8k_blocks < unshared.m8k_blocks);
+    }
+
+    #[test]
+    fn a_48_bit_ram_splits_across_lutram_and_m8k() {
+        // a cheaper M8K puts one block between two and three LUTRAMs, so 64x48 costs less as
+        // 16 bits in two LUTRAMs plus 32 bits in one M8K than as five LUTRAMs or two M8Ks
+        let arch = ArchConfig {
+            has_ram2: false,
+            ram1_area: BramAreaModel {
+                per_bit: 3.0,
+                ..BramAreaModel::DEFAULT
+            },
+            ..ArchConfig::default()
+        };
+        let configs = arch.phys_configs();
+        let circuits = [circuit(0, 100, vec![mem(0, MemMode::SinglePort, 64, 48)])];
+        let map = |allow_split| {
+            let opts = MapOptions {
+                allow_split,
+                type_priority: configs.types(),
+                ..MapOptions::default()
+            };
+            assign_ram(&circuits, &configs, &opts).unwrap().mappings
+        };
+        let total_cost = |mappings: &[RamMapping]| -> f64 {
+            (mappings.iter())
+                .map(|m| mapping_cost(m, configs.get(m.phys_type).unwrap()))
+                .sum()
+        };
+
+        let single = map(false);
+        assert_eq!(single.len(), 1);
+        assert_eq!(
+            (single[0].phys_type, single[0].phys_blocks),
+            (PhysType::Lutram, 5)
+        );
+
+        let split = map(true);
+        let parts: Vec<_> = (split.iter())
+            .map(|m| (m.phys_type, m.logical_width, m.phys_blocks))
+            .collect();
+        assert_eq!(parts, [(PhysType::Lutram, 16, 2), (PhysType::Ram8K, 32, 1)]);
+        assert_eq!(split[0].group_id, split[1].group_id);
+        assert!(total_cost(&split) < total_cost(&single));
+        let m8k_only = best_mapping_for_phys_type(
+            0,
+            &circuits[0].memories[0],
+            0,
+            configs.get(PhysType::Ram8K).unwrap(),
+            &mut 0,
+        )
+        .unwrap();
+        assert!(total_cost(&split) < m8k_only.1);
+    }
+
+    #[test]
+    fn rom_discount_moves_a_rom_onto_lutram() {
+        let circuits = [circuit(
+            0,
+            100,
+            vec![
+                mem(0, MemMode::Rom, 64, 32),
+                mem(1, MemMode::SinglePort, 64, 32),
+            ],
+        )];
+        let types = |rom_discount| {
+            let arch = ArchConfig {
+                rom_discount,
+                ..ArchConfig::default()
+            };
+            let result = map_circuits(&circuits, &arch).unwrap();
+            (result.mappings.iter())
+                .map(|m| m.phys_type)
+                .collect::<Vec<_>>()
+        };
+        // 64x32 on LUTRAM costs about 1.22 times its one M8K, so the switch is near 0.82
+        assert_eq!(types(1.0), [PhysType::Ram8K, PhysType::Ram8K]);
+        assert_eq!(types(0.85), [PhysType::Ram8K, PhysType::Ram8K]);
+        // only the ROM moves; the RAM has a write port and pays full price
+        assert_eq!(types(0.8), [PhysType::Lutram, PhysType::Ram8K]);
+        assert_eq!(types(0.5), [PhysType::Lutram, PhysType::Ram8K]);
+    }
+
+    #[test]
+    fn every_pass_keeps_blocks_equal_to_series_times_parallel() {
+        let circuits = mixed_circuits();
+        let arch = ArchConfig::default();
+        let configs = arch.phys_configs();
+        let opts = MapOptions {
+            allow_split: true,
+            allow_lut_memory: true,
+            type_priority: configs.types(),
+            ..MapOptions::default()
+        };
+        let mut result = assign_ram(&circuits, &configs, &opts).unwrap();
+        let total_area = |r: &CircuitResult| crate::utils::compute_total_area(&circuits, r, &arch);
+        local_search(&circuits, &mut result, &configs, 10, None, total_area);
+        global_objective_pass(&circuits, &mut result, &configs, 1.0, 1.0, total_area);
+        for m in &result.mappings {
+            assert_eq!(m.validate(), Ok(()));
+        }
+
+        // a pass that rewrote a block count without S or P would be caught
+        let mut broken = result.mappings[0].clone();
+        broken.phys_blocks += 1;
+        let err = broken.validate().unwrap_err();
+        assert!(err.starts_with(&format!(
+            "phys_blocks {} != series {} * parallel {}",
+            broken.phys_blocks, broken.series, broken.parallel
+        )));
+    }
+
+    #[test]
+    fn a_4x2_ram_is_built_from_luts() {
+        let configs = ArchConfig::default().phys_configs();
+        let circuits = [circuit(0, 100, vec![mem(0, MemMode::SinglePort, 4, 2)])];
+        let map = |allow_lut_memory| {
+            let opts = MapOptions {
+                allow_lut_memory,
+                type_priority: configs.types(),
+                ..MapOptions::default()
+            };
+            assign_ram(&circuits, &configs, &opts).unwrap()
+        };
+        let lutram = map(false);
+        assert_eq!(lutram.lutram_blocks, 1);
+        assert!(!lutram.mappings[0].is_lut_only());
+
+        let luts = map(true);
+        let m = &luts.mappings[0];
+        assert!(m.is_lut_only());
+        assert_eq!((m.phys_blocks, m.extra_luts), (0, 8));
+        assert_eq!(luts.lutram_blocks, 0);
+        let cfg = configs.get(PhysType::Lutram).unwrap();
+        assert!(mapping_cost(m, cfg) < mapping_cost(&lutram.mappings[0], cfg));
+    }
+
+    #[test]
+    fn lut_memory_too_large_for_an_i32_is_dropped() {
+        let huge = mem(0, MemMode::Rom, 1 << 16, 1 << 15);
+        assert!(lut_memory_mapping(0, &huge, 0).is_none());
+        let small = mem(0, MemMode::Rom, 1 << 15, 1 << 15);
+        assert_eq!(
+            lut_memory_mapping(0, &small, 0).unwrap().extra_luts,
+            1 << 30
+        );
+    }
+
+    #[test]
+    fn max_parallel_8_rejects_a_5000_bit_ram_on_m8k_only() {
+        let wide = mem(0, MemMode::SinglePort, 16, 5000);
+        let mut configs = ArchConfig::default().phys_configs();
+        configs.get_mut(PhysType::Ram8K).unwrap().max_parallel = 8;
+        // 32 bits a block needs 157 M8Ks side by side; 128 bits a block needs 40 M128Ks,
+        // and M128K keeps its unlimited default
+        let m8k = configs.get(PhysType::Ram8K).unwrap();
+        assert!(best_mapping_for_phys_type(0, &wide, 0, m8k, &mut 0).is_none());
+        let m128k = configs.get(PhysType::Ram128K).unwrap();
+        let (on_m128k, _) = best_mapping_for_phys_type(0, &wide, 0, m128k, &mut 0).unwrap();
+        assert_eq!(on_m128k.parallel, 40);
+
+        let circuits = [circuit(0, 100, vec![wide])];
+        let strict = MapOptions {
+            strict: true,
+            type_priority: configs.types(),
+            ..MapOptions::default()
+        };
+        let result = assign_ram(&circuits, &configs, &strict).unwrap();
+        assert_ne!(result.mappings[0].phys_type, PhysType::Ram8K);
+
+        // with every type capped at 8 nothing fits, and the error names the narrowest row
+        for t in configs.types() {
+            configs.get_mut(t).unwrap().max_parallel = 8;
+        }
+        match assign_ram(&circuits, &configs, &strict) {
+            Err(RapidMapError::NoLegalMapping(e)) => assert_eq!(e.min_parallel, Some(40)),
+            other => panic!(
+                "expected NoLegalMapping, got {:?}",
+                other.map(|r| r.mappings)
+            ),
+        }
+    }
+
+    #[test]
+    fn four_shape_lutram_table_picks_the_fitting_shape() {
+        let cfg = PhysConfig {
+            shapes: Cow::Owned(vec![(5, 128), (10, 64), (20, 32), (40, 16)]),
+            max_width_non_tdp: 40,
+            max_width_sdp: 40,
+            ..PHYS_LUTRAM
+        };
+        let shape = |depth, width| {
+            let ram = mem(0, MemMode::SinglePort, depth, width);
+            let (m, _) = best_mapping_for_phys_type(0, &ram, 0, &cfg, &mut 0).unwrap();
+            (m.phys_width, m.phys_depth, m.phys_blocks)
+        };
+        assert_eq!(shape(16, 40), (40, 16, 1));
+        assert_eq!(shape(128, 5), (5, 128, 1));
+        assert_eq!(shape(64, 10), (10, 64, 1));
+        assert_eq!(shape(32, 20), (20, 32, 1));
+
+        // the default two shapes need two blocks for either end of the table
+        let ram = mem(0, MemMode::SinglePort, 16, 40);
+        let (m, _) = best_mapping_for_phys_type(0, &ram, 0, &PHYS_LUTRAM, &mut 0).unwrap();
+        assert_eq!(m.phys_blocks, 2);
+        let ram = mem(0, MemMode::SinglePort, 128, 5);
+        let (m, _) = best_mapping_for_phys_type(0, &ram, 0, &PHYS_LUTRAM, &mut 0).unwrap();
+        assert_eq!(m.phys_blocks, 2);
+    }
+
+    #[test]
+    fn utilization_of_a_full_and_a_half_full_block() {
+        let cfg = &PHYS_RAM1;
+        let utilization = |depth, width| {
+            let ram = mem(0, MemMode::SinglePort, depth, width);
+            let (m, _) = best_mapping_for_phys_type(0, &ram, 0, cfg, &mut 0).unwrap();
+            assert_eq!(m.phys_blocks, 1);
+            m.utilization(cfg)
+        };
+        assert_eq!(utilization(1024, 8), 1.0);
+        assert_eq!(utilization(256, 32), 1.0);
+        assert!((utilization(512, 8) - 0.5).abs() < 1e-12);
+        assert!((utilization(1024, 4) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn search_finds_the_exhaustive_minimum() {
+        let configs = ArchConfig::default().phys_configs();
+        let types = configs.types();
+        let mut sample: Vec<Memory> = (mixed_circuits().into_iter())
+            .flat_map(|c| c.memories)
+            .collect();
+        // shapes where the series count steps down as the width grows
+        sample.extend([
+            mem(0, MemMode::SinglePort, 3000, 20),
+            mem(1, MemMode::SimpleDualPort, 700, 48),
+            mem(2, MemMode::TrueDualPort, 5000, 9),
+            mem(3, MemMode::Rom, 20000, 3),
+        ]);
+        for ram in &sample {
+            let candidates = candidate_costs(0, ram, &configs, &types);
+            for &t in &types {
+                let cfg = configs.get(t).unwrap();
+                // the first of the cheapest, as the strict < in the search keeps
+                let exhaustive = (candidates.iter()).filter(|(m, _)| m.phys_type == t).fold(
+                    None,
+                    |best: Option<&(RamMapping, f64)>, c| match best {
+                        Some(b) if b.1 <= c.1 => Some(b),
+                        _ => Some(c),
+                    },
+                );
+                let searched = best_mapping_for_phys_type(0, ram, 0, cfg, &mut 0);
+                match (searched, exhaustive) {
+                    (Some((m, cost)), Some((best, best_cost))) => {
+                        assert_eq!(cost, *best_cost, "{:?} on {:?}", ram, t);
+                        assert_eq!(&m, best);
+                    }
+                    (None, None) => {}
+                    (s, e) => panic!("{:?} on {:?}: search {:?}, exhaustive {:?}", ram, t, s, e),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_remap_of_one_circuit_matches_a_full_run() {
+        let arch = ArchConfig::default();
+        let before = mixed_circuits();
+        let prev = map_circuits(&before, &arch).unwrap();
+
+        let mut after = before.clone();
+        after[3].memories[2].depth *= 4;
+        after[3].memories.push(mem(8, MemMode::Rom, 512, 8));
+        after[3].memories.push(mem(9, MemMode::Rom, 512, 8));
+        let incremental = map_circuits_incremental(&prev, &after, &[after[3].id], &arch).unwrap();
+        let full = map_circuits(&after, &arch).unwrap();
+        assert_ne!(full.mappings, prev.mappings);
+        assert_eq!(incremental.mappings, full.mappings);
+        assert_eq!(incremental.extra_luts, full.extra_luts);
+        assert_eq!(incremental.lutram_blocks, full.lutram_blocks);
+        assert_eq!(incremental.m8k_blocks, full.m8k_blocks);
+        assert_eq!(incremental.m128k_blocks, full.m128k_blocks);
+        assert!(incremental.candidates_evaluated < full.candidates_evaluated);
+    }
+
+    #[test]
+    fn group_ids_survive_reordering_the_circuits() {
+        let arch = ArchConfig::default();
+        let ids = |circuits: &[Circuit]| {
+            let result = map_circuits(circuits, &arch).unwrap();
+            let mut ids: Vec<_> = (result.mappings.iter())
+                .map(|m| (m.circuit_id, m.logical_ram_id, m.group_id))
+                .collect();
+            ids.sort();
+            ids
+        };
+        let mut circuits = mixed_circuits();
+        // RAM ids out of order, and two ROMs that share a block
+        circuits[2].memories.reverse();
+        circuits[4].memories.push(mem(11, MemMode::Rom, 512, 8));
+        circuits[4].memories.push(mem(10, MemMode::Rom, 512, 8));
+        let forward = ids(&circuits);
+        circuits.reverse();
+        circuits.swap(0, 3);
+        assert_eq!(ids(&circuits), forward);
+        assert!(forward.contains(&(4, 11, 10)));
+        assert!(forward.contains(&(4, 10, 10)));
+    }
+
+    #[test]
+    fn huge_and_negative_ids_map() {
+        let rams = || {
+            vec![
+                mem(100_000, MemMode::Rom, 512, 8),
+                mem(-3, MemMode::Rom, 512, 8),
+                mem(i32::MAX, MemMode::SinglePort, 1024, 16),
+            ]
+        };
+        let circuits = [
+            circuit(21_475, 100, rams()),
+            circuit(-1, 100, rams()),
+            circuit(i32::MAX, 100, rams()),
+        ];
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        assert_eq!(result.mappings.len(), 9);
+        for m in &result.mappings {
+            // the two ROMs share under the smaller id in each circuit
+            let expected = if m.logical_ram_id == i32::MAX {
+                i32::MAX
+            } else {
+                -3
+            };
+            assert_eq!(m.group_id, expected);
+        }
+        // each circuit's shared block is counted once
+        assert_eq!(result.m8k_blocks, 3 * 3);
+        let again = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        assert_eq!(again.mappings, result.mappings);
+    }
+
+    #[test]
+    fn equal_group_ids_in_two_circuits_are_not_a_shared_block() {
+        let arch = ArchConfig::default();
+        let configs = arch.phys_configs();
+        // as in local_search_beats_greedy_on_total_area, twice over: both circuits have
+        // groups 0 to 3, each of a single RAM, so both may move RAMs off M128K
+        let rams = || {
+            (0..4)
+                .map(|r| mem(r, MemMode::SinglePort, 16384, 8))
+                .collect()
+        };
+        let circuits = [circuit(0, 100, rams()), circuit(1, 100, rams())];
+        let mut result = map_circuits(&circuits, &arch).unwrap();
+        let total_area = |r: &CircuitResult| crate::utils::compute_total_area(&circuits, r, &arch);
+        local_search(&circuits, &mut result, &configs, 10, None, total_area);
+        for id in [0, 1] {
+            let moved = (result.mappings.iter())
+                .filter(|m| m.circuit_id == id && m.phys_type == PhysType::Ram8K)
+                .count();
+            assert!(moved > 0, "circuit {} kept every RAM on M128K", id);
+        }
+
+        // and the incremental totals count both circuits' M128Ks
+        let full = map_circuits(&circuits, &arch).unwrap();
+        assert_eq!(full.m128k_blocks, 8);
+        let incremental = map_circuits_incremental(&full, &circuits, &[1], &arch).unwrap();
+        assert_eq!(incremental.m128k_blocks, 8);
+    }
+
+    #[test]
+    fn wider_sdp_limit_helps_simple_dual_port_only() {
+        let cfg = PhysConfig {
+            max_width_sdp: 64,
+            ..PHYS_RAM1.clone()
+        };
+        let shape = |mode, cfg: &PhysConfig| {
+            let ram = mem(0, mode, 128, 64);
+            let (m, cost) = best_mapping_for_phys_type(0, &ram, 0, cfg, &mut 0).unwrap();
+            (m.phys_width, m.phys_blocks, cost)
+        };
+        // the default keeps SDP at the single-port width
+        let (width, blocks, narrow_cost) = shape(MemMode::SimpleDualPort, &PHYS_RAM1);
+        assert_eq!((width, blocks), (32, 2));
+        let (width, blocks, wide_cost) = shape(MemMode::SimpleDualPort, &cfg);
+        assert_eq!((width, blocks), (64, 1));
+        assert!(wide_cost < narrow_cost);
+        // single-port and TDP RAMs keep their own limits
+        assert_eq!(shape(MemMode::SinglePort, &cfg).0, 32);
+        assert_eq!(shape(MemMode::TrueDualPort, &cfg).0, 16);
+    }
+
+    #[test]
+    fn sensitivity_of_a_zero_coefficient_is_finite() {
+        let arch = ArchConfig {
+            ram2_area: BramAreaModel {
+                per_bit: 0.0,
+                ..BramAreaModel::DEFAULT
+            },
+            ..ArchConfig::default()
+        };
+        let configs = arch.phys_configs();
+        let params = GeomeanParams::for_model(&arch, AreaModel::Unified);
+        let geom_blocks = [
+            (arch.ram1_bits, arch.max_width_ram1, arch.ram1_area),
+            (arch.ram2_bits, arch.max_width_ram2, arch.ram2_area),
+        ];
+        let circuits = mixed_circuits();
+        let (baseline, report) = cost_sensitivity(
+            &circuits,
+            &configs,
+            &MapOptions::default(),
+            &params,
+            geom_blocks,
+            0.01,
+        )
+        .unwrap();
+        for entry in &report {
+            assert!(
+                entry.gradient.is_finite(),
+                "{} gradient {}",
+                entry.constant.name(),
+                entry.gradient
+            );
+        }
+        let per_bit = |t| {
+            (report.iter())
+                .find(|s| matches!(s.constant, CostConstant::BramPerBit(u) if u == t))
+                .unwrap()
+        };
+        // each type reports its own coefficient, not whichever type came last
+        assert_eq!(per_bit(PhysType::Ram8K).value, 5.0);
+        let m128k = per_bit(PhysType::Ram128K);
+        assert_eq!(m128k.value, 0.0);
+        // raised by 0.01 per bit it still moves the mapping, and so the geomean
+        assert_ne!(m128k.geomean, baseline);
+        assert_ne!(m128k.gradient, 0.0);
+    }
+}
+
+#[cfg(all(test, feature = "mmap"))]
+mod mmap_tests {
+    use super::*;
+
+    #[test]
+    fn mmap_reader_matches_the_buffered_reader() {
+        let dir = tempfile::tempdir().unwrap();
+        let lbs = dir.path().join("logic_block_count.txt");
+        let rams = dir.path().join("logical_rams.txt");
+        std::fs::write(&lbs, "Circuit\tLogic blocks\n0\t500\n1\t300\n\n2\t200\n").unwrap();
+        std::fs::write(
+            &rams,
+            "Num_Circuits 3\nCircuit\tRamID\tMode\tDepth\tWidth\n\
+             1\t0\tROM\t1024\t16\n\
+             0\t0\tSimpleDualPort\t45\t12\n\
+             0\t1\tTrueDualPort\t4Ki\t32\n\
+             2\t0\tSinglePort\t-4\t8\n\
+             2\t1\t3\t2048\t16\n",
+        )
+        .unwrap();
+        let (lbs, rams) = (lbs.to_str().unwrap(), rams.to_str().unwrap());
+
+        let opts = ReadOptions::default();
+        let (buffered, buffered_warnings) = read_data(lbs, rams, &opts).unwrap();
+        let (mapped, mapped_warnings) = read_data_mmap(lbs, rams, &opts).unwrap();
+        assert_eq!(mapped, buffered);
+        assert_eq!(format!("{:?}", mapped), format!("{:?}", buffered));
+        // the negative depth is dropped by both, with the same warning
+        assert_eq!(mapped_warnings.len(), 1);
+        assert_eq!(
+            format!("{:?}", mapped_warnings),
+            format!("{:?}", buffered_warnings)
+        );
+    }
+}
@@ -1,670 +1,692 @@
-#![allow(dead_code)]
 use std::collections::HashMap;
-use std::f64;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
-use std::time::Instant;
-mod utils;
-
-pub const AVG_LB_AREA: f64 = (35000.0 + 40000.0) / 2.0;
-use crate::utils::{compute_geometric_area, compute_total_area, write_csv, write_mappings};
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum MemMode {
-    Rom,
-    SinglePort,
-    SimpleDualPort,
-    TrueDualPort,
-}
-
-impl MemMode {
-    fn from_str(s: &str) -> Option<Self> {
-        match s {
-            "ROM" => Some(MemMode::Rom),
-            "SinglePort" => Some(MemMode::SinglePort),
-            "SimpleDualPort" => Some(MemMode::SimpleDualPort),
-            "TrueDualPort" => Some(MemMode::TrueDualPort),
-            _ => None,
-        }
-    }
-    fn as_str(&self) -> &'static str {
-        match self {
-            MemMode::Rom => "ROM",
-            MemMode::SinglePort => "SinglePort",
-            MemMode::SimpleDualPort => "SimpleDualPort",
-            MemMode::TrueDualPort => "TrueDualPort",
-        }
-    }
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum PhysType {
-    Lutram,
-    Ram8K,
-    Ram128K,
-}
-impl PhysType {
-    fn type_id(&self) -> i32 {
-        match self {
-            PhysType::Lutram => 1,
-            PhysType::Ram8K => 2,
-            PhysType::Ram128K => 3,
-        }
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct PhysConfig {
-    phys_type: PhysType,
-    bits: i32,
-    max_width_non_tdp: i32,
-    max_width_tdp: i32,
-}
-
-// Default templates
-pub const PHYS_LUTRAM: PhysConfig = PhysConfig {
-    phys_type: PhysType::Lutram,
-    bits: 64 * 10,
-    max_width_non_tdp: 20,
-    max_width_tdp: 0,
-};
-
-pub const PHYS_RAM1: PhysConfig = PhysConfig {
-    phys_type: PhysType::Ram8K,
-    bits: 8192,
-    max_width_non_tdp: 32,
-    max_width_tdp: 16,
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use rapid_map::error::RapidMapError;
+#[cfg(not(feature = "mmap"))]
+use rapid_map::read_data;
+#[cfg(feature = "mmap")]
+use rapid_map::read_data_mmap;
+use rapid_map::utils::{
+    AreaModel, EffectiveConfig, GeomeanParams, RunManifest, RunSummary, arch_differences,
+    area_overage, bits_per_area, chip_lb_requirement, circuit_usage,
+    compute_geometric_area_in_memory, compute_stats, compute_total_area, device_shortfalls,
+    geometric_area_from_result, input_file_info, lb_requirements_from_result, parse_arch_toml,
+    parse_budgets, parse_device, parse_shapes, parse_sweep, per_type_block_geomeans,
+    print_explanation, print_stats, shape_histogram, top_cost_mappings, write_csv,
+    write_logical_rams, write_manifest, write_mappings, write_mappings_bin, write_mappings_csv,
+    write_mappings_json, write_mappings_long_csv, write_mappings_pretty, write_shared_groups,
+    write_summary,
 };
-
-pub const PHYS_RAM2: PhysConfig = PhysConfig {
-    phys_type: PhysType::Ram128K,
-    bits: 128 * 1024,
-    max_width_non_tdp: 128,
-    max_width_tdp: 64,
+use rapid_map::{
+    ArchConfig, BramAreaModel, CircuitResult, MapOptions, MemMode, OrphanRamPolicy, PHYS_RAM1,
+    PHYS_RAM2, PhysType, ReadOptions, SharingConfig, assign_ram, balance_types, block_ram_area,
+    check_input_shapes, cost_sensitivity, cross_circuit_share_report, global_objective_pass,
+    impossible_tdp_memories, local_search, map_circuits, read_hints, type_ablation,
+    unmappable_memories,
 };
 
-#[derive(Debug)]
-pub struct Memory {
-    ram_id: i32,
-    mode: MemMode,
-    depth: i32,
-    width: i32,
-}
-
-#[derive(Debug)]
-pub struct Circuit {
-    id: i32,
-    logic_blocks: i32,
-    memories: Vec<Memory>,
-}
-
-#[derive(Clone, Debug)]
-pub struct RamMapping {
-    circuit_id: i32,
-    logical_ram_id: i32,
-    extra_luts: i32,
-    logical_width: i32,
-    logical_depth: i32,
-    group_id: i32,
-    series: i32,
-    parallel: i32,
-    phys_type: PhysType,
-    mode: MemMode,
-    phys_width: i32,
-    phys_depth: i32,
-    phys_blocks: i32,
-}
-#[derive(Clone, Debug)]
-pub struct CircuitResult {
-    mappings: Vec<RamMapping>,
-    extra_luts: i32,
-    lutram_blocks: i32,
-    m8k_blocks: i32,
-    m128k_blocks: i32,
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    // with --threads the whole run, and so every parallel mapping, uses its own pool
+    let result = match thread_pool(&args) {
+        Ok(Some(pool)) => pool.install(|| run(&args)),
+        Ok(None) => run(&args),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
 }
 
-//applying physical RAM sharing
-fn apply_sharing(
-    mappings: &mut Vec<RamMapping>,
-    m8k_cfg: Option<&PhysConfig>,
-    m128k_cfg: Option<&PhysConfig>,
-    m8k_blocks: &mut i32,
-    m128k_blocks: &mut i32,
-) {
-    if let Some(cfg) = m8k_cfg {
-        share_type(mappings, cfg, m8k_blocks);
-    }
-    if let Some(cfg) = m128k_cfg {
-        share_type(mappings, cfg, m128k_blocks);
-    }
+// the --threads pool, or None to use the global rayon pool
+fn thread_pool(args: &[String]) -> Result<Option<rayon::ThreadPool>, RapidMapError> {
+    let Some(idx) = args.iter().position(|s| s == "--threads") else {
+        return Ok(None);
+    };
+    let threads = match args.get(idx + 1).and_then(|v| v.parse::<usize>().ok()) {
+        Some(n) if n >= 1 => n,
+        _ => {
+            return Err(RapidMapError::Config(
+                "--threads expects a positive integer".into(),
+            ));
+        }
+    };
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map(Some)
+        .map_err(|e| RapidMapError::Config(format!("cannot start {} threads: {}", threads, e)))
 }
-//function to share BRAMs
-fn share_type(mappings: &mut [RamMapping], cfg: &PhysConfig, total_blocks: &mut i32) {
-    let phys_bits = cfg.bits;
-    let max_tdp_width = cfg.max_width_tdp;
 
-    let mut candidates: Vec<(usize, i32)> = Vec::new();
+// the whole command line run, args[0] being the program name
+fn run(args: &[String]) -> Result<(), RapidMapError> {
+    let start = Instant::now();
 
-    for (idx, m) in mappings.iter().enumerate() {
-        if m.phys_type != cfg.phys_type {
-            continue;
-        }
-        if m.mode != MemMode::Rom && m.mode != MemMode::SinglePort {
-            continue;
+    // --arch gives the starting point; -p values then override it field by field
+    let arch_file = args
+        .iter()
+        .position(|s| s == "--arch")
+        .and_then(|idx| args.get(idx + 1))
+        .cloned();
+    let file_arch = match &arch_file {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| RapidMapError::file(path, e))?;
+            Some(
+                parse_arch_toml(&text)
+                    .map_err(|e| RapidMapError::Config(format!("--arch {}: {}", path, e)))?,
+            )
         }
-        if m.phys_blocks != 1 {
-            continue;
-        }
-        if max_tdp_width > 0 && m.phys_width > max_tdp_width {
-            continue;
+        None => None,
+    };
+    let mut arch = file_arch.clone().unwrap_or_default();
+    // read up front so a bad file fails before the mapping run
+    let compare_arch = match args.iter().position(|s| s == "--compare-arch") {
+        Some(idx) => {
+            let Some(path) = args.get(idx + 1) else {
+                return Err(RapidMapError::Config(
+                    "--compare-arch expects a TOML architecture file".into(),
+                ));
+            };
+            let text = std::fs::read_to_string(path).map_err(|e| RapidMapError::file(path, e))?;
+            let other = parse_arch_toml(&text)
+                .map_err(|e| RapidMapError::Config(format!("--compare-arch {}: {}", path, e)))?;
+            Some((path.clone(), other))
         }
+        None => None,
+    };
 
-        let logical_bits = m.logical_width * m.logical_depth;
-        if logical_bits <= 0 || logical_bits >= phys_bits {
-            continue;
-        }
-        candidates.push((idx, logical_bits));
+    let report_cross_circuit = args.iter().any(|s| s == "--cross-circuit-report");
+    let count_candidates = args.iter().any(|s| s == "--count-candidates");
+    let show_stats = args.iter().any(|s| s == "--stats");
+    let report_wasted_lbs = args.iter().any(|s| s == "--wasted-lbs");
+    let pretty = args.iter().any(|s| s == "--pretty");
+    let fractional_sharing = args.iter().any(|s| s == "--fractional-sharing");
+    let check_determinism = args.iter().any(|s| s == "--check-determinism");
+    let report_binding = args.iter().any(|s| s == "--binding-constraint");
+    let stream_decisions = args.iter().any(|s| s == "--stream-decisions");
+    let allow_split = args.iter().any(|s| s == "--allow-split");
+    let allow_lut_memory = args.iter().any(|s| s == "--allow-lut-memory");
+    let progress = args.iter().any(|s| s == "--progress");
+    let ablation = args.iter().any(|s| s == "--ablation");
+    let sensitivity = args.iter().any(|s| s == "--sensitivity");
+    let strict = args.iter().any(|s| s == "--strict");
+    let type_geomeans = args.iter().any(|s| s == "--type-geomeans");
+    let parallel_memories = args.iter().any(|s| s == "--parallel-memories");
+    let include_max_width = args.iter().any(|s| s == "--include-maxwidth-candidate");
+    let dry_run = args.iter().any(|s| s == "--dry-run");
+    // these only write files
+    if dry_run
+        && let Some(flag) = [
+            "--export-logical",
+            "--manifest",
+            "--summary",
+            "--mapped-bin",
+            "--mapping-csv",
+            "--groups",
+            "--sqlite",
+        ]
+        .into_iter()
+        .find(|flag| args.iter().any(|s| s == flag))
+    {
+        return Err(RapidMapError::Config(format!(
+            "--dry-run writes no files, so it can't be combined with {}",
+            flag
+        )));
     }
-
-    let mut already_shared = vec![false; mappings.len()];
-    for i in 0..candidates.len() {
-        let (idx_i, bits_i) = candidates[i];
-        if already_shared[idx_i] {
-            continue;
-        }
-
-        for i in 0..candidates.len() {
-            let (idx_i, _bits_i) = candidates[i];
-            if already_shared[idx_i] {
-                continue;
-            }
-
-            for j in (i + 1)..candidates.len() {
-                let (idx_j, bits_j) = candidates[j];
-                if already_shared[idx_j] {
-                    continue;
-                }
-                if mappings[idx_i].circuit_id != mappings[idx_j].circuit_id {
-                    continue;
-                }
-
-                // NEW: only share if physical shape is identical. Avoids id mismatch when mapping
-                if mappings[idx_i].phys_width != mappings[idx_j].phys_width
-                    || mappings[idx_i].phys_depth != mappings[idx_j].phys_depth
-                    || mappings[idx_i].series != mappings[idx_j].series
-                    || mappings[idx_i].parallel != mappings[idx_j].parallel
-                {
-                    continue;
-                }
-                //checking depth so it does not exceed
-                let total_phys_depth = mappings[idx_i].phys_depth * mappings[idx_i].series;
-
-                // how much depth the two logical RAMs would collectively need
-                let combined_logical_depth =
-                    mappings[idx_i].logical_depth + mappings[idx_j].logical_depth;
-
-                if combined_logical_depth > total_phys_depth {
-                    continue;
-                }
-
-                if bits_i + bits_j == phys_bits {
-                    already_shared[idx_i] = true;
-                    already_shared[idx_j] = true;
-
-                    let gid = mappings[idx_i].group_id;
-
-                    mappings[idx_i].mode = MemMode::TrueDualPort;
-                    mappings[idx_j].mode = MemMode::TrueDualPort;
-                    mappings[idx_j].group_id = gid;
-
-                    *total_blocks -= 1;
-                    break;
+    let mut forbidden: Vec<(PhysType, MemMode)> = Vec::new();
+    if let Some(idx) = args.iter().position(|s| s == "--forbid") {
+        let spec = args.get(idx + 1).map(String::as_str).unwrap_or("");
+        for item in spec.split(',') {
+            let pair = item.split_once(':').and_then(|(t, mode)| {
+                Some((
+                    PhysType::from_name(t.trim())?,
+                    MemMode::from_name(mode.trim())?,
+                ))
+            });
+            match pair {
+                Some(pair) => forbidden.push(pair),
+                None => {
+                    return Err(RapidMapError::Config(format!(
+                        "--forbid expects type:Mode pairs such as m128k:ROM, got '{}'",
+                        item
+                    )));
                 }
             }
         }
     }
-}
-
-//reading data with error-handling
-fn read_data(logic_block_file: &str, logic_rams_file: &str) -> io::Result<Vec<Circuit>> {
-    let file = File::open(logic_block_file)?;
-    let reader = BufReader::new(file);
-    let mut circuits_map: HashMap<i32, Circuit> = HashMap::new();
-
-    for (line_idx, line_res) in reader.lines().enumerate() {
-        let line = line_res?;
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
-        }
-        if line_idx == 0 {
-            //skipping first line
-            continue;
-        }
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 2 {
-            continue;
-        }
-        let circuit_id: i32 = match parts[0].parse() {
-            Ok(v) => v,
-            Err(_) => continue,
-        };
-        let logic_blocks: i32 = match parts[1].parse() {
-            Ok(v) => v,
-            Err(_) => continue,
-        };
-
-        circuits_map.insert(
-            circuit_id,
-            Circuit {
-                id: circuit_id,
-                logic_blocks,
-                memories: Vec::new(),
-            },
-        );
-    }
-
-    let file = File::open(logic_rams_file)?;
-    let reader = BufReader::new(file);
-
-    for (line_idx, line_res) in reader.lines().enumerate() {
-        let line = line_res?;
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
-        }
-        if line_idx < 2 {
-            continue;
-        }
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 5 {
-            continue;
-        }
-
-        let circuit_id: i32 = match parts[0].parse() {
-            Ok(v) => v,
-            Err(_) => {
-                eprintln!("Bad circuit id: {}", parts[0]);
-                continue;
+    let top_cost = match args.iter().position(|s| s == "--top-cost") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<usize>().ok()) {
+            Some(n) => Some(n),
+            None => {
+                return Err(RapidMapError::Config(
+                    "--top-cost expects a non-negative integer".into(),
+                ));
             }
-        };
-        let ram_id: i32 = match parts[1].parse() {
-            Ok(v) => v,
-            Err(_) => {
-                eprintln!("Bad ram id: {}", parts[1]);
-                continue;
+        },
+        None => None,
+    };
+    let seed = match args.iter().position(|s| s == "--seed") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<u64>().ok()) {
+            Some(seed) => Some(seed),
+            None => {
+                return Err(RapidMapError::Config(
+                    "--seed expects a non-negative integer".into(),
+                ));
             }
-        };
-
-        let mode_str = parts[2];
-        let mode = match MemMode::from_str(mode_str) {
-            Some(m) => m,
+        },
+        None => None,
+    };
+    let explain = match args.iter().position(|s| s == "--explain") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<i32>().ok()) {
+            Some(id) => Some(id),
             None => {
-                eprintln!("Unknown RAM mode: {}", mode_str);
-                continue;
+                return Err(RapidMapError::Config(
+                    "--explain expects a circuit id".into(),
+                ));
             }
-        };
-
-        let depth: i32 = match parts[3].parse() {
-            Ok(v) => v,
-            Err(_) => {
-                eprintln!("Bad depth: {}", parts[3]);
-                continue;
+        },
+        None => None,
+    };
+    let sweep_fractions = match args.iter().position(|s| s == "--sweep-fraction") {
+        Some(idx) => match args.get(idx + 1).map(|s| parse_sweep(s)) {
+            Some(Ok(fractions)) => Some(fractions),
+            Some(Err(e)) => {
+                return Err(RapidMapError::Config(format!("--sweep-fraction: {}", e)));
             }
-        };
-        let width: i32 = match parts[4].parse() {
-            Ok(v) => v,
-            Err(_) => {
-                eprintln!("Bad width: {}", parts[4]);
-                continue;
+            None => {
+                return Err(RapidMapError::Config(
+                    "--sweep-fraction expects start:stop:step".into(),
+                ));
             }
-        };
-
-        let mem = Memory {
-            ram_id,
-            mode,
-            depth,
-            width,
-        };
-
-        let entry = circuits_map.entry(circuit_id).or_insert(Circuit {
-            id: circuit_id,
-            logic_blocks: 0,
-            memories: Vec::new(),
-        });
-        entry.memories.push(mem);
-    }
-
-    let mut circuits: Vec<Circuit> = circuits_map.into_values().collect();
-    circuits.sort_by_key(|c| c.id);
-    Ok(circuits)
-}
-
-fn block_ram_area(bits: i32, max_width: i32) -> f64 {
-    let bits_f = bits as f64;
-    9000.0 + 5.0 * bits_f + 90.0 * bits_f.sqrt() + 600.0 * 2.0 * (max_width as f64)
-}
-fn decoder_luts(s: i32) -> i32 {
-    if s <= 1 {
-        0
-    } else if s == 2 {
-        1
-    } else {
-        s
-    }
-}
-
-fn mux_luts(s: i32, width: i32) -> i32 {
-    if s <= 1 {
-        return 0;
+        },
+        None => None,
+    };
+    #[cfg(not(feature = "sqlite"))]
+    if args.iter().any(|s| s == "--sqlite") {
+        return Err(RapidMapError::Config(
+            "--sqlite needs a build with --features sqlite".into(),
+        ));
     }
-    let mut n = s;
-    let mut total_nodes = 0;
-    while n > 1 {
-        let level_nodes = (n + 3) / 4;
-        total_nodes += level_nodes;
-        n = level_nodes;
+    #[cfg(feature = "sqlite")]
+    let sqlite_file = args
+        .iter()
+        .position(|s| s == "--sqlite")
+        .and_then(|idx| args.get(idx + 1))
+        .cloned();
+    let logical_out_file = args
+        .iter()
+        .position(|s| s == "--export-logical")
+        .and_then(|idx| args.get(idx + 1))
+        .cloned();
+    let manifest_file = args
+        .iter()
+        .position(|s| s == "--manifest")
+        .and_then(|idx| args.get(idx + 1))
+        .cloned();
+    let summary_file = args
+        .iter()
+        .position(|s| s == "--summary")
+        .and_then(|idx| args.get(idx + 1))
+        .cloned();
+    let lut_budget = match args.iter().position(|s| s == "--lut-budget") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<i32>().ok()) {
+            Some(n) if n >= 0 => Some(n),
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--lut-budget expects a non-negative integer".into(),
+                ));
+            }
+        },
+        None => None,
+    };
+    let depth_waste_weight = match args.iter().position(|s| s == "--depth-waste-weight") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<f64>().ok()) {
+            Some(w) if w >= 0.0 => w,
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--depth-waste-weight expects a non-negative number".into(),
+                ));
+            }
+        },
+        None => 0.0,
+    };
+    let max_series = match args.iter().position(|s| s == "--max-series") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<i32>().ok()) {
+            Some(n) if n >= 1 => Some(n),
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--max-series expects a positive integer".into(),
+                ));
+            }
+        },
+        None => None,
+    };
+    // (type, limit) pairs; a type of None applies to every type
+    let mut max_parallel: Vec<(Option<PhysType>, i32)> = Vec::new();
+    if let Some(idx) = args.iter().position(|s| s == "--max-parallel") {
+        let spec = args.get(idx + 1).map(String::as_str).unwrap_or("");
+        for item in spec.split(',') {
+            let entry = match item.split_once('=') {
+                Some((t, n)) => PhysType::from_name(t.trim())
+                    .map(Some)
+                    .zip(n.trim().parse().ok()),
+                None => item.trim().parse().ok().map(|n| (None, n)),
+            };
+            match entry {
+                Some((t, n)) if n >= 1 => max_parallel.push((t, n)),
+                _ => {
+                    return Err(RapidMapError::Config(format!(
+                        "--max-parallel expects a positive integer or type=N pairs such as m8k=8, got '{}'",
+                        item
+                    )));
+                }
+            }
+        }
     }
-    let luts_per_bit = total_nodes;
-    width * luts_per_bit
-}
-
-fn mapping_cost(mapping: &RamMapping, cfg: &PhysConfig) -> f64 {
-    let lb_for_extra_luts = (mapping.extra_luts + 9) / 10;
-
-    let base_area = match cfg.phys_type {
-        PhysType::Lutram => {
-            let lb_total = mapping.phys_blocks + lb_for_extra_luts;
-            (lb_total as f64) * AVG_LB_AREA
+    // (type, width) pairs for SimpleDualPort RAMs
+    let mut max_width_sdp: Vec<(PhysType, i32)> = Vec::new();
+    if let Some(idx) = args.iter().position(|s| s == "--max-width-sdp") {
+        let spec = args.get(idx + 1).map(String::as_str).unwrap_or("");
+        for item in spec.split(',') {
+            let entry = item
+                .split_once('=')
+                .and_then(|(t, w)| PhysType::from_name(t.trim()).zip(w.trim().parse().ok()));
+            match entry {
+                Some((t, w)) if w >= 1 => max_width_sdp.push((t, w)),
+                _ => {
+                    return Err(RapidMapError::Config(format!(
+                        "--max-width-sdp expects type=W pairs such as m8k=64, got '{}'",
+                        item
+                    )));
+                }
+            }
         }
-        PhysType::Ram8K | PhysType::Ram128K => {
-            let lb_area = (lb_for_extra_luts as f64) * AVG_LB_AREA;
-            let max_width = match mapping.mode {
-                MemMode::TrueDualPort => cfg.max_width_tdp,
-                _ => cfg.max_width_non_tdp,
-            };
-            let bram_area_per_block = block_ram_area(cfg.bits, max_width);
-            lb_area + (mapping.phys_blocks as f64) * bram_area_per_block
+    }
+    let decoder_table = match args.iter().position(|s| s == "--decoder-luts") {
+        Some(idx) => {
+            let table = args.get(idx + 1).and_then(|spec| {
+                spec.split(',')
+                    .map(|v| v.trim().parse::<i32>().ok().filter(|&n| n >= 0))
+                    .collect::<Option<Vec<i32>>>()
+            });
+            if table.is_none() {
+                return Err(RapidMapError::Config(
+                    "--decoder-luts expects comma-separated LUT counts for s = 1, 2, ...".into(),
+                ));
+            }
+            table
         }
+        None => None,
     };
-
-    let logical_bits = (mapping.logical_width as i64 * mapping.logical_depth as i64) as f64;
-    let phys_bits = (mapping.phys_blocks as i64 * cfg.bits as i64) as f64;
-    let u = if phys_bits > 0.0 {
-        (logical_bits / phys_bits).max(0.0).min(1.0)
-    } else {
-        1.0
+    let long_csv = match args.iter().position(|s| s == "--format") {
+        Some(idx) => match args.get(idx + 1).map(String::as_str) {
+            Some("text") => false,
+            Some("long-csv") => true,
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--format expects text or long-csv".into(),
+                ));
+            }
+        },
+        None => false,
     };
-
-    let penalty_strength = match cfg.phys_type {
-        PhysType::Lutram => 1.6,
-        PhysType::Ram8K => 2.2,
-        PhysType::Ram128K => 5.0,
+    let mapped_json = match args.iter().position(|s| s == "--mapped-format") {
+        Some(idx) => match args.get(idx + 1).map(String::as_str) {
+            Some("text") => false,
+            Some("json") => true,
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--mapped-format expects text or json".into(),
+                ));
+            }
+        },
+        None => false,
     };
-    let penalty_factor = 10.0 + penalty_strength * (10.0 - u);
-    base_area * penalty_factor
-}
-fn best_mapping_for_phys_type(
-    circuit_id: i32,
-    mem: &Memory,
-    group_id: i32,
-    cfg: &PhysConfig,
-) -> Option<(RamMapping, f64)> {
-    if mem.mode == MemMode::TrueDualPort && cfg.max_width_tdp == 0 {
-        return None;
-    }
-    let max_width = match mem.mode {
-        MemMode::TrueDualPort => cfg.max_width_tdp,
-        _ => cfg.max_width_non_tdp,
-    };
-    if max_width <= 0 {
-        return None;
-    }
-    let width_candidates: Vec<i32> = match cfg.phys_type {
-        PhysType::Lutram => vec![10, 20],
-        _ => {
-            let mut v = Vec::new();
-            let mut w = 1;
-            while w <= max_width {
-                v.push(w);
-                w *= 2;
+    let share_granularity = match args.iter().position(|s| s == "--share-granularity") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<i32>().ok()) {
+            Some(g) if g >= 1 => Some(g),
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--share-granularity expects a positive integer (e.g. 4 for quarter blocks)"
+                        .into(),
+                ));
             }
-            v
-        }
+        },
+        None => None,
     };
-
-    let mut best: Option<(RamMapping, f64)> = None;
-    for &w_phys in &width_candidates {
-        if w_phys > max_width {
-            continue;
-        }
-        let d_phys = match cfg.phys_type {
-            PhysType::Lutram => {
-                if w_phys == 10 {
-                    64
-                } else if w_phys == 20 {
-                    32
-                } else {
-                    continue;
+    let max_sharers = match args.iter().position(|s| s == "--max-sharers") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<usize>().ok()) {
+            Some(n) if n >= 2 => n,
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--max-sharers expects an integer of at least 2".into(),
+                ));
+            }
+        },
+        None => SharingConfig::default().max_sharers,
+    };
+    let share_modes = match args.iter().position(|s| s == "--share-modes") {
+        Some(idx) => {
+            let modes = args.get(idx + 1).and_then(|spec| {
+                spec.split(',')
+                    .map(|s| MemMode::from_name(s.trim()))
+                    .collect::<Option<Vec<MemMode>>>()
+            });
+            match modes {
+                Some(modes) if !modes.contains(&MemMode::TrueDualPort) => modes,
+                _ => {
+                    return Err(RapidMapError::Config(
+                        "--share-modes expects a list of ROM, SinglePort, SimpleDualPort".into(),
+                    ));
                 }
             }
+        }
+        None => SharingConfig::default().modes,
+    };
+    let lutram_area = match args.iter().position(|s| s == "--lutram-area") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<f64>().ok()) {
+            Some(a) if a >= 0.0 => Some(a),
             _ => {
-                if cfg.bits % w_phys != 0 {
-                    continue;
+                return Err(RapidMapError::Config(
+                    "--lutram-area expects a non-negative area".into(),
+                ));
+            }
+        },
+        None => None,
+    };
+    let required_type = match args.iter().position(|s| s == "--require-type") {
+        Some(idx) => match args.get(idx + 1).and_then(|s| PhysType::from_name(s)) {
+            Some(t) => Some(t),
+            None => {
+                return Err(RapidMapError::Config(
+                    "--require-type expects one of lutram, m8k, m128k".into(),
+                ));
+            }
+        },
+        None => None,
+    };
+    let circuit_filter = match args.iter().position(|s| s == "--circuits") {
+        Some(idx) => {
+            let spec = args.get(idx + 1).map(String::as_str).unwrap_or("");
+            let ids: Result<Vec<i32>, _> = spec.split(',').map(|s| s.trim().parse()).collect();
+            match ids {
+                Ok(ids) => Some(ids),
+                Err(_) => {
+                    return Err(RapidMapError::Config(format!(
+                        "--circuits expects a comma-separated list of circuit ids, got '{}'",
+                        spec
+                    )));
                 }
-                cfg.bits / w_phys
             }
-        };
-        if d_phys <= 0 {
-            continue;
         }
-        let mut p = mem.width / w_phys;
-        if mem.width % w_phys != 0 {
-            p += 1;
+        None => None,
+    };
+    let mut type_priority = PhysType::ALL.to_vec();
+    if let Some(idx) = args.iter().position(|s| s == "--type-priority") {
+        let spec = args.get(idx + 1).map(String::as_str).unwrap_or("");
+        let mut listed = Vec::new();
+        for name in spec.split(',') {
+            match PhysType::from_name(name.trim()) {
+                Some(t) if !listed.contains(&t) => listed.push(t),
+                _ => {
+                    return Err(RapidMapError::Config(format!(
+                        "--type-priority expects distinct types from lutram,m8k,m128k,ram3,..., got '{}'",
+                        spec
+                    )));
+                }
+            }
         }
-
-        let mut s = mem.depth / d_phys;
-        if mem.depth % d_phys != 0 {
-            s += 1;
+        // unlisted types keep their default relative order after the listed ones
+        type_priority.retain(|t| !listed.contains(t));
+        listed.append(&mut type_priority);
+        type_priority = listed;
+    }
+    let area_model = match args.iter().position(|s| s == "--area-model") {
+        Some(idx) => match args.get(idx + 1).map(String::as_str) {
+            Some("legacy") => AreaModel::Legacy,
+            Some("unified") => AreaModel::Unified,
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--area-model expects legacy or unified".into(),
+                ));
+            }
+        },
+        None => AreaModel::Unified,
+    };
+    let mut read_opts = ReadOptions::default();
+    if args.iter().any(|s| s == "--no-header") {
+        read_opts.logic_blocks_header = 0;
+        read_opts.logical_rams_header = 0;
+    }
+    // an explicit count wins over --no-header
+    for (flag, header) in [
+        ("--logic-blocks-header", &mut read_opts.logic_blocks_header),
+        ("--logical-rams-header", &mut read_opts.logical_rams_header),
+    ] {
+        if let Some(idx) = args.iter().position(|s| s == flag) {
+            match args.get(idx + 1).and_then(|v| v.parse::<usize>().ok()) {
+                Some(n) => *header = n,
+                None => {
+                    return Err(RapidMapError::Config(format!(
+                        "{} expects a non-negative line count",
+                        flag
+                    )));
+                }
+            }
         }
-
-        if s <= 0 || p <= 0 {
-            continue;
+    }
+    read_opts.orphans = match args.iter().position(|s| s == "--on-orphan-ram") {
+        Some(idx) => match args.get(idx + 1).map(String::as_str) {
+            Some("warn") => OrphanRamPolicy::Warn,
+            Some("error") => OrphanRamPolicy::Error,
+            Some("create") => OrphanRamPolicy::Create,
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--on-orphan-ram expects warn, error or create".into(),
+                ));
+            }
+        },
+        None => OrphanRamPolicy::Warn,
+    };
+    let print_shape_hist = args.iter().any(|s| s == "--shape-hist");
+    let hints_file = args
+        .iter()
+        .position(|s| s == "--hints")
+        .and_then(|idx| args.get(idx + 1))
+        .cloned();
+    let mapped_bin_file = args
+        .iter()
+        .position(|s| s == "--mapped-bin")
+        .and_then(|idx| args.get(idx + 1))
+        .cloned();
+    let mapping_csv_file = args
+        .iter()
+        .position(|s| s == "--mapping-csv")
+        .and_then(|idx| args.get(idx + 1))
+        .cloned();
+    let groups_file = args
+        .iter()
+        .position(|s| s == "--groups")
+        .and_then(|idx| args.get(idx + 1))
+        .cloned();
+    let no_sharing = args.iter().any(|s| s == "--no-sharing");
+    let sharing_iterations = match args.iter().position(|s| s == "--sharing-iterations") {
+        Some(_) if no_sharing => {
+            return Err(RapidMapError::Config(
+                "--no-sharing can't be combined with --sharing-iterations".into(),
+            ));
         }
-        if s > 16 {
-            continue;
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<usize>().ok()) {
+            Some(n) if n >= 1 => n,
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--sharing-iterations expects a positive integer".into(),
+                ));
+            }
+        },
+        None if no_sharing => 0,
+        None => 1,
+    };
+    let mut shapes = [Vec::new(), Vec::new()];
+    for (flag, table) in ["--ram1-shapes", "--ram2-shapes"]
+        .iter()
+        .zip(shapes.iter_mut())
+    {
+        if let Some(idx) = args.iter().position(|s| s == flag) {
+            let spec = args.get(idx + 1).map(String::as_str).unwrap_or("");
+            *table = parse_shapes(spec).map_err(|e| {
+                RapidMapError::Config(format!("{} expects WxD,WxD,... ({})", flag, e))
+            })?;
         }
-        let mut extra_luts = decoder_luts(s) + mux_luts(s, mem.width);
-
-        if s > 1 && mem.mode == MemMode::TrueDualPort {
-            extra_luts *= 2;
+    }
+    let lutram_shapes = match args.iter().position(|s| s == "--lutram-shapes") {
+        Some(idx) => {
+            let spec = args.get(idx + 1).map(String::as_str).unwrap_or("");
+            Some(parse_shapes(spec).map_err(|e| {
+                RapidMapError::Config(format!("--lutram-shapes expects WxD,WxD,... ({})", e))
+            })?)
         }
-
-        let mapping = RamMapping {
-            circuit_id,
-            logical_ram_id: mem.ram_id,
-            extra_luts,
-            logical_width: mem.width,
-            logical_depth: mem.depth,
-            group_id,
-            series: s,
-            parallel: p,
-            phys_type: cfg.phys_type,
-            mode: mem.mode,
-            phys_width: w_phys,
-            phys_depth: d_phys,
-            phys_blocks: s * p,
-        };
-
-        let cost = mapping_cost(&mapping, cfg);
-
-        match &mut best {
-            None => best = Some((mapping, cost)),
-            Some((_, best_cost)) => {
-                if cost < *best_cost {
-                    best = Some((mapping, cost));
+        None => None,
+    };
+    let mut widths = [Vec::new(), Vec::new()];
+    for (flag, list) in ["--ram1-widths", "--ram2-widths"]
+        .iter()
+        .zip(widths.iter_mut())
+    {
+        if let Some(idx) = args.iter().position(|s| s == flag) {
+            let parsed = args.get(idx + 1).and_then(|spec| {
+                spec.split(',')
+                    .map(|v| v.trim().parse::<i32>().ok().filter(|&w| w > 0))
+                    .collect::<Option<Vec<i32>>>()
+            });
+            match parsed {
+                Some(v) => *list = v,
+                None => {
+                    return Err(RapidMapError::Config(format!(
+                        "{} expects comma-separated positive widths",
+                        flag
+                    )));
                 }
             }
         }
     }
-
-    best
-}
-
-// memory mapper
-fn choose_mapping_for_memory(
-    circuit_id: i32,
-    mem: &Memory,
-    group_id: i32,
-    has_lutram: bool,
-    has_m8k: bool,
-    has_m128k: bool,
-    m8k_cfg: &PhysConfig,
-    m128k_cfg: &PhysConfig,
-) -> RamMapping {
-    let mut best_mapping: Option<RamMapping> = None;
-    let mut best_cost = f64::INFINITY;
-
-    if has_lutram {
-        if let Some((m, cost)) = best_mapping_for_phys_type(circuit_id, mem, group_id, &PHYS_LUTRAM)
-        {
-            if cost < best_cost {
-                best_cost = cost;
-                best_mapping = Some(m);
+    let memory_limit = match args
+        .iter()
+        .position(|s| s == "--limit-memories-per-circuit")
+    {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<usize>().ok()) {
+            Some(n) => Some(n),
+            None => {
+                return Err(RapidMapError::Config(
+                    "--limit-memories-per-circuit expects a non-negative integer".into(),
+                ));
             }
-        }
-    }
-
-    if has_m8k {
-        if let Some((m, cost)) = best_mapping_for_phys_type(circuit_id, mem, group_id, m8k_cfg) {
-            if cost < best_cost {
-                best_cost = cost;
-                best_mapping = Some(m);
+        },
+        None => None,
+    };
+    let device = match args.iter().position(|s| s == "--device") {
+        Some(idx) => {
+            let spec = args.get(idx + 1).map(String::as_str).unwrap_or("");
+            match parse_device(spec) {
+                Ok(d) => Some(d),
+                Err(e) => {
+                    return Err(RapidMapError::Config(format!(
+                        "--device expects lbs=N,m8k=N,m128k=N ({})",
+                        e
+                    )));
+                }
             }
         }
-    }
-
-    if has_m128k {
-        if let Some((m, cost)) = best_mapping_for_phys_type(circuit_id, mem, group_id, m128k_cfg) {
-            if cost < best_cost {
-                //best_cost = cost;
-                best_mapping = Some(m);
+        None => None,
+    };
+    let max_area_budget = match args.iter().position(|s| s == "--max-area-budget") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<f64>().ok()) {
+            Some(b) if b > 0.0 && b.is_finite() => Some(b),
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--max-area-budget expects a positive number".into(),
+                ));
+            }
+        },
+        None => None,
+    };
+    let balance = match args.iter().position(|s| s == "--balance") {
+        Some(idx) => {
+            let spec = args.get(idx + 1).map(String::as_str).unwrap_or("");
+            match parse_budgets(spec) {
+                Ok(b) => Some(b),
+                Err(e) => {
+                    return Err(RapidMapError::Config(format!(
+                        "--balance expects type=N pairs such as m8k=N,m128k=N,ram3=N and ratio=R ({})",
+                        e
+                    )));
+                }
             }
         }
-    }
-
-    best_mapping.unwrap_or_else(|| {
-        panic!(
-            "No legal mapping for logical RAM {} in circuit {} under current memory config",
-            mem.ram_id, circuit_id
-        )
-    })
-}
-
-fn assign_ram(
-    circuits: &[Circuit],
-    has_lutram: bool,
-    has_m8k: bool,
-    has_m128k: bool,
-    m8k_bits: i32,
-    m128k_bits: i32,
-    max_width_ram1: i32,
-    max_width_ram2: i32,
-) -> CircuitResult {
-    // dynamic configs for both memories
-    let m8k_cfg = PhysConfig {
-        phys_type: PhysType::Ram8K,
-        bits: m8k_bits,
-        max_width_non_tdp: max_width_ram1,
-        max_width_tdp: max_width_ram1 / 2,
-    };
-    let m128k_cfg = PhysConfig {
-        phys_type: PhysType::Ram128K,
-        bits: m128k_bits,
-        max_width_non_tdp: max_width_ram2,
-        max_width_tdp: max_width_ram2 / 2,
-    };
-    let mut mappings = Vec::new();
-    let mut extra_luts_total = 0;
-    let mut lutram_blocks = 0;
-    let mut m8k_blocks = 0;
-    let mut m128k_blocks = 0;
-    let mut next_group_id = 0;
-
-    for c in circuits {
-        for mem in &c.memories {
-            let mapping = choose_mapping_for_memory(
-                c.id,
-                mem,
-                next_group_id,
-                has_lutram,
-                has_m8k,
-                has_m128k,
-                &m8k_cfg,
-                &m128k_cfg,
-            );
-            next_group_id += 1;
-
-            extra_luts_total += mapping.extra_luts;
-            match mapping.phys_type {
-                PhysType::Lutram => lutram_blocks += mapping.phys_blocks,
-                PhysType::Ram8K => m8k_blocks += mapping.phys_blocks,
-                PhysType::Ram128K => m128k_blocks += mapping.phys_blocks,
+        None => None,
+    };
+    let global_objective = match args.iter().position(|s| s == "--global-objective") {
+        Some(idx) => {
+            let weights = args.get(idx + 1).and_then(|spec| {
+                let (alpha, beta) = spec.split_once(',')?;
+                Some((alpha.parse::<f64>().ok()?, beta.parse::<f64>().ok()?))
+            });
+            if weights.is_none() {
+                return Err(RapidMapError::Config(
+                    "--global-objective expects alpha,beta (e.g. 1.0,0.0)".into(),
+                ));
             }
-
-            mappings.push(mapping);
+            weights
         }
-    }
-
-    // sharing uses dynamic configs
-    let m8k_cfg_opt = if has_m8k { Some(&m8k_cfg) } else { None };
-    let m128k_cfg_opt = if has_m128k { Some(&m128k_cfg) } else { None };
-    apply_sharing(
-        &mut mappings,
-        m8k_cfg_opt,
-        m128k_cfg_opt,
-        &mut m8k_blocks,
-        &mut m128k_blocks,
-    );
-
-    CircuitResult {
-        mappings,
-        extra_luts: extra_luts_total,
-        lutram_blocks,
-        m8k_blocks,
-        m128k_blocks,
-    }
-}
-fn main() -> io::Result<()> {
-    let start = Instant::now();
-    let results_file = "results.csv";
-
-    let logic_block_file = "logic_block_count.txt";
-    let logic_rams_file = "logical_rams.txt";
-
-    let mut has_lutram = true;
-    let mut lutram_fraction: f64 = 0.5;
-
-    let mut has_ram1 = true;
-    let mut ram1_bits: i32 = 8192;
-    let mut lbs_per_ram1: i32 = 10;
-    let mut max_width_ram1: i32 = 32;
-
-    let mut has_ram2 = true;
-    let mut ram2_bits: i32 = 128 * 1024;
-    let mut lbs_per_ram2: i32 = 300;
-    let mut max_width_ram2: i32 = 128;
+        None => None,
+    };
+    let optimize = args.iter().any(|s| s == "--optimize");
+    let optimize_passes = match args.iter().position(|s| s == "--optimize-passes") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<usize>().ok()) {
+            Some(n) if n >= 1 => n,
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--optimize-passes expects a positive integer".into(),
+                ));
+            }
+        },
+        None => 10,
+    };
+    let optimize_seconds = match args.iter().position(|s| s == "--optimize-seconds") {
+        Some(idx) => match args.get(idx + 1).and_then(|v| v.parse::<f64>().ok()) {
+            Some(secs) if secs > 0.0 && secs.is_finite() => Some(Duration::from_secs_f64(secs)),
+            _ => {
+                return Err(RapidMapError::Config(
+                    "--optimize-seconds expects a positive number of seconds".into(),
+                ));
+            }
+        },
+        None => None,
+    };
+    let path_flag = |flag: &str| {
+        args.iter()
+            .position(|s| s == flag)
+            .and_then(|idx| args.get(idx + 1))
+    };
+    let logic_block_file =
+        path_flag("--logic-blocks").map_or("logic_block_count.txt", String::as_str);
+    let logic_rams_file = path_flag("--logical-rams").map_or("logical_rams.txt", String::as_str);
+    let results_file = path_flag("--results").map_or("results.csv", String::as_str);
+    let mapped_file = path_flag("--mapped").map_or("ram_mapped.txt", String::as_str);
 
-    let args: Vec<String> = std::env::args().collect();
     if let Some(p_idx) = args.iter().position(|s| s == "-p") {
         let base = p_idx + 1;
         if args.len() < base + 10 {
-            eprintln!(
-                "Error: -p expects 10 arguments:\n\
+            return Err(RapidMapError::Config(
+                "-p expects 10 arguments:\n\
                  \thas_lutram lutram_fraction \
                  has_ram1 ram1_bits lbs_per_ram1 max_width_ram1 \
                  has_ram2 ram2_bits lbs_per_ram2 max_width_ram2"
-            );
-            std::process::exit(1);
+                    .into(),
+            ));
         }
         let get = |off: usize| &args[base + off];
 
@@ -677,110 +699,755 @@ fn main() -> io::Result<()> {
         };
 
         if let Some(b) = parse_bool(get(0)) {
-            has_lutram = b;
+            arch.has_lutram = b;
         }
 
         if let Ok(v) = get(1).parse::<f64>() {
             if (0.0..=1.0).contains(&v) {
-                lutram_fraction = v;
+                arch.lutram_fraction = v;
             } else {
                 eprintln!(
-                    "Warning: lutram_fraction {} is not between 0 and 1, keeping default {}",
-                    v, lutram_fraction
+                    "Warning: lutram_fraction {} is not between 0 and 1, keeping {}",
+                    v, arch.lutram_fraction
                 );
             }
         }
 
         if let Some(b) = parse_bool(get(2)) {
-            has_ram1 = b;
+            arch.has_ram1 = b;
         }
         if let Ok(v) = get(3).parse::<i32>() {
-            ram1_bits = v;
+            arch.ram1_bits = v;
         }
         if let Ok(v) = get(4).parse::<i32>() {
-            lbs_per_ram1 = v;
+            arch.lbs_per_ram1 = v;
         }
         if let Ok(v) = get(5).parse::<i32>() {
-            max_width_ram1 = v;
+            arch.max_width_ram1 = v;
         }
         if let Some(b) = parse_bool(get(6)) {
-            has_ram2 = b;
+            arch.has_ram2 = b;
         }
         if let Ok(v) = get(7).parse::<i32>() {
-            ram2_bits = v;
+            arch.ram2_bits = v;
         }
         if let Ok(v) = get(8).parse::<i32>() {
-            lbs_per_ram2 = v;
+            arch.lbs_per_ram2 = v;
         }
         if let Ok(v) = get(9).parse::<i32>() {
-            max_width_ram2 = v;
+            arch.max_width_ram2 = v;
         }
     }
 
+    if let (Some(path), Some(file_arch)) = (&arch_file, &file_arch) {
+        for (key, file_value, p_value) in arch_differences(file_arch, &arch) {
+            eprintln!(
+                "Warning: -p {} = {} overrides {} from {}",
+                key, p_value, file_value, path
+            );
+        }
+    }
+    if let Some(idx) = args.iter().position(|s| s == "--pipeline-mux") {
+        match args.get(idx + 1).and_then(|v| v.parse::<i32>().ok()) {
+            Some(t) => {
+                arch.pipeline_mux = true;
+                arch.pipeline_threshold = t;
+            }
+            None => {
+                return Err(RapidMapError::Config(
+                    "--pipeline-mux expects a series threshold".into(),
+                ));
+            }
+        }
+    }
+    if let Some(idx) = args.iter().position(|s| s == "--rom-discount") {
+        match args.get(idx + 1).and_then(|v| v.parse::<f64>().ok()) {
+            Some(d) => arch.rom_discount = d,
+            None => {
+                return Err(RapidMapError::Config(
+                    "--rom-discount expects a number".into(),
+                ));
+            }
+        }
+    }
+    if args.iter().any(|s| s == "--no-tdp") {
+        arch.allow_tdp = false;
+    }
     // require atleast one memory type
-    if !has_lutram && !has_ram1 && !has_ram2 {
-        panic!("At least one memory type (LUTRAM, M8K, or M128K) must be enabled");
+    arch.validate().map_err(RapidMapError::Config)?;
+
+    check_input_shapes(logic_block_file, logic_rams_file)?;
+    #[cfg(not(feature = "mmap"))]
+    let (mut circuits, parse_warnings) = read_data(logic_block_file, logic_rams_file, &read_opts)?;
+    #[cfg(feature = "mmap")]
+    let (mut circuits, parse_warnings) =
+        read_data_mmap(logic_block_file, logic_rams_file, &read_opts)?;
+    for w in &parse_warnings {
+        eprintln!("Warning: {}", w);
+    }
+    if !parse_warnings.is_empty() {
+        eprintln!("{} input lines skipped", parse_warnings.len());
+    }
+    if let Some(ids) = &circuit_filter {
+        circuits.retain(|c| ids.contains(&c.id));
+    }
+    if let Some(id) = explain
+        && !circuits.iter().any(|c| c.id == id)
+    {
+        return Err(RapidMapError::Config(format!(
+            "--explain: circuit {} is not in the input (or --circuits)",
+            id
+        )));
     }
 
-    let circuits = read_data(logic_block_file, logic_rams_file)?;
+    if print_shape_hist {
+        eprintln!("Width Depth Mode Count");
+        for ((width, depth, mode), count) in shape_histogram(&circuits) {
+            eprintln!("{} {} {} {}", width, depth, mode.as_str(), count);
+        }
+    }
     //Print circuit numbers
     //eprintln!("Read {} circuits", circuits.len());
 
-    let result = assign_ram(
-        &circuits,
-        has_lutram,
-        has_ram1,
-        has_ram2,
-        ram1_bits,
-        ram2_bits,
-        max_width_ram1,
-        max_width_ram2,
-    );
-
-    let _global_total_area = compute_total_area(
-        &circuits,
-        &result,
-        has_lutram,
-        lutram_fraction,
-        has_ram1,
-        has_ram2,
-        ram1_bits,
-        ram2_bits,
-        lbs_per_ram1,
-        lbs_per_ram2,
-        max_width_ram1,
-        max_width_ram2,
-    );
-
-    let mut per_circuit: HashMap<i32, (i32, i32, i32, i32)> = HashMap::new();
-
-    for c in &circuits {
-        per_circuit.entry(c.id).or_insert((0, 0, 0, 0));
-    }
-
-    for m in &result.mappings {
-        let entry = per_circuit.entry(m.circuit_id).or_insert((0, 0, 0, 0));
-        match m.phys_type {
-            PhysType::Lutram => entry.0 += m.phys_blocks,
-            PhysType::Ram8K => entry.1 += m.phys_blocks,
-            PhysType::Ram128K => entry.2 += m.phys_blocks,
-        }
-        entry.3 += m.extra_luts;
+    // dynamic configs for both memories
+    let mut configs = arch.phys_configs();
+    for ((phys_type, table), list) in [PhysType::Ram8K, PhysType::Ram128K]
+        .into_iter()
+        .zip(shapes)
+        .zip(widths)
+    {
+        if let Some(cfg) = configs.get_mut(phys_type) {
+            cfg.shapes = table.into();
+            cfg.width_candidates = list;
+            cfg.include_max_width = include_max_width;
+        }
+    }
+
+    // LUTRAM has no -p width, so the widest shape sets it
+    if let Some(table) = lutram_shapes
+        && let Some(cfg) = configs.get_mut(PhysType::Lutram)
+    {
+        cfg.max_width_non_tdp = table.iter().map(|&(w, _)| w).max().unwrap_or(0);
+        cfg.max_width_sdp = cfg.max_width_non_tdp;
+        cfg.shapes = table.into();
+    }
+    for &(phys_type, width) in &max_width_sdp {
+        if let Some(cfg) = configs.get_mut(phys_type) {
+            cfg.max_width_sdp = width;
+        }
+    }
+
+    for &(phys_type, mode) in &forbidden {
+        if let Some(cfg) = configs.get_mut(phys_type) {
+            cfg.forbidden_modes.push(mode);
+        }
+    }
+    // a zero width leaves an enabled type with no legal shape for any mode
+    for t in configs.all_types() {
+        if let Some(cfg) = configs.get(t)
+            && cfg.max_width_non_tdp <= 0
+        {
+            eprintln!(
+                "Warning: {} is enabled but its max_width is {}, so nothing can map to it",
+                t.name(),
+                cfg.max_width_non_tdp
+            );
+        }
+    }
+    if depth_waste_weight != 0.0 {
+        for t in configs.all_types() {
+            if let Some(cfg) = configs.get_mut(t) {
+                cfg.cost.depth_waste_weight = depth_waste_weight;
+            }
+        }
+    }
+    if let Some(n) = max_series {
+        for t in configs.all_types() {
+            if let Some(cfg) = configs.get_mut(t) {
+                cfg.max_series = n;
+            }
+        }
+    }
+    // in the order given, so a later type=N overrides an earlier bare N
+    for &(only, n) in &max_parallel {
+        for t in configs.all_types() {
+            if only.is_none_or(|o| o == t)
+                && let Some(cfg) = configs.get_mut(t)
+            {
+                cfg.max_parallel = n;
+            }
+        }
+    }
+    if let Some(table) = &decoder_table {
+        for t in configs.all_types() {
+            if let Some(cfg) = configs.get_mut(t) {
+                cfg.decoder_table = table.clone();
+            }
+        }
+    }
+
+    if let Some(required) = required_type {
+        let Some(cfg) = configs.get(required) else {
+            return Err(RapidMapError::Config(format!(
+                "--require-type {} but that type is not enabled",
+                required.name()
+            )));
+        };
+        let offending = unmappable_memories(&circuits, cfg);
+        if !offending.is_empty() {
+            return Err(RapidMapError::Unmappable {
+                reason: format!("cannot be mapped to required type {}", required.name()),
+                rams: offending,
+            });
+        }
+        for t in configs.all_types() {
+            if t != required {
+                configs.disable(t);
+            }
+        }
+    }
+
+    // without --strict these circuits are skipped by assign_ram like any other unmappable RAM
+    let impossible_tdp = impossible_tdp_memories(&circuits, &configs);
+    if strict && !impossible_tdp.is_empty() {
+        let reason = if arch.allow_tdp {
+            "are TrueDualPort but no enabled type can implement them \
+             (enable M8K/M128K with sufficient TDP width)"
+        } else {
+            "are TrueDualPort but the architecture has no TDP mode (allow_tdp = false)"
+        };
+        return Err(RapidMapError::Unmappable {
+            reason: reason.into(),
+            rams: impossible_tdp,
+        });
+    }
+
+    let hints = match &hints_file {
+        Some(path) => read_hints(path)?,
+        None => HashMap::new(),
+    };
+    // extra BRAM tiers that --type-priority left out go last, in file order
+    for t in configs.all_types() {
+        if !type_priority.contains(&t) {
+            type_priority.push(t);
+        }
+    }
+    let opts = MapOptions {
+        memory_limit,
+        sharing_iterations,
+        hints,
+        type_priority,
+        lut_budget,
+        stream_decisions,
+        parallel_memories,
+        sharing: SharingConfig {
+            modes: share_modes,
+            granularity: share_granularity,
+            max_sharers,
+        },
+        strict,
+        allow_split,
+        seed,
+        allow_lut_memory,
+        progress,
+    };
+    let mut result = assign_ram(&circuits, &configs, &opts)?;
+    if check_determinism {
+        let first = result.content_hash();
+        let second = assign_ram(&circuits, &configs, &opts)?.content_hash();
+        if first != second {
+            return Err(RapidMapError::Nondeterministic { first, second });
+        }
+        eprintln!("Determinism check passed (hash {:016x})", first);
+    }
+    // everything below reports on the circuits that mapped; their re-runs can't skip any
+    for e in &result.skipped {
+        eprintln!("Skipping circuit {}: {}", e.circuit_id, e);
+    }
+    circuits.retain(|c| !result.skipped.iter().any(|e| e.circuit_id == c.id));
+    let rerun_opts = MapOptions {
+        strict: true,
+        progress: false,
+        ..opts.clone()
+    };
+
+    let total_area = |r: &CircuitResult| compute_total_area(&circuits, r, &arch);
+
+    if let Some((alpha, beta)) = global_objective {
+        let flips =
+            global_objective_pass(&circuits, &mut result, &configs, alpha, beta, total_area);
+        eprintln!("Global objective pass switched {} mappings", flips);
+    }
+
+    if optimize {
+        let before = total_area(&result);
+        let deadline = optimize_seconds.map(|d| Instant::now() + d);
+        let (passes, switched) = local_search(
+            &circuits,
+            &mut result,
+            &configs,
+            optimize_passes,
+            deadline,
+            total_area,
+        );
+        eprintln!(
+            "Local search: {} passes switched {} mappings, total area {:.5e} -> {:.5e}",
+            passes,
+            switched,
+            before,
+            total_area(&result)
+        );
+    }
+
+    if let Some(budgets) = &balance {
+        let moves = balance_types(&circuits, &mut result, &configs, budgets);
+        eprintln!("Balance pass moved {} mappings", moves);
+        for phys_type in configs.all_types() {
+            let used = result.blocks(phys_type);
+            if let Some(budget) = budgets.get(phys_type)
+                && used > budget
+            {
+                eprintln!(
+                    "Warning: {} still uses {} blocks, over its budget of {}",
+                    phys_type.name(),
+                    used,
+                    budget
+                );
+            }
+        }
+    }
+
+    if report_cross_circuit {
+        let pairs = cross_circuit_share_report(&result, &configs, &opts.sharing);
+        eprintln!(
+            "Cross-circuit sharing opportunities: {} (blocks lost to circuit isolation)",
+            pairs.len()
+        );
+        for (a, b) in &pairs {
+            eprintln!(
+                "  circuit {} ram {} <-> circuit {} ram {} (Type {})",
+                a.circuit_id,
+                a.logical_ram_id,
+                b.circuit_id,
+                b.logical_ram_id,
+                a.phys_type.type_id()
+            );
+        }
+    }
+
+    let _global_total_area = total_area(&result);
+
+    let lb_requirement = chip_lb_requirement(&circuits, &result, &arch);
+
+    if report_wasted_lbs {
+        eprintln!(
+            "Wasted LBs: {} of {} (forced by {})",
+            lb_requirement.wasted_lbs(),
+            lb_requirement.nlb_arch(),
+            lb_requirement.binding()
+        );
+        for (constraint, excess) in lb_requirement.excess_by_constraint() {
+            eprintln!("  {} exceeds logic by {}", constraint, excess);
+        }
+    }
+
+    if let Some(device) = &device {
+        let shortfalls = device_shortfalls(device, lb_requirement.nlb_arch(), &result);
+        if !shortfalls.is_empty() {
+            return Err(RapidMapError::DoesNotFit(shortfalls));
+        }
     }
 
+    let per_circuit = circuit_usage(&circuits, &result.mappings, fractional_sharing);
+
     //Write components and blocks in the circuit used
-    let area_8k = block_ram_area(ram1_bits, max_width_ram1);
-    let area_128k = block_ram_area(ram2_bits, max_width_ram2);
-    write_csv(results_file, &circuits, &per_circuit, area_8k, area_128k)?;
+    let area_8k = block_ram_area(arch.ram1_bits, arch.max_width_ram1, &arch.ram1_area);
+    let area_128k = block_ram_area(arch.ram2_bits, arch.max_width_ram2, &arch.ram2_area);
+    let area_extra: Vec<f64> = arch
+        .extra_rams
+        .iter()
+        .map(|ram| block_ram_area(ram.bits, ram.max_width, &ram.area))
+        .collect();
+    if !dry_run {
+        write_csv(
+            results_file,
+            &circuits,
+            &per_circuit,
+            area_8k,
+            area_128k,
+            &area_extra,
+            lutram_area,
+        )?;
+    }
+    if show_stats {
+        print_stats(&compute_stats(&result, &configs, total_area(&result)));
+    } else {
+        if count_candidates {
+            eprintln!("Candidates evaluated: {}", result.candidates_evaluated);
+        }
+        eprintln!(
+            "Bits per area: {:.5e}",
+            bits_per_area(&result, total_area(&result))
+        );
+    }
     let elapsed = start.elapsed();
     //Printing runtime
     eprintln!("Program runtime: {:.3?}", elapsed);
     //write out the RAM mapping file
-    write_mappings("ram_mapped.txt", &result.mappings)?;
+    if dry_run {
+        eprintln!(
+            "Blocks: LUTRAM {} M8K {} M128K {}{}",
+            result.lutram_blocks,
+            result.m8k_blocks,
+            result.m128k_blocks,
+            result
+                .extra_blocks
+                .iter()
+                .enumerate()
+                .map(|(i, n)| format!(" {} {}", PhysType::Extra(i as u8).name().to_uppercase(), n))
+                .collect::<String>()
+        );
+        eprintln!("Extra LUTs: {}", result.extra_luts);
+    } else {
+        write_mappings(mapped_file, &result.mappings)?;
+        if mapped_json {
+            let json_path = Path::new(mapped_file).with_extension("json");
+            write_mappings_json(&json_path.to_string_lossy(), &result.mappings)?;
+        }
+        if long_csv {
+            let csv_path = Path::new(mapped_file).with_extension("csv");
+            write_mappings_long_csv(&csv_path.to_string_lossy(), &result.mappings, &configs)?;
+        }
+    }
+    if pretty {
+        write_mappings_pretty(&mut io::stdout().lock(), &circuits, &result.mappings)?;
+    }
+    if let Some(path) = &logical_out_file {
+        write_logical_rams(path, &result.mappings)?;
+    }
+    if let Some(path) = &mapped_bin_file {
+        write_mappings_bin(path, &result.mappings)?;
+    }
+    if let Some(path) = &mapping_csv_file {
+        write_mappings_csv(path, &result.mappings)?;
+    }
+    if let Some(path) = &groups_file {
+        write_shared_groups(path, &result.mappings, &configs)?;
+    }
+
+    if let Some(id) = explain {
+        match circuits.iter().find(|c| c.id == id) {
+            Some(c) => print_explanation(c, &result, &configs, &opts.type_priority),
+            None => eprintln!("Circuit {} was skipped, nothing to explain", id),
+        }
+    }
+
+    if let Some(n) = top_cost {
+        eprintln!("Top {} mappings by cost:", n);
+        eprintln!("Circuit RamID Type S P Cost");
+        for (m, cost) in top_cost_mappings(&result.mappings, &configs, n) {
+            eprintln!(
+                "{} {} {} {} {} {:.3e}",
+                m.circuit_id,
+                m.logical_ram_id,
+                m.phys_type.type_id(),
+                m.series,
+                m.parallel,
+                cost
+            );
+        }
+    }
 
     //Compute geometric area
-    let geom_area = compute_geometric_area(logic_block_file, "ram_mapped.txt")?;
+    let geom_blocks = match area_model {
+        AreaModel::Legacy => [
+            (
+                PHYS_RAM1.bits,
+                PHYS_RAM1.max_width_non_tdp,
+                BramAreaModel::DEFAULT,
+            ),
+            (
+                PHYS_RAM2.bits,
+                PHYS_RAM2.max_width_non_tdp,
+                BramAreaModel::DEFAULT,
+            ),
+        ],
+        AreaModel::Unified => [
+            (arch.ram1_bits, arch.max_width_ram1, arch.ram1_area),
+            (arch.ram2_bits, arch.max_width_ram2, arch.ram2_area),
+        ],
+    };
+    let geom_params = GeomeanParams::for_model(&arch, area_model);
+    let geom_area = compute_geometric_area_in_memory(&circuits, &result, &geom_params);
     eprintln!("Geometric mean FPGA area = {:.5e}", geom_area);
+    if type_geomeans {
+        for (phys_type, mean) in per_type_block_geomeans(&per_circuit, &configs.all_types()) {
+            match mean {
+                Some(mean) => eprintln!(
+                    "Geometric mean {} blocks per circuit = {:.3} (circuits using it only)",
+                    phys_type.name(),
+                    mean
+                ),
+                None => eprintln!(
+                    "Geometric mean {} blocks per circuit = n/a",
+                    phys_type.name()
+                ),
+            }
+        }
+    }
+
+    let effective_config = EffectiveConfig {
+        arch: arch.clone(),
+        area_model,
+        sharing_iterations: opts.sharing_iterations,
+        type_priority: opts
+            .type_priority
+            .iter()
+            .map(|t| t.name().into_owned())
+            .collect(),
+        args: args[1..].to_vec(),
+    };
+
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &sqlite_file {
+        rapid_map::sqlite::write_run(path, &effective_config, &result, &per_circuit, geom_area)
+            .map_err(io::Error::other)?;
+    }
+
+    if let Some(path) = &manifest_file {
+        let manifest = RunManifest {
+            crate_version: env!("CARGO_PKG_VERSION"),
+            inputs: vec![
+                input_file_info(logic_block_file)?,
+                input_file_info(logic_rams_file)?,
+            ],
+            config: effective_config,
+            content_hash: format!("{:016x}", result.content_hash()),
+            geomean_area: geom_area,
+        };
+        write_manifest(path, &manifest)?;
+    }
+
+    if let Some(path) = &summary_file {
+        let summary = RunSummary::new(
+            &arch,
+            &configs,
+            (logic_block_file, logic_rams_file),
+            &circuits,
+            &result,
+            geom_area,
+        );
+        write_summary(path, &summary)?;
+    }
+
+    if ablation {
+        let (baseline, report) = type_ablation(&circuits, &configs, &rerun_opts, &geom_params)?;
+        eprintln!("Ablation (baseline geomean {:.5e}):", baseline);
+        for entry in report {
+            let t = entry.phys_type;
+            match entry.geomean {
+                Ok(geomean) => eprintln!(
+                    "  without {}: geomean {:.5e} ({:+.2}%)",
+                    t.name(),
+                    geomean,
+                    100.0 * (geomean - baseline) / baseline
+                ),
+                Err(e) => eprintln!("  without {}: cannot map ({})", t.name(), e),
+            }
+        }
+    }
+
+    // both architectures mapped from scratch by map_circuits, without the run's options,
+    // and each geomean under its own spacing and block areas
+    if let Some((path, other)) = &compare_arch {
+        eprintln!("Architecture comparison:");
+        eprintln!("Arch Geomean Total_Area Extra_LUTs LUTRAM M8K M128K");
+        let mut geomeans = Vec::new();
+        for (name, a) in [("primary", &arch), (path.as_str(), other)] {
+            match map_circuits(&circuits, a) {
+                Ok(r) => {
+                    let geomean = geometric_area_from_result(&circuits, &r, a);
+                    eprintln!(
+                        "{} {:.5e} {:.5e} {} {} {} {}",
+                        name,
+                        geomean,
+                        compute_total_area(&circuits, &r, a),
+                        r.extra_luts,
+                        r.lutram_blocks,
+                        r.m8k_blocks,
+                        r.m128k_blocks
+                    );
+                    geomeans.push(geomean);
+                }
+                Err(e) => eprintln!("{} cannot map ({})", name, e),
+            }
+        }
+        if let [primary, second] = geomeans[..] {
+            eprintln!(
+                "Geomean of {} vs primary: {:+.2}%",
+                path,
+                100.0 * (second - primary) / primary
+            );
+        }
+    }
+
+    // one constant at a time, +1%; like --ablation only assign_ram is re-run
+    if sensitivity {
+        let (baseline, report) = cost_sensitivity(
+            &circuits,
+            &configs,
+            &rerun_opts,
+            &geom_params,
+            geom_blocks,
+            0.01,
+        )?;
+        eprintln!(
+            "Sensitivity (baseline geomean {:.5e}, +1% per constant, +0.01 if it is 0):",
+            baseline
+        );
+        eprintln!("Constant Value Geomean dGeomean/dConstant Elasticity");
+        for entry in &report {
+            // n/a when there is no geomean to be relative to
+            let elasticity = entry.gradient * entry.value / baseline;
+            let elasticity = if elasticity.is_finite() {
+                format!("{:+.4}", elasticity)
+            } else {
+                "n/a".to_string()
+            };
+            eprintln!(
+                "{} {} {:.5e} {:.5e} {}",
+                entry.constant.name(),
+                entry.value,
+                entry.geomean,
+                entry.gradient,
+                elasticity
+            );
+        }
+    }
+
+    if report_binding {
+        let requirements = lb_requirements_from_result(&circuits, &result, &geom_params);
+        eprintln!("Circuit NLB_arch Binding");
+        for (cid, req) in &requirements {
+            eprintln!("{} {} {}", cid, req.nlb_arch(), req.binding());
+        }
+    }
+
+    // mapping doesn't depend on the fraction, so only the area models are re-run
+    if let Some(fractions) = &sweep_fractions {
+        let mut writer = csv::Writer::from_writer(io::stdout().lock());
+        writer
+            .write_record(["lutram_fraction", "total_area", "geomean_area"])
+            .map_err(io::Error::from)?;
+        for &fraction in fractions {
+            let swept = ArchConfig {
+                lutram_fraction: fraction,
+                ..arch.clone()
+            };
+            let total = compute_total_area(&circuits, &result, &swept);
+            let params = GeomeanParams {
+                lutram_fraction: fraction,
+                ..geom_params.clone()
+            };
+            let geomean = compute_geometric_area_in_memory(&circuits, &result, &params);
+            writer
+                .write_record([
+                    format!("{:.4}", fraction),
+                    format!("{:.5e}", total),
+                    format!("{:.5e}", geomean),
+                ])
+                .map_err(io::Error::from)?;
+        }
+        writer.flush()?;
+    }
+
+    // checked last, so a failing run still writes its files and reports
+    if let Some(budget) = max_area_budget
+        && area_overage(geom_area, budget).is_some()
+    {
+        return Err(RapidMapError::OverAreaBudget {
+            geomean: geom_area,
+            budget,
+        });
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // rapid-map with the given arguments, reading and writing inside dir
+    fn run_in(dir: &tempfile::TempDir, extra: &[&str]) -> Result<(), RapidMapError> {
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let mut args = vec!["rapid-map".to_string()];
+        for (flag, name) in [
+            ("--logic-blocks", "logic_block_count.txt"),
+            ("--logical-rams", "logical_rams.txt"),
+            ("--results", "results.csv"),
+            ("--mapped", "ram_mapped.txt"),
+        ] {
+            args.push(flag.to_string());
+            args.push(path(name));
+        }
+        args.extend(extra.iter().map(|s| s.to_string()));
+        run(&args)
+    }
+
+    fn tdp_circuit_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("logic_block_count.txt"),
+            "Circuit\tLBs\n0\t100\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("logical_rams.txt"),
+            "Num_Circuits 1\nCircuit\tRamID\tMode\tDepth\tWidth\n0\t0\tTrueDualPort\t1024\t16\n",
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn the_circuit_maps() {
+        let dir = tdp_circuit_dir();
+        run_in(&dir, &[]).unwrap();
+        assert!(dir.path().join("ram_mapped.txt").exists());
+    }
+
+    #[test]
+    fn missing_input_is_a_file_error() {
+        let dir = tdp_circuit_dir();
+        std::fs::remove_file(dir.path().join("logical_rams.txt")).unwrap();
+        let err = run_in(&dir, &[]).unwrap_err();
+        assert!(
+            matches!(&err, RapidMapError::File { path, .. } if path.ends_with("logical_rams.txt"))
+        );
+        assert_eq!(err.exit_code(), 2);
+    }
+
+    #[test]
+    fn no_enabled_type_is_a_config_error() {
+        let dir = tdp_circuit_dir();
+        let none = [
+            "-p", "0", "0.5", "0", "8192", "10", "32", "0", "131072", "300", "128",
+        ];
+        let err = run_in(&dir, &none).unwrap_err();
+        assert!(matches!(&err, RapidMapError::Config(msg) if msg.contains("must be enabled")));
+        assert_eq!(err.exit_code(), 1);
+        assert!(!dir.path().join("ram_mapped.txt").exists());
+    }
+
+    #[test]
+    fn strict_tdp_ram_without_a_tdp_type_is_unmappable() {
+        let dir = tdp_circuit_dir();
+        let lutram_only = [
+            "-p", "1", "0.5", "0", "8192", "10", "32", "0", "131072", "300", "128", "--strict",
+        ];
+        let err = run_in(&dir, &lutram_only).unwrap_err();
+        assert!(matches!(&err, RapidMapError::Unmappable { rams, .. } if rams.len() == 1));
+        assert_eq!(err.exit_code(), 3);
+    }
+}
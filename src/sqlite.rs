@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use rusqlite::{Connection, params};
+
+use crate::CircuitResult;
+use crate::utils::{CircuitUsage, EffectiveConfig};
+
+// Appends one run to the database: a row in runs, then its per-circuit and per-mapping rows
+// keyed by the new run id. Tables are created on first use.
+pub fn write_run(
+    path: &str,
+    config: &EffectiveConfig,
+    result: &CircuitResult,
+    per_circuit: &HashMap<i32, CircuitUsage>,
+    geomean_area: f64,
+) -> rusqlite::Result<()> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS runs (
+             id INTEGER PRIMARY KEY,
+             crate_version TEXT NOT NULL,
+             config TEXT NOT NULL,
+             content_hash TEXT NOT NULL,
+             geomean_area REAL NOT NULL
+         );
+         CREATE TABLE IF NOT EXISTS circuits (
+             run_id INTEGER NOT NULL REFERENCES runs(id),
+             circuit INTEGER NOT NULL,
+             lutram_blocks INTEGER NOT NULL,
+             m8k_blocks REAL NOT NULL,
+             m128k_blocks REAL NOT NULL,
+             extra_luts INTEGER NOT NULL
+         );
+         CREATE TABLE IF NOT EXISTS mappings (
+             run_id INTEGER NOT NULL REFERENCES runs(id),
+             circuit INTEGER NOT NULL,
+             ram INTEGER NOT NULL,
+             type INTEGER NOT NULL,
+             mode TEXT NOT NULL,
+             logical_width INTEGER NOT NULL,
+             logical_depth INTEGER NOT NULL,
+             group_id INTEGER NOT NULL,
+             series INTEGER NOT NULL,
+             parallel INTEGER NOT NULL,
+             phys_width INTEGER NOT NULL,
+             phys_depth INTEGER NOT NULL,
+             extra_luts INTEGER NOT NULL
+         );",
+    )?;
+
+    let config_json = serde_json::to_string(config)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))?;
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO runs (crate_version, config, content_hash, geomean_area)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            env!("CARGO_PKG_VERSION"),
+            config_json,
+            format!("{:016x}", result.content_hash()),
+            geomean_area
+        ],
+    )?;
+    let run_id = tx.last_insert_rowid();
+
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO circuits (run_id, circuit, lutram_blocks, m8k_blocks, m128k_blocks, extra_luts)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        let mut ids: Vec<&i32> = per_circuit.keys().collect();
+        ids.sort();
+        for id in ids {
+            let u = &per_circuit[id];
+            insert.execute(params![
+                run_id,
+                id,
+                u.lutram_blocks,
+                u.m8k_blocks,
+                u.m128k_blocks,
+                u.extra_luts
+            ])?;
+        }
+
+        let mut insert = tx.prepare(
+            "INSERT INTO mappings (run_id, circuit, ram, type, mode, logical_width, logical_depth,
+                                   group_id, series, parallel, phys_width, phys_depth, extra_luts)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        )?;
+        for m in &result.mappings {
+            insert.execute(params![
+                run_id,
+                m.circuit_id,
+                m.logical_ram_id,
+                m.phys_type.type_id(),
+                m.mode.as_str(),
+                m.logical_width,
+                m.logical_depth,
+                m.group_id,
+                m.series,
+                m.parallel,
+                m.phys_width,
+                m.phys_depth,
+                m.extra_luts
+            ])?;
+        }
+    }
+    tx.commit()
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::utils::{AreaModel, circuit_usage, geometric_area_from_result};
+    use crate::{ArchConfig, Circuit, MemMode, Memory, map_circuits};
+
+    #[test]
+    fn written_run_reads_back() {
+        let circuits = vec![Circuit {
+            id: 0,
+            logic_blocks: 200,
+            memories: vec![
+                Memory {
+                    ram_id: 0,
+                    mode: MemMode::SinglePort,
+                    depth: 1024,
+                    width: 16,
+                },
+                Memory {
+                    ram_id: 1,
+                    mode: MemMode::Rom,
+                    depth: 512,
+                    width: 8,
+                },
+            ],
+        }];
+        let arch = ArchConfig::default();
+        let result = map_circuits(&circuits, &arch).unwrap();
+        let per_circuit = circuit_usage(&circuits, &result.mappings, false);
+        let geomean = geometric_area_from_result(&circuits, &result, &arch);
+        let config = EffectiveConfig {
+            arch,
+            area_model: AreaModel::Unified,
+            sharing_iterations: 1,
+            type_priority: Vec::new(),
+            args: Vec::new(),
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("runs.db");
+        let path = path.to_str().unwrap();
+        write_run(path, &config, &result, &per_circuit, geomean).unwrap();
+
+        let conn = Connection::open(path).unwrap();
+        let (run_id, stored): (i64, f64) = conn
+            .query_row("SELECT id, geomean_area FROM runs", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(stored, geomean);
+        let count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM mappings WHERE run_id = ?1",
+                [run_id],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(count as usize, result.mappings.len());
+    }
+}
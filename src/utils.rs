@@ -1,79 +1,154 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    AVG_LB_AREA, Circuit, CircuitResult, PHYS_RAM1, PHYS_RAM2, RamMapping, block_ram_area,
+    AVG_LB_AREA, ArchConfig, BramAreaModel, Circuit, CircuitResult, ExtraRam, MemMode, PHYS_RAM1,
+    PHYS_RAM2, PhysConfigs, PhysType, RamMapping, ReadOptions, block_ram_area, candidate_costs,
+    mapping_cost,
 };
 
-pub fn compute_total_area(
+// LB tiles the chip needs, one entry per constraint. The chip gets the largest of them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LbRequirement {
+    pub logic: i32,
+    pub m8k_sites: i32,
+    pub m128k_sites: i32,
+    // the most any extra BRAM tier needs
+    pub extra_sites: i32,
+    pub lutram_capacity: i32,
+}
+
+impl LbRequirement {
+    pub fn nlb_arch(&self) -> i32 {
+        self.logic
+            .max(self.m8k_sites)
+            .max(self.m128k_sites)
+            .max(self.extra_sites)
+            .max(self.lutram_capacity)
+    }
+
+    // the constraint that sets nlb_arch; logic wins ties
+    pub fn binding(&self) -> &'static str {
+        let nlb_arch = self.nlb_arch();
+        if self.logic == nlb_arch {
+            "logic"
+        } else if self.m8k_sites == nlb_arch {
+            "m8k-sites"
+        } else if self.m128k_sites == nlb_arch {
+            "m128k-sites"
+        } else if self.extra_sites == nlb_arch {
+            "extra-sites"
+        } else {
+            "lutram-capacity"
+        }
+    }
+
+    // LBs that exist only to satisfy BRAM spacing or LUTRAM capacity, not logic
+    pub fn wasted_lbs(&self) -> i32 {
+        self.nlb_arch() - self.logic
+    }
+
+    // how far each non-logic constraint exceeds the logic need (0 if it doesn't);
+    // extra-sites is listed only when an extra BRAM tier is used
+    pub fn excess_by_constraint(&self) -> Vec<(&'static str, i32)> {
+        let mut excess = vec![
+            ("m8k-sites", (self.m8k_sites - self.logic).max(0)),
+            ("m128k-sites", (self.m128k_sites - self.logic).max(0)),
+        ];
+        if self.extra_sites > 0 {
+            excess.push(("extra-sites", (self.extra_sites - self.logic).max(0)));
+        }
+        excess.push((
+            "lutram-capacity",
+            (self.lutram_capacity - self.logic).max(0),
+        ));
+        excess
+    }
+}
+
+// LB tiles needed so that lutram_fraction of them can host lutram_blocks LUTRAMs.
+// Shared by the chip-level and per-circuit area models so the two agree.
+pub fn lutram_capacity_lbs(lutram_blocks: i32, lutram_fraction: f64) -> i32 {
+    if lutram_fraction <= 0.0 {
+        return 0;
+    }
+    ((lutram_blocks as f64) / lutram_fraction).ceil() as i32
+}
+
+pub fn chip_lb_requirement(
     circuits: &[Circuit],
     result: &CircuitResult,
-    has_lutram: bool,
-    lutram_fraction: f64,
-    has_m8k: bool,
-    has_m128k: bool,
-    m8k_bits: i32,
-    m128k_bits: i32,
-    lbs_per_m8k: i32,
-    lbs_per_m128k: i32,
-    max_width_ram1: i32,
-    max_width_ram2: i32,
-) -> f64 {
+    arch: &ArchConfig,
+) -> LbRequirement {
     let logic_general: i32 = circuits.iter().map(|c| c.logic_blocks).sum();
 
     let extra_logic_blocks = (result.extra_luts + 9) / 10;
     let lutram_blocks = result.lutram_blocks;
 
-    let lb_for_logic = logic_general + extra_logic_blocks + lutram_blocks;
-
-    let mut nlb_arch = lb_for_logic;
+    let mut req = LbRequirement {
+        logic: logic_general + extra_logic_blocks + lutram_blocks,
+        ..Default::default()
+    };
 
     // LBs needed to provide enough M8K sites
-    if has_m8k && result.m8k_blocks > 0 && lbs_per_m8k > 0 {
-        let lb_for_m8k_sites = result.m8k_blocks * lbs_per_m8k;
-        if nlb_arch < lb_for_m8k_sites {
-            nlb_arch = lb_for_m8k_sites;
-        }
+    if arch.has_ram1 && result.m8k_blocks > 0 && arch.lbs_per_ram1 > 0 {
+        req.m8k_sites = result.m8k_blocks * arch.lbs_per_ram1;
     }
 
-    if has_m128k && result.m128k_blocks > 0 && lbs_per_m128k > 0 {
-        let lb_for_m128k_sites = result.m128k_blocks * lbs_per_m128k;
-        if nlb_arch < lb_for_m128k_sites {
-            nlb_arch = lb_for_m128k_sites;
-        }
+    if arch.has_ram2 && result.m128k_blocks > 0 && arch.lbs_per_ram2 > 0 {
+        req.m128k_sites = result.m128k_blocks * arch.lbs_per_ram2;
     }
 
-    if has_lutram && lutram_fraction > 0.0 {
-        let lb_for_lutram_capacity = ((lutram_blocks as f64) / lutram_fraction).ceil() as i32;
-        if nlb_arch < lb_for_lutram_capacity {
-            nlb_arch = lb_for_lutram_capacity;
-        }
+    for (i, ram) in arch.extra_rams.iter().enumerate() {
+        let used = result.blocks(PhysType::Extra(i as u8));
+        req.extra_sites = req.extra_sites.max(used * ram.lbs_per);
+    }
+
+    if arch.has_lutram {
+        req.lutram_capacity = lutram_capacity_lbs(lutram_blocks, arch.lutram_fraction);
     }
 
+    req
+}
+
+pub fn compute_total_area(circuits: &[Circuit], result: &CircuitResult, arch: &ArchConfig) -> f64 {
+    let nlb_arch = chip_lb_requirement(circuits, result, arch).nlb_arch();
+
     let nlb_f = nlb_arch as f64;
 
     // LB area
     let logic_area = nlb_f * AVG_LB_AREA;
 
     // Number of BRAM macros on chip, from LB spacing.
-    let num_m8k_arch = if has_m8k && lbs_per_m8k > 0 {
-        nlb_arch / lbs_per_m8k
+    let num_m8k_arch = if arch.has_ram1 && arch.lbs_per_ram1 > 0 {
+        nlb_arch / arch.lbs_per_ram1
     } else {
         0
     };
-    let num_m128k_arch = if has_m128k && lbs_per_m128k > 0 {
-        nlb_arch / lbs_per_m128k
+    let num_m128k_arch = if arch.has_ram2 && arch.lbs_per_ram2 > 0 {
+        nlb_arch / arch.lbs_per_ram2
     } else {
         0
     };
 
-    let area_8k = block_ram_area(m8k_bits, max_width_ram1);
-    let area_128k = block_ram_area(m128k_bits, max_width_ram2);
+    let area_8k = block_ram_area(arch.ram1_bits, arch.max_width_ram1, &arch.ram1_area);
+    let area_128k = block_ram_area(arch.ram2_bits, arch.max_width_ram2, &arch.ram2_area);
+
+    let extra_area: f64 = arch
+        .extra_rams
+        .iter()
+        .map(|ram| {
+            (nlb_arch / ram.lbs_per) as f64 * block_ram_area(ram.bits, ram.max_width, &ram.area)
+        })
+        .sum();
 
     let bram_area = (num_m8k_arch as f64) * area_8k + (num_m128k_arch as f64) * area_128k;
 
-    logic_area + bram_area
+    logic_area + bram_area + extra_area
 }
 
 pub fn write_mappings(path: &str, mappings: &[RamMapping]) -> io::Result<()> {
@@ -107,7 +182,421 @@ pub fn write_mappings(path: &str, mappings: &[RamMapping]) -> io::Result<()> {
     Ok(())
 }
 
-pub fn compute_geometric_area(logic_block_file: &str, mapped_file: &str) -> io::Result<f64> {
+// The mappings as a JSON array of objects with RamMapping's field names, in the same
+// order as write_mappings
+pub fn write_mappings_json(path: &str, mappings: &[RamMapping]) -> io::Result<()> {
+    let mut sorted: Vec<&RamMapping> = mappings.iter().collect();
+    sorted.sort_by_key(|m| (m.circuit_id, m.logical_ram_id));
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, &sorted)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+// Human-readable mappings: a header line per circuit, then its memories indented beneath
+pub fn write_mappings_pretty<W: Write>(
+    out: &mut W,
+    circuits: &[Circuit],
+    mappings: &[RamMapping],
+) -> io::Result<()> {
+    let mut by_circuit: HashMap<i32, Vec<&RamMapping>> = HashMap::new();
+    for m in mappings {
+        by_circuit.entry(m.circuit_id).or_default().push(m);
+    }
+
+    for c in circuits {
+        let mut members = by_circuit.remove(&c.id).unwrap_or_default();
+        members.sort_by_key(|m| m.logical_ram_id);
+        // a shared block is used once by its whole group
+        let mut groups = HashSet::new();
+        let blocks: i32 = (members.iter())
+            .filter(|m| groups.insert((m.group_id, m.phys_type)))
+            .map(|m| m.phys_blocks)
+            .sum();
+        writeln!(
+            out,
+            "Circuit {} (logic blocks {}, physical blocks used {})",
+            c.id, c.logic_blocks, blocks
+        )?;
+        for m in members {
+            writeln!(
+                out,
+                "    RAM {:<5} {:<14} {}x{} -> Type {} {}x{} S {} P {} group {} extra LUTs {}",
+                m.logical_ram_id,
+                m.mode.as_str(),
+                m.logical_width,
+                m.logical_depth,
+                m.phys_type.type_id(),
+                m.phys_width,
+                m.phys_depth,
+                m.series,
+                m.parallel,
+                m.group_id,
+                m.extra_luts
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Tidy (long-format) CSV for pivot tables: one row per (circuit, ram, metric, value)
+pub fn write_mappings_long_csv(
+    path: &str,
+    mappings: &[RamMapping],
+    configs: &PhysConfigs,
+) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["Circuit", "RamID", "Metric", "Value"])?;
+    for m in mappings {
+        let Some(cfg) = configs.get(m.phys_type) else {
+            continue;
+        };
+        // 1 for a LUT-only mapping, which has no blocks to fill
+        let utilization = m.utilization(cfg);
+        let metrics = [
+            ("type", m.phys_type.type_id().to_string()),
+            ("series", m.series.to_string()),
+            ("parallel", m.parallel.to_string()),
+            ("blocks", m.phys_blocks.to_string()),
+            ("extra_luts", m.extra_luts.to_string()),
+            ("utilization", format!("{:.4}", utilization)),
+            ("cost", format!("{:.3}", mapping_cost(m, cfg))),
+        ];
+        for (metric, value) in metrics {
+            writer.write_record([
+                m.circuit_id.to_string(),
+                m.logical_ram_id.to_string(),
+                metric.to_string(),
+                value,
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// One row per mapping with a header, for pandas or a spreadsheet. phys_type is the type id and
+// mode the implemented mode, as in ram_mapped.txt
+pub fn write_mappings_csv(path: &str, mappings: &[RamMapping]) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record([
+        "circuit_id",
+        "logical_ram_id",
+        "mode",
+        "phys_type",
+        "series",
+        "parallel",
+        "phys_width",
+        "phys_depth",
+        "phys_blocks",
+        "extra_luts",
+        "group_id",
+    ])?;
+    for m in mappings {
+        writer.write_record([
+            m.circuit_id.to_string(),
+            m.logical_ram_id.to_string(),
+            m.mode.as_str().to_string(),
+            m.phys_type.type_id().to_string(),
+            m.series.to_string(),
+            m.parallel.to_string(),
+            m.phys_width.to_string(),
+            m.phys_depth.to_string(),
+            m.phys_blocks.to_string(),
+            m.extra_luts.to_string(),
+            m.group_id.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// One row per shared physical block group (more than one logical RAM): its member ram ids,
+// separated by spaces, and the fraction of the group's block bits they use
+pub fn write_shared_groups(
+    path: &str,
+    mappings: &[RamMapping],
+    configs: &PhysConfigs,
+) -> io::Result<()> {
+    let mut groups: BTreeMap<(i32, i32), Vec<&RamMapping>> = BTreeMap::new();
+    for m in mappings {
+        groups
+            .entry((m.circuit_id, m.group_id))
+            .or_default()
+            .push(m);
+    }
+
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record([
+        "circuit_id",
+        "group_id",
+        "phys_type",
+        "logical_ram_ids",
+        "phys_blocks",
+        "utilization",
+    ])?;
+    for ((circuit_id, group_id), members) in groups {
+        let first = members[0];
+        // a width-split RAM's parts share a group id but not a block, and a LUT-only
+        // mapping has no block at all
+        if first.is_lut_only()
+            || members
+                .iter()
+                .all(|m| m.logical_ram_id == first.logical_ram_id)
+        {
+            continue;
+        }
+        let Some(cfg) = configs.get(first.phys_type) else {
+            continue;
+        };
+        let logical_bits: f64 = members
+            .iter()
+            .map(|m| m.logical_width as f64 * m.logical_depth as f64)
+            .sum();
+        let utilization = logical_bits / (first.phys_blocks as f64 * cfg.bits as f64);
+        let ram_ids: Vec<String> = members
+            .iter()
+            .map(|m| m.logical_ram_id.to_string())
+            .collect();
+        writer.write_record([
+            circuit_id.to_string(),
+            group_id.to_string(),
+            first.phys_type.type_id().to_string(),
+            ram_ids.join(" "),
+            first.phys_blocks.to_string(),
+            format!("{:.4}", utilization),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Writes the logical side of each mapping in the logical_rams.txt layout read_data expects,
+// so a run's memories can be edited and fed back in
+pub fn write_logical_rams(path: &str, mappings: &[RamMapping]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+
+    let mut sorted: Vec<&RamMapping> = mappings.iter().collect();
+    sorted.sort_by_key(|m| (m.circuit_id, m.logical_ram_id));
+    let mut circuit_ids: Vec<i32> = sorted.iter().map(|m| m.circuit_id).collect();
+    circuit_ids.dedup();
+
+    writeln!(file, "Num_Circuits {}", circuit_ids.len())?;
+    writeln!(file, "Circuit\tRamID\tMode\t\tDepth\tWidth")?;
+    for (i, m) in sorted.iter().enumerate() {
+        let same_ram =
+            |o: &RamMapping| (o.circuit_id, o.logical_ram_id) == (m.circuit_id, m.logical_ram_id);
+        // the parts of a width-split RAM are written once, with their widths added back up
+        if i > 0 && same_ram(sorted[i - 1]) {
+            continue;
+        }
+        let width: i32 = sorted[i..]
+            .iter()
+            .take_while(|o| same_ram(o))
+            .map(|o| o.logical_width)
+            .sum();
+        writeln!(
+            file,
+            "{}\t{}\t{}\t{}\t{}",
+            m.circuit_id,
+            m.logical_ram_id,
+            m.logical_mode.as_str(),
+            m.logical_depth,
+            width
+        )?;
+    }
+    file.flush()
+}
+
+// Binary mapping file, all integers little-endian:
+//   magic "RMAP", version u8 (= 1), record count u32
+//   per record: circuit_id, logical_ram_id, extra_luts, logical_width, logical_depth,
+//   group_id, series, parallel as i32; phys_type (type id), mode and logical mode (codes)
+//   as u8; phys_width, phys_depth, phys_blocks as i32
+const MAPPINGS_BIN_MAGIC: &[u8; 4] = b"RMAP";
+const MAPPINGS_BIN_VERSION: u8 = 1;
+
+pub fn write_mappings_bin(path: &str, mappings: &[RamMapping]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAPPINGS_BIN_MAGIC)?;
+    out.write_all(&[MAPPINGS_BIN_VERSION])?;
+    out.write_all(&(mappings.len() as u32).to_le_bytes())?;
+
+    for m in mappings {
+        for v in [
+            m.circuit_id,
+            m.logical_ram_id,
+            m.extra_luts,
+            m.logical_width,
+            m.logical_depth,
+            m.group_id,
+            m.series,
+            m.parallel,
+        ] {
+            out.write_all(&v.to_le_bytes())?;
+        }
+        out.write_all(&[
+            m.phys_type.type_id() as u8,
+            m.mode.code() as u8,
+            m.logical_mode.code() as u8,
+        ])?;
+        for v in [m.phys_width, m.phys_depth, m.phys_blocks] {
+            out.write_all(&v.to_le_bytes())?;
+        }
+    }
+    out.flush()
+}
+
+pub fn read_mappings_bin(path: &str) -> io::Result<Vec<RamMapping>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut input = BufReader::new(File::open(path)?);
+
+    let mut header = [0u8; 9];
+    input.read_exact(&mut header)?;
+    if &header[0..4] != MAPPINGS_BIN_MAGIC {
+        return Err(invalid(format!("{} is not a binary mapping file", path)));
+    }
+    if header[4] != MAPPINGS_BIN_VERSION {
+        return Err(invalid(format!(
+            "unsupported mapping file version {}",
+            header[4]
+        )));
+    }
+    let count = u32::from_le_bytes([header[5], header[6], header[7], header[8]]) as usize;
+
+    let read_i32 = |input: &mut BufReader<File>| -> io::Result<i32> {
+        let mut buf = [0u8; 4];
+        input.read_exact(&mut buf)?;
+        Ok(i32::from_le_bytes(buf))
+    };
+
+    let mut mappings = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut head = [0i32; 8];
+        for v in head.iter_mut() {
+            *v = read_i32(&mut input)?;
+        }
+        let mut codes = [0u8; 3];
+        input.read_exact(&mut codes)?;
+        let phys_type = PhysType::from_type_id(codes[0] as i32)
+            .ok_or_else(|| invalid(format!("bad physical type id {}", codes[0])))?;
+        let mode = MemMode::from_code(codes[1] as i32)
+            .ok_or_else(|| invalid(format!("bad mode code {}", codes[1])))?;
+        let logical_mode = MemMode::from_code(codes[2] as i32)
+            .ok_or_else(|| invalid(format!("bad mode code {}", codes[2])))?;
+
+        let mapping = RamMapping {
+            circuit_id: head[0],
+            logical_ram_id: head[1],
+            extra_luts: head[2],
+            logical_width: head[3],
+            logical_depth: head[4],
+            group_id: head[5],
+            series: head[6],
+            parallel: head[7],
+            phys_type,
+            mode,
+            phys_width: read_i32(&mut input)?,
+            phys_depth: read_i32(&mut input)?,
+            phys_blocks: read_i32(&mut input)?,
+            logical_mode,
+        };
+        mapping.validate().map_err(invalid)?;
+        mappings.push(mapping);
+    }
+    Ok(mappings)
+}
+
+// Which architecture parameters the per-circuit geomean uses
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AreaModel {
+    // fixed 10/300 LB spacing and default M8K/M128K block areas, whatever -p says
+    Legacy,
+    // the configured spacing, block sizes and widths, same as compute_total_area (default)
+    Unified,
+}
+
+// Per-circuit area parameters for compute_geometric_area. A spacing of 0 means no sites.
+#[derive(Clone, Debug)]
+pub struct GeomeanParams {
+    pub lutram_fraction: f64,
+    pub lbs_per_m8k: i32,
+    pub lbs_per_m128k: i32,
+    pub area_8k_block: f64,
+    pub area_128k_block: f64,
+    pub lb_area: f64,
+    // (lbs_per, block area) of each extra BRAM tier, indexed like PhysType::Extra
+    pub extra: Vec<(i32, f64)>,
+}
+
+impl GeomeanParams {
+    pub fn legacy(lutram_fraction: f64) -> Self {
+        GeomeanParams {
+            lutram_fraction,
+            lbs_per_m8k: 10,
+            lbs_per_m128k: 300,
+            area_8k_block: block_ram_area(
+                PHYS_RAM1.bits,
+                PHYS_RAM1.max_width_non_tdp,
+                &BramAreaModel::DEFAULT,
+            ),
+            area_128k_block: block_ram_area(
+                PHYS_RAM2.bits,
+                PHYS_RAM2.max_width_non_tdp,
+                &BramAreaModel::DEFAULT,
+            ),
+            lb_area: AVG_LB_AREA,
+            extra: Vec::new(),
+        }
+    }
+
+    // the parameters area_model takes from arch; legacy keeps the default block area
+    // coefficients too. Extra BRAM tiers have no legacy equivalent, so both models use their
+    // configured spacing, size and coefficients
+    pub fn for_model(arch: &ArchConfig, area_model: AreaModel) -> Self {
+        let params = match area_model {
+            AreaModel::Legacy => GeomeanParams::legacy(arch.lutram_fraction),
+            AreaModel::Unified => GeomeanParams {
+                lutram_fraction: arch.lutram_fraction,
+                lbs_per_m8k: if arch.has_ram1 { arch.lbs_per_ram1 } else { 0 },
+                lbs_per_m128k: if arch.has_ram2 { arch.lbs_per_ram2 } else { 0 },
+                area_8k_block: block_ram_area(arch.ram1_bits, arch.max_width_ram1, &arch.ram1_area),
+                area_128k_block: block_ram_area(
+                    arch.ram2_bits,
+                    arch.max_width_ram2,
+                    &arch.ram2_area,
+                ),
+                lb_area: AVG_LB_AREA,
+                extra: Vec::new(),
+            },
+        };
+        GeomeanParams {
+            extra: arch
+                .extra_rams
+                .iter()
+                .map(|ram| {
+                    (
+                        ram.lbs_per,
+                        block_ram_area(ram.bits, ram.max_width, &ram.area),
+                    )
+                })
+                .collect(),
+            ..params
+        }
+    }
+}
+
+// Per-circuit LB requirement read back from the logic-block and mapped files, skipping the
+// logic-block header lines as read_data does. circuit_ids restricts it to those circuits;
+// None covers every circuit in the file
+pub fn circuit_lb_requirements(
+    logic_block_file: &str,
+    mapped_file: &str,
+    read_opts: &ReadOptions,
+    params: &GeomeanParams,
+    circuit_ids: Option<&[i32]>,
+) -> io::Result<BTreeMap<i32, LbRequirement>> {
     // ----- Step 1: read logic blocks per circuit -----
     let mut logic_blocks_map: HashMap<i32, i32> = HashMap::new();
     let file = File::open(logic_block_file)?;
@@ -119,8 +608,7 @@ pub fn compute_geometric_area(logic_block_file: &str, mapped_file: &str) -> io::
         if line.is_empty() {
             continue;
         }
-        if line_idx == 0 {
-            // skip header
+        if line_idx < read_opts.logic_blocks_header {
             continue;
         }
         let parts: Vec<&str> = line.split_whitespace().collect();
@@ -135,12 +623,14 @@ pub fn compute_geometric_area(logic_block_file: &str, mapped_file: &str) -> io::
             Ok(v) => v,
             Err(_) => continue,
         };
+        if circuit_ids.is_some_and(|ids| !ids.contains(&circuit_id)) {
+            continue;
+        }
         logic_blocks_map.insert(circuit_id, logic_blocks);
     }
 
     //read mapped file and accumulate usage per circuit
-    // maps as circuit id, lutram_blocks_used, m8k_blocks_used, m128k_blocks_used, extra_luts
-    let mut usage: HashMap<i32, (i32, i32, i32, i32)> = HashMap::new();
+    let mut usage: HashMap<i32, MappedUsage> = HashMap::new();
 
     let file = File::open(mapped_file)?;
     let reader = BufReader::new(file);
@@ -174,59 +664,172 @@ pub fn compute_geometric_area(logic_block_file: &str, mapped_file: &str) -> io::
         };
         let phys_blocks = series * parallel;
 
-        let phys_type_id: i32 = match parts[14].parse() {
-            Ok(v) => v,
-            Err(_) => continue,
+        // numeric id (1/2/3/4...) or canonical name (LUTRAM/M8K/M128K/RAM3...)
+        let phys_type = match parts[14].parse() {
+            Ok(v) => PhysType::from_type_id(v),
+            Err(_) => PhysType::from_name(parts[14]),
         };
 
-        let entry = usage
-            .entry(circuit_id)
-            .or_insert((0_i32, 0_i32, 0_i32, 0_i32));
+        let entry = usage.entry(circuit_id).or_default();
 
-        entry.3 += extra_luts; // accumulate extra LUTs
+        entry.extra_luts += extra_luts; // accumulate extra LUTs
 
-        match phys_type_id {
-            1 => entry.0 += phys_blocks, // LUTRAM
-            2 => entry.1 += phys_blocks, // M8K
-            3 => entry.2 += phys_blocks, // M128K
-            _ => {}
+        if let Some(phys_type) = phys_type {
+            entry.add(phys_type, phys_blocks);
         }
     }
 
+    Ok(requirements_from_usage(&logic_blocks_map, &usage, params))
+}
+
+// Whole blocks of each type and extra LUTs of one circuit's mappings
+#[derive(Clone, Debug, Default)]
+struct MappedUsage {
+    lutram: i32,
+    m8k: i32,
+    m128k: i32,
+    // indexed like PhysType::Extra
+    extra: Vec<i32>,
+    extra_luts: i32,
+}
+
+impl MappedUsage {
+    fn add(&mut self, phys_type: PhysType, blocks: i32) {
+        match phys_type {
+            PhysType::Lutram => self.lutram += blocks,
+            PhysType::Ram8K => self.m8k += blocks,
+            PhysType::Ram128K => self.m128k += blocks,
+            PhysType::Extra(i) => {
+                let i = i as usize;
+                if self.extra.len() <= i {
+                    self.extra.resize(i + 1, 0);
+                }
+                self.extra[i] += blocks;
+            }
+        }
+    }
+}
+
+// one entry per logic_blocks key; circuits without usage map nothing
+fn requirements_from_usage(
+    logic_blocks_map: &HashMap<i32, i32>,
+    usage: &HashMap<i32, MappedUsage>,
+    params: &GeomeanParams,
+) -> BTreeMap<i32, LbRequirement> {
+    let none = MappedUsage::default();
+    let mut requirements = BTreeMap::new();
+    for (&cid, &logic_blocks) in logic_blocks_map.iter() {
+        let used = usage.get(&cid).unwrap_or(&none);
+
+        let extra_logic_blocks = (used.extra_luts + 9) / 10;
+        let extra_sites = params
+            .extra
+            .iter()
+            .zip(&used.extra)
+            .map(|(&(lbs_per, _), &blocks)| lbs_per * blocks)
+            .max()
+            .unwrap_or(0);
+        let req = LbRequirement {
+            logic: logic_blocks + extra_logic_blocks + used.lutram,
+            m8k_sites: params.lbs_per_m8k * used.m8k,
+            m128k_sites: params.lbs_per_m128k * used.m128k,
+            extra_sites,
+            lutram_capacity: lutram_capacity_lbs(used.lutram, params.lutram_fraction),
+        };
+        requirements.insert(cid, req);
+    }
+    requirements
+}
+
+// circuit_ids restricts the mean to those circuits; None covers every circuit in the file
+pub fn compute_geometric_area(
+    logic_block_file: &str,
+    mapped_file: &str,
+    read_opts: &ReadOptions,
+    params: &GeomeanParams,
+    circuit_ids: Option<&[i32]>,
+) -> io::Result<f64> {
+    let requirements = circuit_lb_requirements(
+        logic_block_file,
+        mapped_file,
+        read_opts,
+        params,
+        circuit_ids,
+    )?;
+    Ok(geomean_from_requirements(&requirements, params))
+}
+
+// Same requirements as circuit_lb_requirements, straight from a result instead of the written files
+pub fn lb_requirements_from_result(
+    circuits: &[Circuit],
+    result: &CircuitResult,
+    params: &GeomeanParams,
+) -> BTreeMap<i32, LbRequirement> {
+    let logic_blocks_map: HashMap<i32, i32> =
+        circuits.iter().map(|c| (c.id, c.logic_blocks)).collect();
+    let mut usage: HashMap<i32, MappedUsage> = HashMap::new();
+    for m in &result.mappings {
+        let entry = usage.entry(m.circuit_id).or_default();
+        entry.extra_luts += m.extra_luts;
+        entry.add(m.phys_type, m.phys_blocks);
+    }
+    requirements_from_usage(&logic_blocks_map, &usage, params)
+}
+
+// Same geomean as compute_geometric_area, straight from a result instead of the written files
+pub fn compute_geometric_area_in_memory(
+    circuits: &[Circuit],
+    result: &CircuitResult,
+    params: &GeomeanParams,
+) -> f64 {
+    let requirements = lb_requirements_from_result(circuits, result, params);
+    geomean_from_requirements(&requirements, params)
+}
+
+// Geomean of result under arch's own spacing and block areas (the unified area model)
+pub fn geometric_area_from_result(
+    circuits: &[Circuit],
+    result: &CircuitResult,
+    arch: &ArchConfig,
+) -> f64 {
+    let params = GeomeanParams::for_model(arch, AreaModel::Unified);
+    compute_geometric_area_in_memory(circuits, result, &params)
+}
+
+fn geomean_from_requirements(
+    requirements: &BTreeMap<i32, LbRequirement>,
+    params: &GeomeanParams,
+) -> f64 {
     //per-circuit area with SAME MODEL as compute_total_area -----
-    let area_8k_block = block_ram_area(PHYS_RAM1.bits, PHYS_RAM1.max_width_non_tdp);
-    let area_128k_block = block_ram_area(PHYS_RAM2.bits, PHYS_RAM2.max_width_non_tdp);
+    let area_8k_block = params.area_8k_block;
+    let area_128k_block = params.area_128k_block;
 
     let scale = 1.0e7_f64;
     let mut product = 1.0_f64;
     let mut count = 0_usize;
 
-    for (cid, logic_blocks) in logic_blocks_map.iter() {
-        let (lutram_used, m8k_used, m128k_used, extra_luts) =
-            usage.get(cid).copied().unwrap_or((0, 0, 0, 0));
-
-        let extra_logic_blocks = (extra_luts + 9) / 10;
-        let mut nlb_arch = logic_blocks + extra_logic_blocks + lutram_used;
-
-        let lb_for_m8k = 10 * m8k_used;
-        let lb_for_m128k = 300 * m128k_used;
-        let lb_for_lutram_capacity = 2 * lutram_used;
-
-        if nlb_arch < lb_for_m8k {
-            nlb_arch = lb_for_m8k;
-        }
-        if nlb_arch < lb_for_m128k {
-            nlb_arch = lb_for_m128k;
-        }
-        if nlb_arch < lb_for_lutram_capacity {
-            nlb_arch = lb_for_lutram_capacity;
-        }
+    for req in requirements.values() {
+        let nlb_arch = req.nlb_arch();
 
-        let avail_8k = nlb_arch / 10;
-        let avail_128k = nlb_arch / 300;
+        let avail_8k = if params.lbs_per_m8k > 0 {
+            nlb_arch / params.lbs_per_m8k
+        } else {
+            0
+        };
+        let avail_128k = if params.lbs_per_m128k > 0 {
+            nlb_arch / params.lbs_per_m128k
+        } else {
+            0
+        };
 
-        let logic_area = (nlb_arch as f64) * AVG_LB_AREA;
-        let bram_area = (avail_8k as f64) * area_8k_block + (avail_128k as f64) * area_128k_block;
+        let logic_area = (nlb_arch as f64) * params.lb_area;
+        let bram_area = (avail_8k as f64) * area_8k_block
+            + (avail_128k as f64) * area_128k_block
+            + params
+                .extra
+                .iter()
+                .map(|&(lbs_per, area)| (nlb_arch / lbs_per) as f64 * area)
+                .sum::<f64>();
 
         let total_area_circuit = logic_area + bram_area;
 
@@ -236,41 +839,150 @@ pub fn compute_geometric_area(logic_block_file: &str, mapped_file: &str) -> io::
     }
 
     if count == 0 {
-        return Ok(0.0);
+        return 0.0;
     }
 
     let nth = 1.0 / (count as f64);
     let geom_scaled = product.powf(nth);
-    let geom = geom_scaled * scale;
+    geom_scaled * scale
+}
 
-    Ok(geom)
+// Blocks and extra LUTs attributed to one circuit in results.csv. BRAM counts are
+// fractional when shared blocks are split between their sharers.
+#[derive(Clone, Debug, Default)]
+pub struct CircuitUsage {
+    pub lutram_blocks: i32,
+    pub m8k_blocks: f64,
+    pub m128k_blocks: f64,
+    // indexed like PhysType::Extra; shorter when the last tiers are unused
+    pub extra_blocks: Vec<f64>,
+    pub extra_luts: i32,
+}
+
+impl CircuitUsage {
+    pub fn blocks(&self, phys_type: PhysType) -> f64 {
+        match phys_type {
+            PhysType::Lutram => self.lutram_blocks as f64,
+            PhysType::Ram8K => self.m8k_blocks,
+            PhysType::Ram128K => self.m128k_blocks,
+            PhysType::Extra(i) => self.extra_blocks.get(i as usize).copied().unwrap_or(0.0),
+        }
+    }
 }
 
+// Per-circuit usage. With fractional_sharing each member of a shared group is charged
+// 1/k of the block (0.5 for a pair); otherwise every mapping counts its whole blocks.
+pub fn circuit_usage(
+    circuits: &[Circuit],
+    mappings: &[RamMapping],
+    fractional_sharing: bool,
+) -> HashMap<i32, CircuitUsage> {
+    // logical RAMs per group; the parts of a width-split RAM count once
+    let mut group_rams: HashMap<(i32, i32), Vec<i32>> = HashMap::new();
+    for m in mappings {
+        let rams = group_rams.entry((m.circuit_id, m.group_id)).or_default();
+        if !rams.contains(&m.logical_ram_id) {
+            rams.push(m.logical_ram_id);
+        }
+    }
+
+    let mut usage: HashMap<i32, CircuitUsage> = HashMap::new();
+    for c in circuits {
+        usage.entry(c.id).or_default();
+    }
+    for m in mappings {
+        let share = if fractional_sharing {
+            group_rams[&(m.circuit_id, m.group_id)].len() as f64
+        } else {
+            1.0
+        };
+        let blocks = m.phys_blocks as f64 / share;
+        let entry = usage.entry(m.circuit_id).or_default();
+        match m.phys_type {
+            PhysType::Lutram => entry.lutram_blocks += m.phys_blocks,
+            PhysType::Ram8K => entry.m8k_blocks += blocks,
+            PhysType::Ram128K => entry.m128k_blocks += blocks,
+            PhysType::Extra(i) => {
+                let i = i as usize;
+                if entry.extra_blocks.len() <= i {
+                    entry.extra_blocks.resize(i + 1, 0.0);
+                }
+                entry.extra_blocks[i] += blocks;
+            }
+        }
+        entry.extra_luts += m.extra_luts;
+    }
+    usage
+}
+
+// Geometric mean of each type's per-circuit block count across the suite. Circuits that use
+// none of a type are left out of that type's mean; None if no circuit uses the type.
+pub fn per_type_block_geomeans(
+    usage: &HashMap<i32, CircuitUsage>,
+    types: &[PhysType],
+) -> Vec<(PhysType, Option<f64>)> {
+    let geomean = |t: PhysType| {
+        let nonzero: Vec<f64> = usage
+            .values()
+            .map(|u| u.blocks(t))
+            .filter(|&c| c > 0.0)
+            .collect();
+        (!nonzero.is_empty()).then(|| {
+            let log_sum: f64 = nonzero.iter().map(|c| c.ln()).sum();
+            (log_sum / nonzero.len() as f64).exp()
+        })
+    };
+    types.iter().map(|&t| (t, geomean(t))).collect()
+}
+
+// Column layout of results.csv, reported in the --summary JSON. Version 2 added
+// 128K_BRAMs_Used and fixed Regular_LBs_used, which used to hold the M128K count.
+pub const RESULTS_CSV_VERSION: u32 = 2;
+
 pub fn write_csv(
     results_file: &str,
     circuits: &[Circuit],
-    per_circuit: &HashMap<i32, (i32, i32, i32, i32)>,
+    per_circuit: &HashMap<i32, CircuitUsage>,
     area_8k: f64,
     area_128k: f64,
+    area_extra: &[f64],
+    area_lutram: Option<f64>,
 ) -> io::Result<()> {
     let mut writer = csv::Writer::from_path(results_file)?;
-    writer.write_record(&[
+    writer.write_record([
         "Circuit",
         "LUTRAM_Blocks_used",
         "8K_BRAMs_Used",
+        "128K_BRAMs_Used",
         "Regular_LBs_used",
         "Required_LB_Tiles_in_Chip",
         "Total_FPGA_Area",
     ])?;
 
     for c in circuits {
-        let (lutram_used, m8k_used, m128k_used, extra_luts) =
-            per_circuit.get(&c.id).copied().unwrap_or((0, 0, 0, 0));
+        let usage = per_circuit.get(&c.id).cloned().unwrap_or_default();
+        let (lutram_used, m8k_used, m128k_used, extra_luts) = (
+            usage.lutram_blocks,
+            usage.m8k_blocks,
+            usage.m128k_blocks,
+            usage.extra_luts,
+        );
 
         let regular_lbs_used = c.logic_blocks + (extra_luts + 9) / 10;
         let required_lb_tiles = regular_lbs_used + lutram_used;
-        let logic_area = required_lb_tiles as f64 * AVG_LB_AREA;
-        let bram_area = (m8k_used as f64) * area_8k + (m128k_used as f64) * area_128k;
+        // LUTRAM blocks are LB tiles unless the device gives them their own block area
+        let logic_area = match area_lutram {
+            Some(area) => regular_lbs_used as f64 * AVG_LB_AREA + lutram_used as f64 * area,
+            None => required_lb_tiles as f64 * AVG_LB_AREA,
+        };
+        // extra tiers have no column of their own but count towards the area
+        let extra_area: f64 = usage
+            .extra_blocks
+            .iter()
+            .zip(area_extra)
+            .map(|(blocks, area)| blocks * area)
+            .sum();
+        let bram_area = m8k_used * area_8k + m128k_used * area_128k + extra_area;
         let total_area_circuit = logic_area + bram_area;
         let total_area_cir_simplified = format!("{:.3}", total_area_circuit);
         //Printing csv data
@@ -283,8 +995,9 @@ pub fn write_csv(
         let results = (
             c.id,
             lutram_used,
-            m8k_used,
-            m128k_used,
+            m8k_used.to_string(),
+            m128k_used.to_string(),
+            regular_lbs_used,
             required_lb_tiles,
             total_area_cir_simplified,
         );
@@ -294,3 +1007,1627 @@ pub fn write_csv(
     writer.flush()?;
     Ok(())
 }
+
+// Resources available on a target device; None means unconstrained
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeviceLimits {
+    pub lbs: Option<i32>,
+    pub m8k: Option<i32>,
+    pub m128k: Option<i32>,
+}
+
+// parses "lbs=N,m8k=N,m128k=N"; any subset of the keys may be given
+pub fn parse_device(spec: &str) -> Result<DeviceLimits, String> {
+    let mut device = DeviceLimits::default();
+    for item in spec.split(',').filter(|s| !s.is_empty()) {
+        let (key, value) = item
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value, got '{}'", item))?;
+        let n: i32 = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("bad count for {}: '{}'", key, value))?;
+        match key.trim() {
+            "lbs" => device.lbs = Some(n),
+            "m8k" => device.m8k = Some(n),
+            "m128k" => device.m128k = Some(n),
+            other => return Err(format!("unknown device resource '{}'", other)),
+        }
+    }
+    Ok(device)
+}
+
+// Per-type BRAM block budgets for the balancing pass; None leaves a type unconstrained.
+// A move may raise a memory's mapping cost to at most max_cost_ratio times its current cost.
+#[derive(Clone, Debug)]
+pub struct BlockBudgets {
+    pub m8k: Option<i32>,
+    pub m128k: Option<i32>,
+    // indexed like PhysType::Extra
+    pub extra: Vec<Option<i32>>,
+    pub max_cost_ratio: f64,
+}
+
+impl Default for BlockBudgets {
+    fn default() -> Self {
+        BlockBudgets {
+            m8k: None,
+            m128k: None,
+            extra: Vec::new(),
+            max_cost_ratio: 2.0,
+        }
+    }
+}
+
+impl BlockBudgets {
+    pub fn get(&self, phys_type: PhysType) -> Option<i32> {
+        match phys_type {
+            PhysType::Lutram => None,
+            PhysType::Ram8K => self.m8k,
+            PhysType::Ram128K => self.m128k,
+            PhysType::Extra(i) => self.extra.get(i as usize).copied().flatten(),
+        }
+    }
+
+    fn set(&mut self, phys_type: PhysType, budget: i32) {
+        match phys_type {
+            PhysType::Lutram => {}
+            PhysType::Ram8K => self.m8k = Some(budget),
+            PhysType::Ram128K => self.m128k = Some(budget),
+            PhysType::Extra(i) => {
+                let i = i as usize;
+                if self.extra.len() <= i {
+                    self.extra.resize(i + 1, None);
+                }
+                self.extra[i] = Some(budget);
+            }
+        }
+    }
+}
+
+// --arch file layout. Keys left out keep their defaults; unknown keys are an error
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct ArchFile {
+    has_lutram: Option<bool>,
+    lutram_fraction: Option<f64>,
+    rom_discount: Option<f64>,
+    allow_tdp: Option<bool>,
+    pipeline_mux: Option<bool>,
+    pipeline_threshold: Option<i32>,
+    ram1: BramSection,
+    ram2: BramSection,
+    // [[extra_ram]] tables, ram3 first
+    extra_ram: Vec<ExtraRamSection>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct BramSection {
+    enabled: Option<bool>,
+    bits: Option<i32>,
+    lbs_per: Option<i32>,
+    max_width: Option<i32>,
+    area: AreaSection,
+}
+
+// [ramN.area] block area coefficients; keys left out keep the defaults
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct AreaSection {
+    base: Option<f64>,
+    per_bit: Option<f64>,
+    per_sqrt_bit: Option<f64>,
+    per_width: Option<f64>,
+}
+
+impl AreaSection {
+    fn from_model(model: &BramAreaModel) -> AreaSection {
+        AreaSection {
+            base: Some(model.base),
+            per_bit: Some(model.per_bit),
+            per_sqrt_bit: Some(model.per_sqrt_bit),
+            per_width: Some(model.per_width),
+        }
+    }
+
+    fn apply(&self, model: &mut BramAreaModel) {
+        for (value, field) in [
+            (self.base, &mut model.base),
+            (self.per_bit, &mut model.per_bit),
+            (self.per_sqrt_bit, &mut model.per_sqrt_bit),
+            (self.per_width, &mut model.per_width),
+        ] {
+            if let Some(v) = value {
+                *field = v;
+            }
+        }
+    }
+}
+
+// an extra tier has no defaults to fall back on, so every key but the area is required
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ExtraRamSection {
+    bits: i32,
+    lbs_per: i32,
+    max_width: i32,
+    #[serde(default)]
+    area: AreaSection,
+}
+
+// parses an --arch TOML file on top of the default architecture and validates the result
+pub fn parse_arch_toml(text: &str) -> Result<ArchConfig, String> {
+    let file: ArchFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    let mut arch = ArchConfig::default();
+    if let Some(v) = file.has_lutram {
+        arch.has_lutram = v;
+    }
+    if let Some(v) = file.lutram_fraction {
+        arch.lutram_fraction = v;
+    }
+    if let Some(v) = file.rom_discount {
+        arch.rom_discount = v;
+    }
+    if let Some(v) = file.allow_tdp {
+        arch.allow_tdp = v;
+    }
+    if let Some(v) = file.pipeline_mux {
+        arch.pipeline_mux = v;
+    }
+    if let Some(v) = file.pipeline_threshold {
+        arch.pipeline_threshold = v;
+    }
+    for (section, enabled, bits, lbs_per, max_width, area) in [
+        (
+            &file.ram1,
+            &mut arch.has_ram1,
+            &mut arch.ram1_bits,
+            &mut arch.lbs_per_ram1,
+            &mut arch.max_width_ram1,
+            &mut arch.ram1_area,
+        ),
+        (
+            &file.ram2,
+            &mut arch.has_ram2,
+            &mut arch.ram2_bits,
+            &mut arch.lbs_per_ram2,
+            &mut arch.max_width_ram2,
+            &mut arch.ram2_area,
+        ),
+    ] {
+        section.area.apply(area);
+        if let Some(v) = section.enabled {
+            *enabled = v;
+        }
+        if let Some(v) = section.bits {
+            *bits = v;
+        }
+        if let Some(v) = section.lbs_per {
+            *lbs_per = v;
+        }
+        if let Some(v) = section.max_width {
+            *max_width = v;
+        }
+    }
+    arch.extra_rams = file
+        .extra_ram
+        .iter()
+        .map(|ram| {
+            let mut area = BramAreaModel::DEFAULT;
+            ram.area.apply(&mut area);
+            ExtraRam {
+                bits: ram.bits,
+                lbs_per: ram.lbs_per,
+                max_width: ram.max_width,
+                area,
+            }
+        })
+        .collect();
+    arch.validate()?;
+    Ok(arch)
+}
+
+// the --arch file that parse_arch_toml reads back as arch, with every key written out
+pub fn arch_toml(arch: &ArchConfig) -> Result<String, String> {
+    let file = ArchFile {
+        has_lutram: Some(arch.has_lutram),
+        lutram_fraction: Some(arch.lutram_fraction),
+        rom_discount: Some(arch.rom_discount),
+        allow_tdp: Some(arch.allow_tdp),
+        pipeline_mux: Some(arch.pipeline_mux),
+        pipeline_threshold: Some(arch.pipeline_threshold),
+        ram1: BramSection {
+            enabled: Some(arch.has_ram1),
+            bits: Some(arch.ram1_bits),
+            lbs_per: Some(arch.lbs_per_ram1),
+            max_width: Some(arch.max_width_ram1),
+            area: AreaSection::from_model(&arch.ram1_area),
+        },
+        ram2: BramSection {
+            enabled: Some(arch.has_ram2),
+            bits: Some(arch.ram2_bits),
+            lbs_per: Some(arch.lbs_per_ram2),
+            max_width: Some(arch.max_width_ram2),
+            area: AreaSection::from_model(&arch.ram2_area),
+        },
+        extra_ram: (arch.extra_rams.iter())
+            .map(|ram| ExtraRamSection {
+                bits: ram.bits,
+                lbs_per: ram.lbs_per,
+                max_width: ram.max_width,
+                area: AreaSection::from_model(&ram.area),
+            })
+            .collect(),
+    };
+    toml::to_string(&file).map_err(|e| e.to_string())
+}
+
+// (key, old, new) for every parameter that differs, keyed by the -p parameter names
+pub fn arch_differences(old: &ArchConfig, new: &ArchConfig) -> Vec<(String, String, String)> {
+    let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return Vec::new();
+    };
+    old.into_iter()
+        .filter_map(|(key, old_value)| {
+            let new_value = new.get(&key)?;
+            (*new_value != old_value).then(|| (key, old_value.to_string(), new_value.to_string()))
+        })
+        .collect()
+}
+
+// parses "m8k=N,m128k=N,ratio=R"; any subset of the keys may be given
+pub fn parse_budgets(spec: &str) -> Result<BlockBudgets, String> {
+    let mut budgets = BlockBudgets::default();
+    for item in spec.split(',').filter(|s| !s.is_empty()) {
+        let (key, value) = item
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value, got '{}'", item))?;
+        let value = value.trim();
+        match key.trim() {
+            "ratio" => match value.parse::<f64>() {
+                Ok(r) if r >= 1.0 => budgets.max_cost_ratio = r,
+                _ => return Err(format!("ratio must be a number >= 1, got '{}'", value)),
+            },
+            // any BRAM type: m8k, m128k, ram3, ...
+            other => match PhysType::from_name(other) {
+                Some(t) if t != PhysType::Lutram => {
+                    let n: i32 = value
+                        .parse()
+                        .map_err(|_| format!("bad budget for {}: '{}'", other, value))?;
+                    budgets.set(t, n);
+                }
+                _ => return Err(format!("unknown budget key '{}'", other)),
+            },
+        }
+    }
+    Ok(budgets)
+}
+
+// parses "start:stop:step" into the inclusive list of fractions, each in (0, 1]
+pub fn parse_sweep(spec: &str) -> Result<Vec<f64>, String> {
+    let parts: Vec<&str> = spec.split(':').collect();
+    let [start, stop, step] = parts[..] else {
+        return Err(format!("expected start:stop:step, got '{}'", spec));
+    };
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<f64>()
+            .map_err(|_| format!("bad number '{}'", s))
+    };
+    let (start, stop, step) = (parse(start)?, parse(stop)?, parse(step)?);
+    if step <= 0.0 || stop < start {
+        return Err(format!("empty sweep '{}'", spec));
+    }
+    if start <= 0.0 || stop > 1.0 {
+        return Err(format!("fractions must lie in (0, 1], got '{}'", spec));
+    }
+    // computed from the index so the last value doesn't drift past stop
+    let count = ((stop - start) / step + 1e-9).floor() as usize + 1;
+    Ok((0..count).map(|i| start + i as f64 * step).collect())
+}
+
+// parses "WxD,WxD,..." into explicit (width, depth) physical shapes
+pub fn parse_shapes(spec: &str) -> Result<Vec<(i32, i32)>, String> {
+    let mut shapes = Vec::new();
+    for item in spec.split(',').filter(|s| !s.is_empty()) {
+        let (w, d) = item
+            .split_once('x')
+            .ok_or_else(|| format!("expected WxD, got '{}'", item))?;
+        match (w.trim().parse::<i32>(), d.trim().parse::<i32>()) {
+            (Ok(w), Ok(d)) if w > 0 && d > 0 => shapes.push((w, d)),
+            _ => return Err(format!("bad shape '{}'", item)),
+        }
+    }
+    if shapes.is_empty() {
+        return Err("no shapes given".into());
+    }
+    Ok(shapes)
+}
+
+// one line per resource the run needs more of than the device has
+pub fn device_shortfalls(
+    device: &DeviceLimits,
+    nlb_arch: i32,
+    result: &CircuitResult,
+) -> Vec<String> {
+    let checks = [
+        ("LB tiles", device.lbs, nlb_arch),
+        ("M8K blocks", device.m8k, result.m8k_blocks),
+        ("M128K blocks", device.m128k, result.m128k_blocks),
+    ];
+
+    let mut shortfalls = Vec::new();
+    for (name, available, needed) in checks {
+        if let Some(available) = available
+            && needed > available
+        {
+            shortfalls.push(format!(
+                "{}: need {}, device has {} (short by {})",
+                name,
+                needed,
+                available,
+                needed - available
+            ));
+        }
+    }
+    shortfalls
+}
+
+/// How far `geomean` is over `budget`, or None when it is within it. A NaN geomean counts
+/// as over.
+///
+/// ```
+/// use rapid_map::utils::{area_overage, geometric_area_from_result};
+/// use rapid_map::{ArchConfig, Circuit, MemMode, Memory, map_circuits};
+///
+/// let circuits = vec![Circuit {
+///     id: 0,
+///     logic_blocks: 500,
+///     memories: vec![Memory { ram_id: 0, mode: MemMode::TrueDualPort, depth: 4096, width: 32 }],
+/// }];
+/// let arch = ArchConfig::default();
+/// let result = map_circuits(&circuits, &arch).unwrap();
+/// let geomean = geometric_area_from_result(&circuits, &result, &arch);
+/// assert_eq!(area_overage(geomean, geomean * 1.01), None);
+/// let over = area_overage(geomean, geomean * 0.99).unwrap();
+/// assert!((over - geomean * 0.01).abs() < 1e-6 * geomean);
+/// ```
+pub fn area_overage(geomean: f64, budget: f64) -> Option<f64> {
+    if geomean <= budget {
+        None
+    } else {
+        Some(geomean - budget)
+    }
+}
+
+// number of logical RAMs of each (width, depth, mode) across all circuits
+pub fn shape_histogram(circuits: &[Circuit]) -> BTreeMap<(i32, i32, MemMode), usize> {
+    let mut hist = BTreeMap::new();
+    for c in circuits {
+        for mem in &c.memories {
+            *hist.entry((mem.width, mem.depth, mem.mode)).or_insert(0) += 1;
+        }
+    }
+    hist
+}
+
+// Run statistics derived from a mapping result
+#[derive(Clone, Debug, Default)]
+pub struct MapStats {
+    pub candidates_evaluated: u64,
+    // mean bit utilization of each type's blocks, weighted by blocks; None if the type is unused
+    pub lutram_utilization: Option<f64>,
+    pub m8k_utilization: Option<f64>,
+    pub m128k_utilization: Option<f64>,
+    // one per extra BRAM tier, indexed like PhysType::Extra
+    pub extra_utilization: Vec<Option<f64>>,
+    pub bits_per_area: f64,
+}
+
+// total logical bits over all mappings divided by the chip area from compute_total_area
+pub fn bits_per_area(result: &CircuitResult, total_area: f64) -> f64 {
+    let logical_bits: i64 = result
+        .mappings
+        .iter()
+        .map(|m| m.logical_width as i64 * m.logical_depth as i64)
+        .sum();
+    if total_area > 0.0 {
+        logical_bits as f64 / total_area
+    } else {
+        0.0
+    }
+}
+
+pub fn compute_stats(result: &CircuitResult, configs: &PhysConfigs, total_area: f64) -> MapStats {
+    // per type: logical bits, physical bits. A shared block counts once for its whole group,
+    // and LUT-only mappings have no block to fill
+    let mut bits: HashMap<i32, (f64, f64)> = HashMap::new();
+    let mut counted = HashSet::new();
+    for m in &result.mappings {
+        let Some(cfg) = configs.get(m.phys_type) else {
+            continue;
+        };
+        if m.is_lut_only() {
+            continue;
+        }
+        let entry = bits.entry(m.phys_type.type_id()).or_insert((0.0, 0.0));
+        entry.0 += (m.logical_width as i64 * m.logical_depth as i64) as f64;
+        if counted.insert((m.circuit_id, m.group_id, m.phys_type)) {
+            entry.1 += (m.phys_blocks as i64 * cfg.bits as i64) as f64;
+        }
+    }
+    let utilization = |t: PhysType| {
+        bits.get(&t.type_id())
+            .filter(|(_, phys)| *phys > 0.0)
+            .map(|(logical, phys)| logical / phys)
+    };
+
+    MapStats {
+        candidates_evaluated: result.candidates_evaluated,
+        lutram_utilization: utilization(PhysType::Lutram),
+        m8k_utilization: utilization(PhysType::Ram8K),
+        m128k_utilization: utilization(PhysType::Ram128K),
+        extra_utilization: (0..configs.extra.len())
+            .map(|i| utilization(PhysType::Extra(i as u8)))
+            .collect(),
+        bits_per_area: bits_per_area(result, total_area),
+    }
+}
+
+pub fn print_stats(stats: &MapStats) {
+    let fmt = |u: Option<f64>| u.map_or("n/a".to_string(), |u| format!("{:.4}", u));
+    eprintln!("Candidates evaluated: {}", stats.candidates_evaluated);
+    eprintln!("LUTRAM utilization: {}", fmt(stats.lutram_utilization));
+    eprintln!("M8K utilization: {}", fmt(stats.m8k_utilization));
+    eprintln!("M128K utilization: {}", fmt(stats.m128k_utilization));
+    for (i, &u) in stats.extra_utilization.iter().enumerate() {
+        eprintln!(
+            "{} utilization: {}",
+            PhysType::Extra(i as u8).name().to_uppercase(),
+            fmt(u)
+        );
+    }
+    eprintln!("Bits per area: {:.5e}", stats.bits_per_area);
+}
+
+// --explain: every candidate the search costs for each memory of circuit, with the cheapest of
+// each type marked, then how the mapping in result compares with them
+pub fn print_explanation(
+    circuit: &Circuit,
+    result: &CircuitResult,
+    configs: &PhysConfigs,
+    type_priority: &[PhysType],
+) {
+    let shape = |m: &RamMapping| (m.phys_type, m.phys_width, m.series, m.parallel);
+    eprintln!(
+        "Circuit {} ({} logic blocks, {} RAMs):",
+        circuit.id,
+        circuit.logic_blocks,
+        circuit.memories.len()
+    );
+    for mem in &circuit.memories {
+        eprintln!(
+            "  RAM {} {} {}x{}:",
+            mem.ram_id,
+            mem.mode.as_str(),
+            mem.depth,
+            mem.width
+        );
+        let candidates = candidate_costs(circuit.id, mem, configs, type_priority);
+        // index of each type's cheapest candidate, in priority order; strict < keeps the
+        // first on a tie, as the search does
+        let mut best: Vec<usize> = Vec::new();
+        for (i, (m, cost)) in candidates.iter().enumerate() {
+            match best
+                .iter_mut()
+                .find(|b| candidates[**b].0.phys_type == m.phys_type)
+            {
+                Some(b) if *cost < candidates[*b].1 => *b = i,
+                Some(_) => {}
+                None => best.push(i),
+            }
+        }
+        for (i, (m, cost)) in candidates.iter().enumerate() {
+            eprintln!(
+                "    {} {}x{} S {} P {} blocks {} extra LUTs {} cost {:.3e}{}",
+                m.phys_type.name(),
+                m.phys_width,
+                m.phys_depth,
+                m.series,
+                m.parallel,
+                m.phys_blocks,
+                m.extra_luts,
+                cost,
+                if best.contains(&i) { " *" } else { "" }
+            );
+        }
+
+        let parts: Vec<&RamMapping> = result
+            .mappings
+            .iter()
+            .filter(|m| m.circuit_id == circuit.id && m.logical_ram_id == mem.ram_id)
+            .collect();
+        let Some(&chosen) = parts.first() else {
+            eprintln!("    not mapped");
+            continue;
+        };
+        for m in &parts {
+            if m.is_lut_only() {
+                eprintln!("    chosen: LUTs only, {} extra LUTs", m.extra_luts);
+                continue;
+            }
+            eprintln!(
+                "    chosen: {} {}x{} S {} P {}{}",
+                m.phys_type.name(),
+                m.phys_width,
+                m.phys_depth,
+                m.series,
+                m.parallel,
+                if parts.len() > 1 {
+                    format!(" for {} bits of the width", m.logical_width)
+                } else {
+                    String::new()
+                }
+            );
+        }
+        // the overall winner; on a tie the type earlier in the priority order
+        let mut ranked: Vec<&(RamMapping, f64)> = best.iter().map(|&i| &candidates[i]).collect();
+        ranked.sort_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((cheapest, cost)) = ranked.first() {
+            if chosen.is_lut_only() {
+                let lut_cost = configs
+                    .get(chosen.phys_type)
+                    .map_or(0.0, |cfg| mapping_cost(chosen, cfg));
+                eprintln!(
+                    "    LUTs cost {:.3e}, below the best physical RAM ({} at {:.3e})",
+                    lut_cost,
+                    cheapest.phys_type.name(),
+                    cost
+                );
+            } else if parts.len() > 1 {
+                let split_cost: f64 = parts
+                    .iter()
+                    .filter_map(|m| configs.get(m.phys_type).map(|cfg| mapping_cost(m, cfg)))
+                    .sum();
+                eprintln!(
+                    "    width split costs {:.3e}, below the best single type ({} at {:.3e})",
+                    split_cost,
+                    cheapest.phys_type.name(),
+                    cost
+                );
+            } else if shape(chosen) != shape(cheapest) {
+                eprintln!(
+                    "    not the cheapest candidate ({} at {:.3e}); set by a hint, --lut-budget or a pass after mapping",
+                    cheapest.phys_type.name(),
+                    cost
+                );
+            } else if let Some((next, next_cost)) = ranked.get(1) {
+                if next_cost > cost {
+                    eprintln!(
+                        "    cheapest; the best {} costs {:.3e} (+{:.1}%)",
+                        next.phys_type.name(),
+                        next_cost,
+                        (next_cost / cost - 1.0) * 100.0
+                    );
+                } else {
+                    eprintln!(
+                        "    ties with {}, which comes later in the type priority",
+                        next.phys_type.name()
+                    );
+                }
+            } else {
+                eprintln!("    cheapest; no other enabled type can hold it");
+            }
+        }
+        let sharers: Vec<String> = result
+            .mappings
+            .iter()
+            .filter(|m| {
+                m.circuit_id == circuit.id
+                    && m.group_id == chosen.group_id
+                    && m.logical_ram_id != mem.ram_id
+            })
+            .map(|m| m.logical_ram_id.to_string())
+            .collect();
+        if !sharers.is_empty() {
+            eprintln!("    shares its block with RAM {}", sharers.join(", "));
+        }
+    }
+}
+
+// the n mappings with the highest mapping_cost, most expensive first
+pub fn top_cost_mappings<'a>(
+    mappings: &'a [RamMapping],
+    configs: &PhysConfigs,
+    n: usize,
+) -> Vec<(&'a RamMapping, f64)> {
+    let mut costed: Vec<(&RamMapping, f64)> = mappings
+        .iter()
+        .filter_map(|m| {
+            configs
+                .get(m.phys_type)
+                .map(|cfg| (m, mapping_cost(m, cfg)))
+        })
+        .collect();
+    costed.sort_by(|a, b| b.1.total_cmp(&a.1));
+    costed.truncate(n);
+    costed
+}
+
+// Size and modification time of an input file, recorded in the run manifest
+#[derive(Debug, Serialize)]
+pub struct InputFileInfo {
+    pub path: String,
+    pub size: u64,
+    pub modified_unix_secs: Option<u64>,
+}
+
+pub fn input_file_info(path: &str) -> io::Result<InputFileInfo> {
+    let meta = std::fs::metadata(path)?;
+    let modified_unix_secs = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+    Ok(InputFileInfo {
+        path: path.to_string(),
+        size: meta.len(),
+        modified_unix_secs,
+    })
+}
+
+// Architecture and options the run actually used, after defaults and flags are applied
+#[derive(Debug, Serialize)]
+pub struct EffectiveConfig {
+    // the -p values, flattened so they stay top-level keys in the JSON
+    #[serde(flatten)]
+    pub arch: ArchConfig,
+    pub area_model: AreaModel,
+    pub sharing_iterations: usize,
+    pub type_priority: Vec<String>,
+    // command line as given, so flags without a field here are still recorded
+    pub args: Vec<String>,
+}
+
+// Everything needed to audit or cache-key a run
+#[derive(Debug, Serialize)]
+pub struct RunManifest {
+    pub crate_version: &'static str,
+    pub inputs: Vec<InputFileInfo>,
+    pub config: EffectiveConfig,
+    // CircuitResult::content_hash as 16 hex digits
+    pub content_hash: String,
+    pub geomean_area: f64,
+}
+
+pub fn write_manifest(path: &str, manifest: &RunManifest) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, manifest)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+// Architecture, inputs and chip-level totals of a run, for --summary
+#[derive(Debug, Serialize)]
+pub struct RunSummary<'a> {
+    pub crate_version: &'static str,
+    pub results_csv_version: u32,
+    pub arch: &'a ArchConfig,
+    pub logic_block_file: &'a str,
+    pub logical_rams_file: &'a str,
+    // circuits mapped, after --circuits and any skipped ones
+    pub circuits: usize,
+    pub skipped_circuits: usize,
+    pub geomean_area: f64,
+    pub total_area: f64,
+    pub extra_luts: i32,
+    pub lutram_blocks: i32,
+    pub m8k_blocks: i32,
+    pub m128k_blocks: i32,
+    // one per extra BRAM tier, ram3 first
+    pub extra_blocks: &'a [i32],
+    // per type with any mappings, in type id order
+    pub utilization: Vec<TypeUtilization>,
+}
+
+// RamMapping::utilization over one type's mappings, each mapping weighted equally
+#[derive(Debug, Serialize)]
+pub struct TypeUtilization {
+    pub phys_type: String,
+    pub mappings: usize,
+    pub mean: f64,
+    pub min: f64,
+}
+
+// LUT-only mappings have no block to fill and are left out
+pub fn type_utilizations(result: &CircuitResult, configs: &PhysConfigs) -> Vec<TypeUtilization> {
+    configs
+        .all_types()
+        .into_iter()
+        .filter_map(|t| {
+            let cfg = configs.get(t)?;
+            let values: Vec<f64> = result
+                .mappings
+                .iter()
+                .filter(|m| m.phys_type == t && !m.is_lut_only())
+                .map(|m| m.utilization(cfg))
+                .collect();
+            (!values.is_empty()).then(|| TypeUtilization {
+                phys_type: t.name().into_owned(),
+                mappings: values.len(),
+                mean: values.iter().sum::<f64>() / values.len() as f64,
+                min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            })
+        })
+        .collect()
+}
+
+impl<'a> RunSummary<'a> {
+    pub fn new(
+        arch: &'a ArchConfig,
+        configs: &PhysConfigs,
+        inputs: (&'a str, &'a str),
+        circuits: &[Circuit],
+        result: &'a CircuitResult,
+        geomean_area: f64,
+    ) -> Self {
+        RunSummary {
+            crate_version: env!("CARGO_PKG_VERSION"),
+            results_csv_version: RESULTS_CSV_VERSION,
+            arch,
+            logic_block_file: inputs.0,
+            logical_rams_file: inputs.1,
+            circuits: circuits.len(),
+            skipped_circuits: result.skipped.len(),
+            geomean_area,
+            total_area: compute_total_area(circuits, result, arch),
+            extra_luts: result.extra_luts,
+            lutram_blocks: result.lutram_blocks,
+            m8k_blocks: result.m8k_blocks,
+            m128k_blocks: result.m128k_blocks,
+            extra_blocks: &result.extra_blocks,
+            utilization: type_utilizations(result, configs),
+        }
+    }
+}
+
+pub fn write_summary(path: &str, summary: &RunSummary) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, summary)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Memory, PHYS_LUTRAM, ReadOptions, build_mapping, map_circuits, read_data};
+
+    fn circuit(id: i32, logic_blocks: i32, rams: &[(MemMode, i32, i32)]) -> Circuit {
+        Circuit {
+            id,
+            logic_blocks,
+            memories: (rams.iter().enumerate())
+                .map(|(i, &(mode, depth, width))| Memory {
+                    ram_id: i as i32,
+                    mode,
+                    depth,
+                    width,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn too_small_device_reports_each_shortfall() {
+        let circuits = vec![circuit(0, 400, &[(MemMode::TrueDualPort, 2048, 32)])];
+        let arch = ArchConfig::default();
+        let result = map_circuits(&circuits, &arch).unwrap();
+        let nlb = chip_lb_requirement(&circuits, &result, &arch).nlb_arch();
+        assert_eq!(result.m8k_blocks, 8);
+        assert_eq!(nlb, 400);
+
+        let device = parse_device("lbs=300,m8k=5,m128k=5").unwrap();
+        assert_eq!(
+            device_shortfalls(&device, nlb, &result),
+            [
+                "LB tiles: need 400, device has 300 (short by 100)",
+                "M8K blocks: need 8, device has 5 (short by 3)",
+            ]
+        );
+        let roomy = parse_device("lbs=400,m8k=8").unwrap();
+        assert!(device_shortfalls(&roomy, nlb, &result).is_empty());
+    }
+
+    #[test]
+    fn shape_histogram_counts_each_shape() {
+        let circuits = [
+            circuit(0, 10, &[(MemMode::Rom, 256, 8), (MemMode::Rom, 256, 8)]),
+            circuit(
+                1,
+                10,
+                &[(MemMode::Rom, 256, 8), (MemMode::SinglePort, 256, 8)],
+            ),
+        ];
+        let hist = shape_histogram(&circuits);
+        assert_eq!(
+            hist.into_iter().collect::<Vec<_>>(),
+            [
+                ((8, 256, MemMode::Rom), 3),
+                ((8, 256, MemMode::SinglePort), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn binary_mappings_round_trip() {
+        let circuits = [
+            circuit(0, 100, &[(MemMode::Rom, 512, 8), (MemMode::Rom, 512, 8)]),
+            circuit(1, 100, &[(MemMode::TrueDualPort, 4096, 32)]),
+        ];
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        // the ROMs share a block, so mode and logical mode differ
+        assert!(result.mappings.iter().any(|m| m.mode != m.logical_mode));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mapped.bin");
+        let path = path.to_str().unwrap();
+        write_mappings_bin(path, &result.mappings).unwrap();
+        assert_eq!(read_mappings_bin(path).unwrap(), result.mappings);
+    }
+
+    #[test]
+    fn stats_weight_utilization_by_blocks() {
+        let configs = ArchConfig::default().phys_configs();
+        // a shared pair filling one M8K, a 64x8 RAM wasting most of another, and a full
+        // LUTRAM block
+        let circuits = [circuit(
+            0,
+            100,
+            &[
+                (MemMode::Rom, 512, 8),
+                (MemMode::Rom, 512, 8),
+                (MemMode::SinglePort, 64, 8),
+                (MemMode::SinglePort, 64, 10),
+            ],
+        )];
+        let rams = &circuits[0].memories;
+        let mut mappings = vec![
+            build_mapping(0, &rams[0], 0, &PHYS_RAM1, 8, 1024, 1, 1),
+            build_mapping(0, &rams[1], 0, &PHYS_RAM1, 8, 1024, 1, 1),
+            build_mapping(0, &rams[2], 2, &PHYS_RAM1, 8, 1024, 1, 1),
+            build_mapping(0, &rams[3], 3, &PHYS_LUTRAM, 10, 64, 1, 1),
+        ];
+        mappings[0].mode = MemMode::TrueDualPort;
+        mappings[1].mode = MemMode::TrueDualPort;
+        let result = CircuitResult {
+            mappings,
+            extra_luts: 0,
+            lutram_blocks: 1,
+            m8k_blocks: 2,
+            m128k_blocks: 0,
+            extra_blocks: Vec::new(),
+            candidates_evaluated: 0,
+            skipped: Vec::new(),
+        };
+
+        let stats = compute_stats(&result, &configs, 1.0);
+        assert_eq!(
+            stats.m8k_utilization,
+            Some((8192.0 + 512.0) / (2.0 * 8192.0))
+        );
+        assert_eq!(stats.lutram_utilization, Some(1.0));
+        assert_eq!(stats.m128k_utilization, None);
+    }
+
+    #[test]
+    fn lutram_capacity_floor_agrees_between_area_models() {
+        let arch = ArchConfig {
+            lutram_fraction: 0.25,
+            ..ArchConfig::default()
+        };
+        let circuits = [circuit(0, 10, &[(MemMode::SinglePort, 64, 10); 40])];
+        let result = map_circuits(&circuits, &arch).unwrap();
+        assert_eq!(result.lutram_blocks, 40);
+
+        let chip = chip_lb_requirement(&circuits, &result, &arch);
+        let params = GeomeanParams::for_model(&arch, AreaModel::Unified);
+        let per_circuit = lb_requirements_from_result(&circuits, &result, &params)[&0];
+        assert_eq!(chip.lutram_capacity, 160);
+        assert_eq!(per_circuit.lutram_capacity, chip.lutram_capacity);
+        assert_eq!(chip.binding(), "lutram-capacity");
+        assert_eq!(per_circuit.nlb_arch(), chip.nlb_arch());
+    }
+
+    #[test]
+    fn area_models_differ_only_off_the_default_spacing() {
+        let circuits = [circuit(0, 50, &[(MemMode::TrueDualPort, 4096, 32)])];
+        let geomeans = |arch: &ArchConfig| {
+            let result = map_circuits(&circuits, arch).unwrap();
+            [AreaModel::Legacy, AreaModel::Unified].map(|model| {
+                let params = GeomeanParams::for_model(arch, model);
+                compute_geometric_area_in_memory(&circuits, &result, &params)
+            })
+        };
+
+        let [legacy, unified] = geomeans(&ArchConfig::default());
+        assert_eq!(legacy, unified);
+
+        let sparse = ArchConfig {
+            lbs_per_ram1: 20,
+            ..ArchConfig::default()
+        };
+        let [legacy, unified] = geomeans(&sparse);
+        assert!(unified > legacy);
+    }
+
+    #[test]
+    fn bram_heavy_design_wastes_lbs_on_spacing() {
+        let circuits = [circuit(0, 20, &[(MemMode::TrueDualPort, 4096, 32)])];
+        let arch = ArchConfig::default();
+        let result = map_circuits(&circuits, &arch).unwrap();
+        let req = chip_lb_requirement(&circuits, &result, &arch);
+
+        // 16 M8Ks need 160 LBs of spacing for 20 LBs of logic
+        assert_eq!(result.m8k_blocks, 16);
+        assert_eq!(req.binding(), "m8k-sites");
+        assert_eq!(req.wasted_lbs(), 160 - req.logic);
+        assert!(req.wasted_lbs() > 0);
+        assert_eq!(
+            req.excess_by_constraint(),
+            [
+                ("m8k-sites", req.wasted_lbs()),
+                ("m128k-sites", 0),
+                ("lutram-capacity", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn top_cost_lists_n_mappings_most_expensive_first() {
+        let circuits = [circuit(
+            0,
+            100,
+            &[
+                (MemMode::Rom, 64, 4),
+                (MemMode::TrueDualPort, 4096, 32),
+                (MemMode::SinglePort, 1024, 16),
+                (MemMode::SimpleDualPort, 16384, 64),
+            ],
+        )];
+        let configs = ArchConfig::default().phys_configs();
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+
+        let top = top_cost_mappings(&result.mappings, &configs, 3);
+        assert_eq!(top.len(), 3);
+        assert!(top.windows(2).all(|w| w[0].1 >= w[1].1));
+        // the cheapest of the four is the one left out
+        let cheapest = (result.mappings.iter())
+            .map(|m| mapping_cost(m, configs.get(m.phys_type).unwrap()))
+            .fold(f64::INFINITY, f64::min);
+        assert!(top.iter().all(|&(_, cost)| cost > cheapest));
+        assert_eq!(top_cost_mappings(&result.mappings, &configs, 10).len(), 4);
+    }
+
+    #[test]
+    fn pretty_output_has_a_header_per_circuit() {
+        let circuits = [
+            circuit(0, 100, &[(MemMode::Rom, 512, 8), (MemMode::Rom, 512, 8)]),
+            circuit(1, 200, &[(MemMode::SinglePort, 1024, 16)]),
+            circuit(2, 300, &[]),
+        ];
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        let mut out = Vec::new();
+        write_mappings_pretty(&mut out, &circuits, &result.mappings).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        let headers: Vec<&str> = text.lines().filter(|l| l.starts_with("Circuit ")).collect();
+        assert_eq!(
+            headers,
+            [
+                "Circuit 0 (logic blocks 100, physical blocks used 1)",
+                "Circuit 1 (logic blocks 200, physical blocks used 2)",
+                "Circuit 2 (logic blocks 300, physical blocks used 0)",
+            ]
+        );
+        assert_eq!(
+            text.lines().filter(|l| l.starts_with("    RAM ")).count(),
+            3
+        );
+    }
+
+    #[test]
+    fn fractional_sharing_charges_each_sharer_half_a_block() {
+        let circuits = [
+            circuit(0, 100, &[(MemMode::Rom, 512, 8), (MemMode::Rom, 512, 8)]),
+            circuit(1, 100, &[(MemMode::Rom, 512, 8)]),
+        ];
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        assert_eq!(result.m8k_blocks, 2);
+
+        let whole = circuit_usage(&circuits, &result.mappings, false);
+        let fractional = circuit_usage(&circuits, &result.mappings, true);
+        assert_eq!(whole[&0].m8k_blocks, 2.0);
+        assert_eq!(fractional[&0].m8k_blocks, 0.5 + 0.5);
+        // an unshared block is whole either way
+        assert_eq!(whole[&1].m8k_blocks, 1.0);
+        assert_eq!(fractional[&1].m8k_blocks, 1.0);
+    }
+
+    #[test]
+    fn lutram_block_area_changes_the_circuit_total() {
+        let circuits = [circuit(0, 100, &[(MemMode::SinglePort, 64, 10); 4])];
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        assert_eq!(result.lutram_blocks, 4);
+        let usage = circuit_usage(&circuits, &result.mappings, false);
+
+        let dir = tempfile::tempdir().unwrap();
+        let total_area = |area_lutram| {
+            let path = dir.path().join("results.csv");
+            let path = path.to_str().unwrap();
+            write_csv(path, &circuits, &usage, 1.0, 1.0, &[], area_lutram).unwrap();
+            let text = std::fs::read_to_string(path).unwrap();
+            let row = text.lines().nth(1).unwrap().to_string();
+            row.rsplit(',').next().unwrap().parse::<f64>().unwrap()
+        };
+        // LUTRAM blocks as LB tiles: 104 tiles
+        assert_eq!(total_area(None), 104.0 * AVG_LB_AREA);
+        assert_eq!(total_area(Some(1000.0)), 100.0 * AVG_LB_AREA + 4000.0);
+    }
+
+    #[test]
+    fn binding_constraint_per_circuit() {
+        let circuits = [
+            circuit(0, 20, &[(MemMode::TrueDualPort, 4096, 32)]),
+            circuit(1, 2000, &[(MemMode::TrueDualPort, 4096, 32)]),
+        ];
+        let arch = ArchConfig::default();
+        let result = map_circuits(&circuits, &arch).unwrap();
+        let params = GeomeanParams::for_model(&arch, AreaModel::Unified);
+        let requirements = lb_requirements_from_result(&circuits, &result, &params);
+
+        let binding: Vec<_> = requirements
+            .iter()
+            .map(|(&id, r)| (id, r.binding()))
+            .collect();
+        assert_eq!(binding, [(0, "m8k-sites"), (1, "logic")]);
+    }
+
+    #[test]
+    fn bits_per_area_is_logical_bits_over_total_area() {
+        let circuits = [
+            circuit(
+                0,
+                100,
+                &[(MemMode::Rom, 512, 8), (MemMode::SinglePort, 1024, 16)],
+            ),
+            circuit(1, 50, &[(MemMode::TrueDualPort, 2048, 32)]),
+        ];
+        let arch = ArchConfig::default();
+        let result = map_circuits(&circuits, &arch).unwrap();
+        let total_area = compute_total_area(&circuits, &result, &arch);
+
+        let bits = (512 * 8 + 1024 * 16 + 2048 * 32) as f64;
+        assert_eq!(bits_per_area(&result, total_area), bits / total_area);
+        let stats = compute_stats(&result, &arch.phys_configs(), total_area);
+        assert_eq!(stats.bits_per_area, bits / total_area);
+        assert_eq!(bits_per_area(&result, 0.0), 0.0);
+    }
+
+    #[test]
+    fn long_csv_has_a_row_per_metric() {
+        let ram = Memory {
+            ram_id: 7,
+            mode: MemMode::SinglePort,
+            depth: 2048,
+            width: 8,
+        };
+        let mapping = build_mapping(3, &ram, 7, &PHYS_RAM1, 8, 1024, 2, 1);
+        let configs = ArchConfig::default().phys_configs();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ram_mapped.csv");
+        let path = path.to_str().unwrap();
+        write_mappings_long_csv(path, &[mapping], &configs).unwrap();
+
+        let text = std::fs::read_to_string(path).unwrap();
+        let rows: Vec<&str> = text.lines().skip(1).collect();
+        assert_eq!(rows.len(), 7);
+        assert!(rows.iter().all(|r| r.starts_with("3,7,")));
+        assert!(rows.contains(&"3,7,series,2"));
+        assert!(rows.contains(&"3,7,utilization,1.0000"));
+    }
+
+    #[test]
+    fn mapped_file_type_names_read_like_ids() {
+        let circuits = [
+            circuit(
+                0,
+                100,
+                &[(MemMode::SinglePort, 64, 10), (MemMode::Rom, 1024, 16)],
+            ),
+            circuit(1, 300, &[(MemMode::TrueDualPort, 4096, 32)]),
+        ];
+        let arch = ArchConfig::default();
+        let result = map_circuits(&circuits, &arch).unwrap();
+        let params = GeomeanParams::for_model(&arch, AreaModel::Unified);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        std::fs::write(path("lbs.txt"), "Circuit\t#LBs\n0\t100\n1\t300\n").unwrap();
+        write_mappings(&path("ids.txt"), &result.mappings).unwrap();
+
+        let ids = std::fs::read_to_string(path("ids.txt")).unwrap();
+        let names: String = ids
+            .lines()
+            .map(|line| {
+                let mut parts: Vec<String> = line.split(' ').map(String::from).collect();
+                let id: i32 = parts[14].parse().unwrap();
+                parts[14] = PhysType::from_type_id(id).unwrap().name().to_uppercase();
+                parts.join(" ") + "\n"
+            })
+            .collect();
+        assert!(names.contains("Type LUTRAM") && names.contains("Type M8K"));
+        std::fs::write(path("names.txt"), names).unwrap();
+
+        let geomean = |mapped: &str| {
+            compute_geometric_area(
+                &path("lbs.txt"),
+                &path(mapped),
+                &ReadOptions::default(),
+                &params,
+                None,
+            )
+            .unwrap()
+        };
+        assert_eq!(geomean("names.txt"), geomean("ids.txt"));
+        assert_eq!(
+            geomean("ids.txt"),
+            compute_geometric_area_in_memory(&circuits, &result, &params)
+        );
+    }
+
+    #[test]
+    fn exported_logical_rams_read_back_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        std::fs::write(path("lbs.txt"), "Circuit\t#LBs\n0\t500\n1\t300\n").unwrap();
+        std::fs::write(
+            path("rams.txt"),
+            "Num_Circuits 2\nCircuit\tRamID\tMode\t\tDepth\tWidth\n\
+             0\t0\tSimpleDualPort\t45\t12\n\
+             0\t1\tTrueDualPort\t4096\t32\n\
+             0\t2\tROM\t512\t8\n\
+             0\t3\tROM\t512\t8\n\
+             1\t0\tSinglePort\t1024\t16\n",
+        )
+        .unwrap();
+
+        let opts = ReadOptions::default();
+        let (circuits, _) = read_data(&path("lbs.txt"), &path("rams.txt"), &opts).unwrap();
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        // the two ROMs share a block and are written back as ROMs, not TDP
+        assert!(result.mappings.iter().any(|m| m.mode != m.logical_mode));
+
+        write_logical_rams(&path("exported.txt"), &result.mappings).unwrap();
+        let (reread, _) = read_data(&path("lbs.txt"), &path("exported.txt"), &opts).unwrap();
+        assert_eq!(reread, circuits);
+    }
+
+    #[test]
+    fn per_type_geomeans_skip_circuits_without_the_type() {
+        let circuits = [
+            circuit(
+                0,
+                100,
+                &[
+                    (MemMode::TrueDualPort, 2048, 32),
+                    (MemMode::SinglePort, 64, 10),
+                ],
+            ),
+            circuit(1, 100, &[(MemMode::TrueDualPort, 4096, 32)]),
+            circuit(2, 100, &[(MemMode::SinglePort, 64, 10); 2]),
+            circuit(3, 100, &[]),
+        ];
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        let usage = circuit_usage(&circuits, &result.mappings, false);
+        let types = [PhysType::Lutram, PhysType::Ram8K, PhysType::Ram128K];
+        let geomeans = per_type_block_geomeans(&usage, &types);
+
+        // LUTRAM: circuits 0 and 2 use 1 and 2; M8K: circuits 0 and 1 use 8 and 16
+        let close = |g: Option<f64>, want: f64| (g.unwrap() - want).abs() < 1e-9;
+        assert_eq!(geomeans[0].0, PhysType::Lutram);
+        assert!(close(geomeans[0].1, 2f64.sqrt()));
+        assert!(close(geomeans[1].1, 128f64.sqrt()));
+        assert_eq!(geomeans[2], (PhysType::Ram128K, None));
+    }
+
+    #[test]
+    fn json_mappings_carry_the_text_fields() {
+        let circuits = [
+            circuit(1, 100, &[(MemMode::Rom, 1024, 16)]),
+            circuit(
+                0,
+                100,
+                &[
+                    (MemMode::SinglePort, 64, 10),
+                    (MemMode::TrueDualPort, 2048, 32),
+                ],
+            ),
+        ];
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ram_mapped.json");
+        let path = path.to_str().unwrap();
+        write_mappings_json(path, &result.mappings).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let rows = json.as_array().unwrap();
+        assert_eq!(rows.len(), 3);
+        // sorted by circuit then RAM, like the text file
+        let ids: Vec<(i64, i64)> = (rows.iter())
+            .map(|r| {
+                let id = |k: &str| r[k].as_i64().unwrap();
+                (id("circuit_id"), id("logical_ram_id"))
+            })
+            .collect();
+        assert_eq!(ids, [(0, 0), (0, 1), (1, 0)]);
+        // type as its numeric id, mode by name
+        assert_eq!(rows[0]["phys_type"], 1);
+        assert_eq!(rows[1]["phys_type"], 2);
+        assert_eq!(rows[1]["mode"], "TrueDualPort");
+        assert_eq!(rows[1]["phys_blocks"], 8);
+        assert_eq!(rows[2]["mode"], "ROM");
+    }
+
+    #[test]
+    fn file_and_in_memory_geomeans_agree() {
+        let circuits = [
+            circuit(
+                0,
+                500,
+                &[
+                    (MemMode::SimpleDualPort, 45, 12),
+                    (MemMode::TrueDualPort, 4096, 32),
+                ],
+            ),
+            circuit(1, 300, &[(MemMode::Rom, 512, 8), (MemMode::Rom, 512, 8)]),
+            circuit(2, 50, &[(MemMode::SinglePort, 16384, 64)]),
+        ];
+        let arch = ArchConfig {
+            lbs_per_ram1: 8,
+            lbs_per_ram2: 150,
+            lutram_fraction: 0.3,
+            ..ArchConfig::default()
+        };
+        let result = map_circuits(&circuits, &arch).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        std::fs::write(path("lbs.txt"), "Circuit\t#LBs\n0\t500\n1\t300\n2\t50\n").unwrap();
+        write_mappings(&path("mapped.txt"), &result.mappings).unwrap();
+
+        let params = GeomeanParams::for_model(&arch, AreaModel::Unified);
+        let from_file = compute_geometric_area(
+            &path("lbs.txt"),
+            &path("mapped.txt"),
+            &ReadOptions::default(),
+            &params,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            from_file,
+            geometric_area_from_result(&circuits, &result, &arch)
+        );
+    }
+
+    #[test]
+    fn m128k_spacing_sets_the_geomean() {
+        // M128K only, so the RAM's blocks set the tile count of a 50-LB circuit
+        let circuits = [circuit(0, 50, &[(MemMode::SinglePort, 8192, 64)])];
+        let run = |lbs_per_ram2| {
+            let arch = ArchConfig {
+                has_lutram: false,
+                has_ram1: false,
+                lbs_per_ram2,
+                ..ArchConfig::default()
+            };
+            let result = map_circuits(&circuits, &arch).unwrap();
+            let params = GeomeanParams::for_model(&arch, AreaModel::Unified);
+            let req = lb_requirements_from_result(&circuits, &result, &params)[&0];
+            let geomean = geometric_area_from_result(&circuits, &result, &arch);
+            (result.m128k_blocks, req, geomean)
+        };
+        let (blocks, req_300, geomean_300) = run(300);
+        assert_eq!(blocks, 4);
+        assert_eq!(
+            (req_300.m128k_sites, req_300.binding()),
+            (1200, "m128k-sites")
+        );
+
+        let (_, req_150, geomean_150) = run(150);
+        assert_eq!(req_150.m128k_sites, 600);
+        // 600 fewer LB tiles, the BRAM blocks unchanged
+        assert!((geomean_300 - geomean_150 - 600.0 * AVG_LB_AREA).abs() < 1e-6 * geomean_300);
+    }
+
+    #[test]
+    fn summary_block_totals_match_the_result() {
+        let circuits = [
+            circuit(
+                0,
+                500,
+                &[
+                    (MemMode::SimpleDualPort, 45, 12),
+                    (MemMode::TrueDualPort, 4096, 32),
+                ],
+            ),
+            circuit(1, 300, &[(MemMode::SinglePort, 16384, 8)]),
+        ];
+        let arch = ArchConfig::default();
+        let configs = arch.phys_configs();
+        let result = map_circuits(&circuits, &arch).unwrap();
+        let geomean = geometric_area_from_result(&circuits, &result, &arch);
+        let summary = RunSummary::new(
+            &arch,
+            &configs,
+            ("blocks.txt", "rams.txt"),
+            &circuits,
+            &result,
+            geomean,
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("summary.json");
+        let path = path.to_str().unwrap();
+        write_summary(path, &summary).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+        let total = |key: &str| json[key].as_i64().unwrap() as i32;
+        assert_eq!(total("lutram_blocks"), result.lutram_blocks);
+        assert_eq!(total("m8k_blocks"), result.m8k_blocks);
+        assert_eq!(total("m128k_blocks"), result.m128k_blocks);
+        assert_eq!(total("extra_luts"), result.extra_luts);
+        assert!(result.lutram_blocks > 0 && result.m8k_blocks > 0 && result.m128k_blocks > 0);
+        assert_eq!(json["circuits"], 2);
+        assert_eq!(json["geomean_area"].as_f64().unwrap(), geomean);
+        assert_eq!(json["crate_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["arch"]["ram1_bits"], 8192);
+    }
+
+    #[test]
+    fn block_area_is_linear_in_per_bit() {
+        let circuits = [circuit(0, 1000, &[(MemMode::TrueDualPort, 4096, 32)])];
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        let with_per_bit = |per_bit| ArchConfig {
+            ram1_area: BramAreaModel {
+                per_bit,
+                ..BramAreaModel::DEFAULT
+            },
+            ..ArchConfig::default()
+        };
+
+        let base = with_per_bit(0.0);
+        let block_0 = block_ram_area(8192, 32, &base.ram1_area);
+        let total_0 = compute_total_area(&circuits, &result, &base);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9 * b;
+        // 1000 tiles hold 100 M8K sites
+        for per_bit in [1.0, 5.0, 12.5] {
+            let arch = with_per_bit(per_bit);
+            let block = block_ram_area(8192, 32, &arch.ram1_area);
+            assert!(close(block - block_0, per_bit * 8192.0));
+            let total = compute_total_area(&circuits, &result, &arch);
+            assert!(close(total - total_0, 100.0 * per_bit * 8192.0));
+        }
+    }
+
+    #[test]
+    fn mapping_csv_rebuilds_the_block_totals() {
+        let circuits = [
+            circuit(
+                0,
+                500,
+                &[
+                    (MemMode::SimpleDualPort, 45, 12),
+                    (MemMode::TrueDualPort, 4096, 32),
+                ],
+            ),
+            circuit(1, 300, &[(MemMode::Rom, 512, 8), (MemMode::Rom, 512, 8)]),
+            circuit(2, 300, &[(MemMode::SinglePort, 16384, 8)]),
+        ];
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mappings.csv");
+        write_mappings_csv(path.to_str().unwrap(), &result.mappings).unwrap();
+
+        // a shared block appears once per member, so count each (circuit, group) once
+        let mut blocks: BTreeMap<(i32, i32), (i32, i32)> = BTreeMap::new();
+        let mut extra_luts = 0;
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        for row in reader.deserialize::<HashMap<String, String>>() {
+            let row = row.unwrap();
+            let field = |k: &str| row[k].parse::<i32>().unwrap();
+            blocks.insert(
+                (field("circuit_id"), field("group_id")),
+                (field("phys_type"), field("phys_blocks")),
+            );
+            extra_luts += field("extra_luts");
+        }
+        let total = |type_id| {
+            (blocks.values())
+                .filter(|&&(t, _)| t == type_id)
+                .map(|&(_, n)| n)
+                .sum::<i32>()
+        };
+        assert_eq!(
+            (total(1), total(2), total(3)),
+            (result.lutram_blocks, result.m8k_blocks, result.m128k_blocks)
+        );
+        assert_eq!(extra_luts, result.extra_luts);
+        assert_eq!(result.m8k_blocks, 16 + 1);
+    }
+
+    #[test]
+    fn shared_groups_list_only_the_shared_pair() {
+        let circuits = [
+            circuit(
+                0,
+                500,
+                &[
+                    (MemMode::SinglePort, 64, 10),
+                    (MemMode::Rom, 512, 8),
+                    (MemMode::TrueDualPort, 2048, 32),
+                    (MemMode::Rom, 512, 8),
+                ],
+            ),
+            circuit(1, 300, &[(MemMode::Rom, 512, 8)]),
+        ];
+        let arch = ArchConfig::default();
+        let result = map_circuits(&circuits, &arch).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("groups.csv");
+        let path = path.to_str().unwrap();
+        write_shared_groups(path, &result.mappings, &arch.phys_configs()).unwrap();
+
+        let text = std::fs::read_to_string(path).unwrap();
+        let rows: Vec<&str> = text.lines().skip(1).collect();
+        // RAMs 1 and 3 fill one M8K exactly; the group takes the smaller id
+        assert_eq!(rows, ["0,1,2,1 3,1,1.0000"]);
+    }
+
+    #[test]
+    fn lut_only_mappings_write_finite_utilization() {
+        let circuits = [circuit(
+            0,
+            100,
+            &[(MemMode::SinglePort, 4, 2), (MemMode::Rom, 4, 2)],
+        )];
+        let configs = ArchConfig::default().phys_configs();
+        let opts = crate::MapOptions {
+            allow_lut_memory: true,
+            type_priority: configs.types(),
+            ..crate::MapOptions::default()
+        };
+        let result = crate::assign_ram(&circuits, &configs, &opts).unwrap();
+        assert!(result.mappings.iter().all(|m| m.is_lut_only()));
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+        write_mappings_long_csv(&path("long.csv"), &result.mappings, &configs).unwrap();
+        let long = std::fs::read_to_string(path("long.csv")).unwrap();
+        let utilizations: Vec<&str> = (long.lines())
+            .filter(|l| l.contains(",utilization,"))
+            .collect();
+        assert_eq!(
+            utilizations,
+            ["0,0,utilization,1.0000", "0,1,utilization,1.0000"]
+        );
+        assert!(!long.contains("inf") && !long.contains("NaN"));
+
+        write_shared_groups(&path("groups.csv"), &result.mappings, &configs).unwrap();
+        let groups = std::fs::read_to_string(path("groups.csv")).unwrap();
+        assert_eq!(groups.lines().count(), 1);
+    }
+
+    #[test]
+    fn results_csv_has_the_128k_column() {
+        let circuits = [
+            circuit(0, 100, &[(MemMode::SinglePort, 16384, 8)]),
+            circuit(1, 200, &[(MemMode::Rom, 256, 8)]),
+        ];
+        let result = map_circuits(&circuits, &ArchConfig::default()).unwrap();
+        assert!(result.m128k_blocks > 0);
+        let usage = circuit_usage(&circuits, &result.mappings, false);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.csv");
+        let path = path.to_str().unwrap();
+        write_csv(path, &circuits, &usage, 1.0, 1.0, &[], None).unwrap();
+
+        let mut reader = csv::Reader::from_path(path).unwrap();
+        let headers = reader.headers().unwrap().clone();
+        assert_eq!(&headers[2], "8K_BRAMs_Used");
+        assert_eq!(&headers[3], "128K_BRAMs_Used");
+        assert_eq!(&headers[4], "Regular_LBs_used");
+        let mut column = 0;
+        for (row, c) in reader.records().zip(&circuits) {
+            let row = row.unwrap();
+            let blocks: i32 = (result.mappings.iter())
+                .filter(|m| m.circuit_id == c.id && m.phys_type == PhysType::Ram128K)
+                .map(|m| m.phys_blocks)
+                .sum();
+            assert_eq!(row[3].parse::<f64>().unwrap(), blocks as f64);
+            assert_eq!(row[4].parse::<i32>().unwrap(), c.logic_blocks);
+            column += blocks;
+        }
+        assert_eq!(column, result.m128k_blocks);
+    }
+
+    #[test]
+    fn type_utilization_mean_and_min() {
+        // 1024x8 fills an M8K; 512x8 in SinglePort mode can't share, so it fills half of one
+        let circuits = [circuit(
+            0,
+            100,
+            &[
+                (MemMode::SinglePort, 1024, 8),
+                (MemMode::SinglePort, 512, 8),
+            ],
+        )];
+        let arch = ArchConfig::default();
+        let result = map_circuits(&circuits, &arch).unwrap();
+        assert_eq!(result.m8k_blocks, 2);
+        let stats = type_utilizations(&result, &arch.phys_configs());
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].phys_type, "m8k");
+        assert_eq!(stats[0].mappings, 2);
+        assert!((stats[0].mean - 0.75).abs() < 1e-12);
+        assert!((stats[0].min - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn geomean_from_files_skips_the_configured_header() {
+        let circuits = [
+            circuit(0, 500, &[(MemMode::TrueDualPort, 4096, 32)]),
+            circuit(1, 300, &[(MemMode::Rom, 1024, 16)]),
+        ];
+        let arch = ArchConfig::default();
+        let result = map_circuits(&circuits, &arch).unwrap();
+        let params = GeomeanParams::for_model(&arch, AreaModel::Unified);
+        let expected = geometric_area_from_result(&circuits, &result, &arch);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        write_mappings(&path("mapped.txt"), &result.mappings).unwrap();
+        let geomean = |lbs: &str, logic_blocks_header| {
+            std::fs::write(path("lbs.txt"), lbs).unwrap();
+            let opts = ReadOptions {
+                logic_blocks_header,
+                ..ReadOptions::default()
+            };
+            let ids = [0, 1];
+            let requirements = circuit_lb_requirements(
+                &path("lbs.txt"),
+                &path("mapped.txt"),
+                &opts,
+                &params,
+                None,
+            )
+            .unwrap();
+            assert_eq!(requirements.keys().copied().collect::<Vec<_>>(), ids);
+            compute_geometric_area(&path("lbs.txt"), &path("mapped.txt"), &opts, &params, None)
+                .unwrap()
+        };
+        // no header: circuit 0 on the first line is data, not skipped
+        assert_eq!(geomean("0\t500\n1\t300\n", 0), expected);
+        assert_eq!(geomean("Circuit\tLBs\n0\t500\n1\t300\n", 1), expected);
+        assert_eq!(
+            geomean("# suite\nCircuit\tLBs\n0\t500\n1\t300\n", 2),
+            expected
+        );
+    }
+}
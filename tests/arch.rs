@@ -0,0 +1,55 @@
+use rapid_map::utils::{arch_differences, arch_toml, parse_arch_toml};
+use rapid_map::{ArchConfig, BramAreaModel, ExtraRam};
+
+const FIXTURE: &str = include_str!("fixtures/arch.toml");
+
+#[test]
+fn fixture_parses_to_the_listed_values() {
+    let arch = parse_arch_toml(FIXTURE).unwrap();
+    assert!(!arch.has_lutram);
+    assert_eq!(arch.lutram_fraction, 0.25);
+    assert_eq!(arch.rom_discount, 0.8);
+    assert!(arch.pipeline_mux);
+    assert_eq!(arch.pipeline_threshold, 8);
+    assert!(arch.has_ram1);
+    assert_eq!(
+        (arch.ram1_bits, arch.lbs_per_ram1, arch.max_width_ram1),
+        (16384, 12, 64)
+    );
+    assert_eq!(
+        arch.ram1_area,
+        BramAreaModel {
+            base: 8000.0,
+            per_width: 500.0,
+            ..BramAreaModel::DEFAULT
+        }
+    );
+    // keys left out keep their defaults
+    assert!(!arch.has_ram2);
+    assert_eq!(arch.ram2_bits, ArchConfig::default().ram2_bits);
+    assert_eq!(
+        arch.extra_rams,
+        [ExtraRam {
+            bits: 524288,
+            lbs_per: 1000,
+            max_width: 256,
+            area: BramAreaModel {
+                per_bit: 4.0,
+                ..BramAreaModel::DEFAULT
+            },
+        }]
+    );
+}
+
+#[test]
+fn arch_round_trips_through_toml() {
+    for arch in [ArchConfig::default(), parse_arch_toml(FIXTURE).unwrap()] {
+        let text = arch_toml(&arch).unwrap();
+        let reread = parse_arch_toml(&text).unwrap();
+        assert!(
+            arch_differences(&arch, &reread).is_empty(),
+            "{:?}",
+            arch_differences(&arch, &reread)
+        );
+    }
+}
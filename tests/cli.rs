@@ -0,0 +1,602 @@
+use assert_cmd::Command;
+use predicates::str::contains;
+use std::fs;
+use tempfile::TempDir;
+
+// a two-circuit benchmark in the default input file names
+fn bench_dir() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("logic_block_count.txt"),
+        "Circuit\t# Logic blocks (N=10, k=6, fracturable)\n0\t500\n1\t300\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("logical_rams.txt"),
+        "Num_Circuits 2\nCircuit\tRamID\tMode\t\tDepth\tWidth\n\
+         0\t0\tSimpleDualPort\t45\t12\n\
+         0\t1\tTrueDualPort\t4096\t32\n\
+         1\t0\tROM\t1024\t16\n",
+    )
+    .unwrap();
+    dir
+}
+
+// one RAM per circuit, circuits 0..5
+fn five_circuit_dir(ids: &[i32]) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let rams = [
+        "ROM\t256\t8",
+        "SinglePort\t1024\t16",
+        "TrueDualPort\t2048\t32",
+        "SimpleDualPort\t512\t8",
+        "ROM\t64\t4",
+    ];
+    let mut lbs = String::from("Circuit\tLogic blocks\n");
+    let mut ram_lines = format!(
+        "Num_Circuits {}\nCircuit\tRamID\tMode\tDepth\tWidth\n",
+        ids.len()
+    );
+    for &id in ids {
+        lbs += &format!("{}\t{}\n", id, 100 * (id + 1));
+        ram_lines += &format!("{}\t0\t{}\n", id, rams[id as usize]);
+    }
+    fs::write(dir.path().join("logic_block_count.txt"), lbs).unwrap();
+    fs::write(dir.path().join("logical_rams.txt"), ram_lines).unwrap();
+    dir
+}
+
+fn geomean_line(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let line = stderr.lines().find(|l| l.starts_with("Geometric mean"));
+    line.unwrap().to_string()
+}
+
+fn rapid_map(dir: &TempDir) -> Command {
+    let mut cmd = Command::cargo_bin("rapid-map").unwrap();
+    cmd.current_dir(dir.path());
+    cmd
+}
+
+#[test]
+fn maps_the_benchmark() {
+    let dir = bench_dir();
+    rapid_map(&dir).assert().success();
+    assert!(dir.path().join("ram_mapped.txt").exists());
+}
+
+#[test]
+fn missing_input_file_exits_2() {
+    let dir = bench_dir();
+    rapid_map(&dir)
+        .args(["--logical-rams", "no_such_file.txt"])
+        .assert()
+        .code(2)
+        .stderr(contains("no_such_file.txt"));
+}
+
+#[test]
+fn bad_config_exits_1() {
+    let dir = bench_dir();
+    fs::write(dir.path().join("arch.toml"), "[m8k]\nbits = \"lots\"\n").unwrap();
+    rapid_map(&dir)
+        .args(["--arch", "arch.toml"])
+        .assert()
+        .code(1)
+        .stderr(contains("--arch arch.toml"));
+}
+
+#[test]
+fn illegal_hint_warns_and_falls_back() {
+    let dir = bench_dir();
+    // circuit 1's ROM is 16 wide, so four 1-wide M8K columns cannot hold it
+    fs::write(dir.path().join("hints.txt"), "1 0 2 1 1 4\n").unwrap();
+    rapid_map(&dir)
+        .args(["--hints", "hints.txt"])
+        .assert()
+        .success()
+        .stderr(contains("ignoring hint for RAM 0 in circuit 1"));
+}
+
+#[test]
+fn circuit_filter_maps_and_costs_only_the_listed_circuits() {
+    let all = five_circuit_dir(&[0, 1, 2, 3, 4]);
+    let out = rapid_map(&all)
+        .args(["--circuits", "1,3"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let mapped = fs::read_to_string(all.path().join("ram_mapped.txt")).unwrap();
+    let circuits: Vec<&str> = mapped
+        .lines()
+        .map(|l| l.split(' ').next().unwrap())
+        .collect();
+    assert_eq!(circuits, ["1", "3"]);
+
+    // the geomean is the one of an input holding just those two circuits
+    let two = five_circuit_dir(&[1, 3]);
+    let only = rapid_map(&two).output().unwrap();
+    assert_eq!(geomean_line(&out.stderr), geomean_line(&only.stderr));
+}
+
+#[test]
+fn required_type_lists_the_rams_it_cannot_hold() {
+    let dir = bench_dir();
+    fs::write(
+        dir.path().join("wide.txt"),
+        "Num_Circuits 2\nCircuit\tRamID\tMode\t\tDepth\tWidth\n\
+         0\t0\tSimpleDualPort\t45\t12\n\
+         0\t1\tTrueDualPort\t1024\t128\n\
+         1\t0\tROM\t1024\t16\n",
+    )
+    .unwrap();
+    // an M128K TDP port is 64 wide, and a single column cannot hold 128
+    rapid_map(&dir)
+        .args(["--logical-rams", "wide.txt", "--require-type", "m128k"])
+        .args(["--max-parallel", "m128k=1"])
+        .assert()
+        .code(3)
+        .stderr(contains(
+            "1 logical RAMs cannot be mapped to required type m128k:\n  circuit 0 ram 1",
+        ));
+}
+
+#[test]
+fn determinism_check_passes() {
+    let dir = bench_dir();
+    rapid_map(&dir)
+        .args(["--check-determinism", "--parallel-memories"])
+        .assert()
+        .success()
+        .stderr(contains("Determinism check passed"));
+}
+
+#[test]
+fn fraction_sweep_has_a_row_per_fraction() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("logic_block_count.txt"),
+        "Circuit\tLogic blocks\n0\t10\n",
+    )
+    .unwrap();
+    // 40 LUTRAM-sized RAMs: the LUTRAM capacity floor sets the chip size
+    let mut rams = String::from("Num_Circuits 1\nCircuit\tRamID\tMode\tDepth\tWidth\n");
+    for id in 0..40 {
+        rams += &format!("0\t{}\tSinglePort\t64\t10\n", id);
+    }
+    fs::write(dir.path().join("logical_rams.txt"), rams).unwrap();
+
+    let out = rapid_map(&dir)
+        .args(["--sweep-fraction", "0.1:0.5:0.1"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(
+        lines.next(),
+        Some("lutram_fraction,total_area,geomean_area")
+    );
+    let rows: Vec<(String, f64)> = lines
+        .map(|l| {
+            let cols: Vec<&str> = l.split(',').collect();
+            (cols[0].to_string(), cols[2].parse().unwrap())
+        })
+        .collect();
+    let fractions: Vec<&str> = rows.iter().map(|(f, _)| f.as_str()).collect();
+    assert_eq!(
+        fractions,
+        ["0.1000", "0.2000", "0.3000", "0.4000", "0.5000"]
+    );
+    // a larger fraction needs fewer tiles to host the same LUTRAMs
+    assert!(rows.windows(2).all(|w| w[1].1 < w[0].1));
+}
+
+#[test]
+fn manifest_records_the_run() {
+    let dir = bench_dir();
+    let out = rapid_map(&dir)
+        .args(["--manifest", "run.json", "--check-determinism"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let text = fs::read_to_string(dir.path().join("run.json")).unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&text).unwrap();
+
+    let keys: Vec<&str> = manifest
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    for key in [
+        "crate_version",
+        "inputs",
+        "config",
+        "content_hash",
+        "geomean_area",
+    ] {
+        assert!(keys.contains(&key), "manifest has no {}", key);
+    }
+    let inputs = manifest["inputs"].as_array().unwrap();
+    assert_eq!(inputs[0]["path"], "logic_block_count.txt");
+    assert_eq!(inputs[1]["path"], "logical_rams.txt");
+    let size = fs::metadata(dir.path().join("logical_rams.txt"))
+        .unwrap()
+        .len();
+    assert_eq!(inputs[1]["size"], size);
+    assert_eq!(manifest["config"]["lutram_fraction"], 0.5);
+
+    // the same hash the determinism check reports for the result
+    let hash = manifest["content_hash"].as_str().unwrap();
+    assert_eq!(hash.len(), 16);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains(&format!("Determinism check passed (hash {})", hash)));
+}
+
+// -p with only LUTRAM enabled
+const LUTRAM_ONLY: [&str; 11] = [
+    "-p", "1", "0.5", "0", "8192", "10", "32", "0", "131072", "300", "128",
+];
+
+#[test]
+fn impossible_tdp_rams_are_reported_up_front() {
+    let dir = bench_dir();
+    rapid_map(&dir)
+        .args(LUTRAM_ONLY)
+        .arg("--strict")
+        .assert()
+        .code(3)
+        .stderr(contains(
+            "1 logical RAMs are TrueDualPort but no enabled type can implement them \
+             (enable M8K/M128K with sufficient TDP width):\n  circuit 0 ram 1",
+        ));
+}
+
+#[test]
+fn zero_max_width_warns_and_leaves_the_type_unused() {
+    let dir = bench_dir();
+    rapid_map(&dir)
+        .args([
+            "-p", "1", "0.5", "1", "8192", "10", "0", "1", "131072", "300", "128",
+        ])
+        .assert()
+        .success()
+        .stderr(contains(
+            "Warning: m8k is enabled but its max_width is 0, so nothing can map to it",
+        ));
+    let mapped = fs::read_to_string(dir.path().join("ram_mapped.txt")).unwrap();
+    assert_eq!(mapped.lines().count(), 3);
+    assert!(!mapped.contains(" Type 2 "));
+}
+
+#[test]
+fn p_value_overrides_the_arch_file_with_a_warning() {
+    let dir = bench_dir();
+    fs::write(dir.path().join("arch.toml"), "has_lutram = false\n").unwrap();
+    rapid_map(&dir)
+        .args(["--arch", "arch.toml"])
+        .args(LUTRAM_ONLY)
+        .assert()
+        .success()
+        .stderr(contains(
+            "Warning: -p has_lutram = true overrides false from arch.toml",
+        ));
+    // circuit 0's TDP RAM can't map without BRAMs; circuit 1's ROM lands on LUTRAM, which
+    // the file alone would have disabled
+    let mapped = fs::read_to_string(dir.path().join("ram_mapped.txt")).unwrap();
+    let types: Vec<&str> = mapped
+        .lines()
+        .map(|l| l.split(' ').nth(14).unwrap())
+        .collect();
+    assert_eq!(types, ["1"]);
+}
+
+#[test]
+fn long_csv_follows_the_mapped_path() {
+    let dir = bench_dir();
+    fs::create_dir(dir.path().join("out")).unwrap();
+    rapid_map(&dir)
+        .args(["--mapped", "out/run1.txt", "--format", "long-csv"])
+        .assert()
+        .success();
+    let csv = fs::read_to_string(dir.path().join("out/run1.csv")).unwrap();
+    assert!(csv.starts_with("Circuit,RamID,Metric,Value"));
+    assert!(!dir.path().join("ram_mapped.csv").exists());
+}
+
+#[test]
+fn one_thread_and_many_write_identical_mappings() {
+    let dir = five_circuit_dir(&[0, 1, 2, 3, 4]);
+    let mapped = |threads: &str| {
+        let out = format!("mapped_{}.txt", threads);
+        rapid_map(&dir)
+            .args(["--threads", threads, "--mapped", &out])
+            .assert()
+            .success();
+        fs::read(dir.path().join(out)).unwrap()
+    };
+    let serial = mapped("1");
+    assert!(!serial.is_empty());
+    assert_eq!(serial, mapped("8"));
+}
+
+#[test]
+fn dry_run_writes_nothing() {
+    let dir = bench_dir();
+    let listing = || {
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        names
+    };
+    let before = listing();
+    rapid_map(&dir)
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stderr(contains("Extra LUTs:"))
+        .stderr(contains("Geometric mean FPGA area"));
+    assert_eq!(listing(), before);
+}
+
+#[test]
+fn explain_lists_both_block_types_and_the_choice() {
+    // circuit 1 is one SinglePort 1024x16, which fits M8K and M128K alike
+    let dir = five_circuit_dir(&[0, 1]);
+    let output = rapid_map(&dir).args(["--explain", "1"]).output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Circuit 1 (200 logic blocks, 1 RAMs):"));
+    assert!(stderr.contains("  RAM 0 SinglePort 1024x16:"));
+    // the cheapest of each type is starred
+    assert!(stderr.contains("    m8k 8x1024 S 1 P 2 blocks 2 extra LUTs 0 cost"));
+    assert!(stderr.contains("    m128k 16x8192 S 1 P 1 blocks 1 extra LUTs 0 cost"));
+    let starred: Vec<&str> = stderr.lines().filter(|l| l.ends_with(" *")).collect();
+    assert_eq!(starred.len(), 3);
+    assert!(stderr.contains("    chosen: m8k 8x1024 S 1 P 2\n    cheapest;"));
+    // circuit 0 is not explained
+    assert!(!stderr.contains("Circuit 0 ("));
+}
+
+#[test]
+fn same_seed_same_mapping_other_seeds_differ() {
+    // on M128K alone every width that fits in one block costs the same, so each RAM is a tie
+    let dir = five_circuit_dir(&[0, 1, 2, 3, 4]);
+    let mapped_with_seed = |seed: u64| {
+        rapid_map(&dir)
+            .args([
+                "-p", "0", "0.5", "0", "8192", "10", "32", "1", "131072", "300", "128",
+            ])
+            .args(["--seed", &seed.to_string()])
+            .assert()
+            .success();
+        fs::read_to_string(dir.path().join("ram_mapped.txt")).unwrap()
+    };
+    let first = mapped_with_seed(1);
+    assert_eq!(mapped_with_seed(1), first);
+    assert!((2..=4).any(|seed| mapped_with_seed(seed) != first));
+}
+
+#[test]
+fn threads_1_and_4_write_identical_outputs() {
+    let dir = bench_dir();
+    let outputs = |threads: &str| {
+        let results = format!("results_{}.csv", threads);
+        let mapped = format!("mapped_{}.txt", threads);
+        rapid_map(&dir)
+            .args(["--threads", threads])
+            .args(["--results", &results, "--mapped", &mapped])
+            .assert()
+            .success();
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        (read(&results), read(&mapped))
+    };
+    assert_eq!(outputs("1"), outputs("4"));
+}
+
+#[test]
+fn zero_threads_is_a_config_error() {
+    let dir = bench_dir();
+    rapid_map(&dir)
+        .args(["--threads", "0"])
+        .assert()
+        .code(1)
+        .stderr(contains("--threads expects a positive integer"));
+}
+
+#[test]
+fn compare_arch_m128k_columns_help_a_ram_heavy_design() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("logic_block_count.txt"),
+        "Circuit\tLogic blocks\n0\t100\n1\t200\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("logical_rams.txt"),
+        "Num_Circuits 2\nCircuit\tRamID\tMode\tDepth\tWidth\n\
+         0\t0\tSinglePort\t16384\t32\n\
+         0\t1\tROM\t8192\t16\n\
+         1\t0\tTrueDualPort\t4096\t64\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("m8k.toml"), "[ram2]\nenabled = false\n").unwrap();
+    // M128K every 50 logic blocks instead of 300, so its columns pay for themselves
+    fs::write(dir.path().join("rich.toml"), "[ram2]\nlbs_per = 50\n").unwrap();
+    let out = rapid_map(&dir)
+        .args(["--arch", "m8k.toml", "--compare-arch", "rich.toml"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let geomean = |arch: &str| -> f64 {
+        let line = stderr.lines().find(|l| l.starts_with(arch)).unwrap();
+        line.split(' ').nth(1).unwrap().parse().unwrap()
+    };
+    let (primary, rich) = (geomean("primary "), geomean("rich.toml "));
+    assert!(rich <= primary, "{} > {}", rich, primary);
+    // the M8K-only arch has no M128K column at all
+    let m128k = |arch: &str| {
+        stderr
+            .lines()
+            .find(|l| l.starts_with(arch))
+            .unwrap()
+            .split(' ')
+            .nth(6)
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(m128k("primary "), "0");
+    assert_ne!(m128k("rich.toml "), "0");
+    assert!(stderr.contains("Geomean of rich.toml vs primary: -"));
+}
+
+#[test]
+fn progress_leaves_the_outputs_unchanged() {
+    let dir = five_circuit_dir(&[0, 1, 2, 3, 4]);
+    let outputs = |progress: bool| {
+        let mut cmd = rapid_map(&dir);
+        if progress {
+            cmd.arg("--progress");
+        }
+        let out = cmd.output().unwrap();
+        assert!(out.status.success());
+        let read = |name: &str| fs::read(dir.path().join(name)).unwrap();
+        let stderr = String::from_utf8_lossy(&out.stderr).into_owned();
+        ((read("results.csv"), read("ram_mapped.txt")), stderr)
+    };
+    let (quiet, quiet_stderr) = outputs(false);
+    let (reported, stderr) = outputs(true);
+    assert_eq!(quiet, reported);
+    assert!(!quiet_stderr.contains("Mapped "));
+    assert!(stderr.contains("Mapped 5/5 circuits (100%)"));
+}
+
+#[test]
+fn no_tdp_writes_no_true_dual_port_mapping() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("logic_block_count.txt"),
+        "Circuit\tLogic blocks\n0\t500\n1\t300\n",
+    )
+    .unwrap();
+    // circuit 1's two ROMs share one block, in TDP mode, when TDP is allowed
+    fs::write(
+        dir.path().join("logical_rams.txt"),
+        "Num_Circuits 2\nCircuit\tRamID\tMode\tDepth\tWidth\n\
+         0\t0\tSimpleDualPort\t45\t12\n\
+         0\t1\tTrueDualPort\t4096\t32\n\
+         1\t0\tROM\t512\t8\n\
+         1\t1\tROM\t512\t8\n",
+    )
+    .unwrap();
+    let modes = || {
+        let mapped = fs::read_to_string(dir.path().join("ram_mapped.txt")).unwrap();
+        mapped
+            .lines()
+            .map(|l| l.split(' ').nth(16).unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    rapid_map(&dir).assert().success();
+    assert!(modes().iter().any(|m| m == "TrueDualPort"));
+
+    // the TDP RAM's circuit is skipped and the ROMs stay unshared
+    rapid_map(&dir)
+        .arg("--no-tdp")
+        .assert()
+        .success()
+        .stderr(contains("Skipping circuit 0"));
+    assert_eq!(modes(), ["ROM", "ROM"]);
+
+    rapid_map(&dir)
+        .args(["--no-tdp", "--strict"])
+        .assert()
+        .code(3)
+        .stderr(contains(
+            "TrueDualPort but the architecture has no TDP mode (allow_tdp = false)",
+        ));
+}
+
+// bench_dir's circuits plus one ROM in circuit 7, which has no logic block count
+fn orphan_ram_dir() -> TempDir {
+    let dir = bench_dir();
+    fs::write(
+        dir.path().join("logical_rams.txt"),
+        "Num_Circuits 2\nCircuit\tRamID\tMode\t\tDepth\tWidth\n\
+         0\t0\tSimpleDualPort\t45\t12\n\
+         1\t0\tROM\t1024\t16\n\
+         7\t0\tROM\t512\t8\n",
+    )
+    .unwrap();
+    dir
+}
+
+fn mapped_circuits(dir: &TempDir) -> Vec<String> {
+    let mapped = fs::read_to_string(dir.path().join("ram_mapped.txt")).unwrap();
+    mapped
+        .lines()
+        .map(|l| l.split(' ').next().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn orphan_ram_warns_by_default() {
+    let dir = orphan_ram_dir();
+    rapid_map(&dir).assert().success().stderr(contains(
+        "Warning: logical_rams.txt: logical RAMs reference circuits with no logic block \
+         entry: 7 (created with 0 logic blocks)",
+    ));
+    assert_eq!(mapped_circuits(&dir), ["0", "1", "7"]);
+}
+
+#[test]
+fn orphan_ram_error_policy_fails() {
+    let dir = orphan_ram_dir();
+    rapid_map(&dir)
+        .args(["--on-orphan-ram", "error"])
+        .assert()
+        .code(2)
+        .stderr(contains(
+            "logical RAMs reference circuits with no logic block entry: 7",
+        ));
+    assert!(!dir.path().join("ram_mapped.txt").exists());
+}
+
+#[test]
+fn orphan_ram_create_policy_is_silent() {
+    let dir = orphan_ram_dir();
+    let out = rapid_map(&dir)
+        .args(["--on-orphan-ram", "create"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("no logic block entry"));
+    assert_eq!(mapped_circuits(&dir), ["0", "1", "7"]);
+    let results = fs::read_to_string(dir.path().join("results.csv")).unwrap();
+    assert!(results.lines().any(|l| l.starts_with("7,0,1,0,0,")));
+}
+
+#[test]
+fn area_budget_fails_below_the_geomean_and_passes_above() {
+    let dir = five_circuit_dir(&[0, 1, 2, 3, 4]);
+    let out = rapid_map(&dir).output().unwrap();
+    assert!(out.status.success());
+    let line = geomean_line(&out.stderr);
+    let geomean: f64 = line.rsplit(' ').next().unwrap().parse().unwrap();
+
+    let below = format!("{}", geomean * 0.9);
+    rapid_map(&dir)
+        .args(["--max-area-budget", &below])
+        .assert()
+        .code(6)
+        .stderr(contains("exceeds the budget"))
+        .stderr(contains("(+11.11%)"));
+    let above = format!("{}", geomean * 1.1);
+    rapid_map(&dir)
+        .args(["--max-area-budget", &above])
+        .assert()
+        .success();
+}